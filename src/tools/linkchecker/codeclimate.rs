@@ -0,0 +1,117 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reports in the Code Climate format, as shown by GitLab's code quality
+//! widget.
+//!
+//! GitLab tells the issues of a merge request apart from the ones already
+//! there by their fingerprint, so it only depends on the page, the kind of
+//! error and the target, and not on where on the page the link is.
+
+use std::path::Path;
+
+use linkchecker::{LinkError, Severity};
+
+use sarif;
+
+#[derive(Serialize)]
+pub struct Issue {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    check_name: &'static str,
+    description: String,
+    fingerprint: String,
+    severity: &'static str,
+    location: Location,
+}
+
+#[derive(Serialize)]
+struct Location {
+    path: String,
+    lines: Lines,
+}
+
+#[derive(Serialize)]
+struct Lines {
+    begin: usize,
+}
+
+/// A 64-bit FNV-1a hash, which unlike the hashers of the standard library is
+/// the same for every build.
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash = 0xcbf29ce484222325;
+    for part in parts {
+        // Hashing a separator as well keeps `("ab", "c")` and `("a", "bc")`
+        // apart.
+        for &byte in part.as_bytes().iter().chain(&[0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// Builds the issues for `errors`, whose paths are relative to `root`.
+/// `root` itself is given relative to the repository root, or is absolute if
+/// it's outside of it.
+pub fn report(errors: &[LinkError], root: &Path) -> Vec<Issue> {
+    errors.iter().map(|err| {
+        let file = root.join(&err.file);
+        let path = if file.is_absolute() {
+            file.display().to_string()
+        } else {
+            sarif::uri(&file)
+        };
+        Issue {
+            kind: "issue",
+            check_name: err.kind.name(),
+            description: err.message(),
+            fingerprint: format!("{:016x}", fnv1a(&[&path, err.kind.name(), &err.target])),
+            severity: match err.severity {
+                Severity::Error => "major",
+                Severity::Warning => "minor",
+            },
+            location: Location {
+                path,
+                // Code Climate counts lines from 1, and has no way to point at
+                // a whole file.
+                lines: Lines { begin: if err.line == 0 { 1 } else { err.line } },
+            },
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use linkchecker::{ErrorKind, LinkError};
+    use serde_json;
+
+    use super::report;
+
+    #[test]
+    fn issues_are_fingerprinted_by_target() {
+        let broken = |line, target| {
+            LinkError::new(ErrorKind::BrokenLink, Path::new("std/index.html"), line, 7, target)
+        };
+        let errors = [broken(3, "a.html"), broken(5, "a.html"), broken(3, "b.html")];
+        let json = serde_json::to_value(report(&errors, Path::new("build/doc"))).unwrap();
+        let issue = &json[0];
+        assert_eq!(issue["type"], "issue");
+        assert_eq!(issue["check_name"], "broken-link");
+        assert_eq!(issue["description"], "broken link - a.html");
+        assert_eq!(issue["severity"], "major");
+        assert_eq!(issue["location"]["path"], "build/doc/std/index.html");
+        assert_eq!(issue["location"]["lines"]["begin"], 3);
+        assert_eq!(json[0]["fingerprint"], json[1]["fingerprint"]);
+        assert!(json[0]["fingerprint"] != json[2]["fingerprint"]);
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reports with a line per file, counting its problems by kind, like
+//! `std/vec/struct.Vec.html: 3 broken links, 1 broken fragment`.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use linkchecker::{ErrorKind, LinkError};
+
+/// How `count` problems of `kind` are described, like `3 broken links`.
+fn count(count: usize, kind: ErrorKind) -> String {
+    if count == 1 {
+        format!("1 {}", kind.name().replace('-', " "))
+    } else {
+        format!("{} {}", count, kind.plural())
+    }
+}
+
+/// Formats a line for each file with problems, from the one with the most to
+/// the one with the least. The kinds of problems are ordered the same way.
+pub fn lines(errors: &[LinkError]) -> Vec<String> {
+    let mut files = BTreeMap::new();
+    for err in errors {
+        let kinds = files.entry(&err.file).or_insert_with(BTreeMap::new);
+        *kinds.entry(err.kind).or_insert(0) += 1;
+    }
+    let mut files = files.into_iter().map(|(file, kinds)| {
+        let mut kinds = kinds.into_iter().collect::<Vec<_>>();
+        kinds.sort_by_key(|&(_, n)| Reverse(n));
+        let total = kinds.iter().map(|&(_, n)| n).sum::<usize>();
+        (file, total, kinds)
+    }).collect::<Vec<(&PathBuf, usize, Vec<(ErrorKind, usize)>)>>();
+    files.sort_by_key(|&(_, total, _)| Reverse(total));
+    files.into_iter().map(|(file, _, kinds)| {
+        let counts = kinds.into_iter().map(|(kind, n)| count(n, kind)).collect::<Vec<_>>();
+        format!("{}: {}", file.display(), counts.join(", "))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use linkchecker::{ErrorKind, LinkError};
+
+    use super::lines;
+
+    #[test]
+    fn lines_per_file() {
+        let error = |file, kind| LinkError::new(kind, Path::new(file), 1, 1, "x.html");
+        let errors = [
+            error("a.html", ErrorKind::JavascriptDependency),
+            error("std/vec/struct.Vec.html", ErrorKind::BrokenFragment),
+            error("std/vec/struct.Vec.html", ErrorKind::BrokenLink),
+            error("a.html", ErrorKind::JavascriptDependency),
+            error("std/vec/struct.Vec.html", ErrorKind::BrokenLink),
+            error("std/vec/struct.Vec.html", ErrorKind::BrokenLink),
+            error("b.html", ErrorKind::MixedContent),
+            error("b.html", ErrorKind::MixedContent),
+        ];
+        assert_eq!(lines(&errors), [
+            "std/vec/struct.Vec.html: 3 broken links, 1 broken fragment",
+            "a.html: 2 javascript dependencies",
+            "b.html: 2 mixed content",
+        ]);
+    }
+}
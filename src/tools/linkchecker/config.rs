@@ -0,0 +1,127 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The options of `linkchecker.toml`, so that a misspelled one is rejected
+//! rather than silently doing nothing, and for `--config-schema`.
+
+use toml::value::Table;
+
+/// Every option with the type of its value, in the order of `TomlConfig`.
+pub const KEYS: &[(&str, &str)] = &[
+    ("docs", "path"),
+    ("root", "path"),
+    ("expand-env", "boolean"),
+    ("ignore", "array of strings"),
+    ("report-absolute-links", "boolean"),
+    ("report-mixed-content", "boolean"),
+    ("check-link-text", "boolean"),
+    ("no-follow-symlinks", "boolean"),
+    ("lint-structure", "boolean"),
+    ("check-canonical", "boolean"),
+    ("check-navigation", "boolean"),
+    ("static-root", "path"),
+    ("report-orphans", "boolean"),
+    ("treat-redirect-as-error", "boolean"),
+    ("strict-fragments", "boolean"),
+    ("check-hidden-fragments", "boolean"),
+    ("fix-suggestions", "boolean"),
+    ("suggest-fragment-pages", "boolean"),
+    ("cross-file-ids", "boolean"),
+    ("extra-attrs", "array of strings"),
+    ("exclude-fragments-for", "array of strings"),
+    ("warn-query-links", "boolean"),
+    ("id-cache", "path"),
+    ("fuzzy-fragments", "boolean"),
+    ("case-insensitive-fragments", "boolean"),
+    ("markdown", "boolean"),
+    ("verify-no-javascript-dependency", "boolean"),
+    ("jobs", "integer"),
+    ("max-depth", "integer"),
+    ("skip-scheme", "array of strings"),
+    ("ignore-extension", "array of strings"),
+    ("list-external", "boolean"),
+    ("allow", "array of strings"),
+    ("severity", "array of strings"),
+    ("json-summary", "boolean"),
+    ("summary-only-on-failure", "boolean"),
+    ("group-by-target-dir", "boolean"),
+    ("stats-timing", "boolean"),
+    ("output", "path"),
+    ("dump-index", "path"),
+    ("dump-links", "path"),
+    ("format", "string"),
+    ("repo-root", "path"),
+    ("relative-to", "path"),
+    ("assume-extension", "string"),
+    ("strict-io", "boolean"),
+    ("stream-threshold", "integer"),
+    ("low-memory", "integer"),
+    ("index-name", "string"),
+    ("warn-self-links", "boolean"),
+    ("rewrite", "array of strings"),
+    ("check-external", "boolean"),
+    ("external-retries", "integer"),
+    ("external-cache", "path"),
+    ("external-cache-ttl", "integer"),
+    ("external-max-per-host", "integer"),
+    ("external-rate", "integer"),
+];
+
+/// Returns the keys of `table` which aren't options, sorted.
+pub fn unknown_keys(table: &Table) -> Vec<&str> {
+    table.keys()
+         .map(|key| &key[..])
+         .filter(|key| !KEYS.iter().any(|&(known, _)| known == *key))
+         .collect()
+}
+
+/// The option `key` was likely meant to be, for a key written with
+/// underscores like the fields of `Options`, or with different case.
+pub fn suggestion(key: &str) -> Option<&'static str> {
+    let key = key.replace('_', "-");
+    KEYS.iter().map(|&(known, _)| known).find(|known| known.eq_ignore_ascii_case(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use toml;
+
+    use TomlConfig;
+    use super::{KEYS, suggestion, unknown_keys};
+
+    #[test]
+    fn keys_match_the_config() {
+        let config = KEYS.iter().map(|&(key, kind)| {
+            let value = match kind {
+                "boolean" => "true",
+                "integer" => "1",
+                "array of strings" => "[\"a\"]",
+                _ => "\"a\"",
+            };
+            format!("{} = {}\n", key, value)
+        }).collect::<String>();
+        toml::from_str::<TomlConfig>(&config).unwrap();
+
+        // The error for an unknown key lists the known ones.
+        let err = toml::from_str::<TomlConfig>("probe = 1").err().unwrap().to_string();
+        let expected = KEYS.iter().map(|&(key, _)| format!("`{}`", key)).collect::<Vec<_>>();
+        assert!(err.contains(&expected.join(", ")), "{}", err);
+    }
+
+    #[test]
+    fn unknown_keys_are_found() {
+        let table = toml::from_str("externl-check = true\njobs = 2\ncheck_external = true")
+                        .unwrap();
+        assert_eq!(unknown_keys(&table), ["check_external", "externl-check"]);
+        assert_eq!(suggestion("check_external"), Some("check-external"));
+        assert_eq!(suggestion("Jobs"), Some("jobs"));
+        assert_eq!(suggestion("externl-check"), None);
+    }
+}
@@ -0,0 +1,99 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Comparison of the errors found in two versions of the docs, like nightly
+//! and stable, to see which links broke or got fixed in between.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use linkchecker::{Checker, ErrorKind, LinkError, Severity};
+
+/// What identifies an error across versions. Lines and columns are left out,
+/// as they shift whenever anything earlier on the page changes.
+fn key(err: &LinkError) -> (&Path, ErrorKind, &str, Option<&str>) {
+    (&err.file, err.kind, &err.target, err.fragment.as_ref().map(|s| &s[..]))
+}
+
+/// The errors found in only one of two versions of the docs. Warnings are
+/// left out.
+pub struct Drift<'a> {
+    /// Only in the new version.
+    pub broken: Vec<&'a LinkError>,
+    /// Only in the old version.
+    pub fixed: Vec<&'a LinkError>,
+}
+
+fn only_in<'a>(errors: &'a [LinkError], other: &[LinkError]) -> Vec<&'a LinkError> {
+    let other = other.iter().map(key).collect::<HashSet<_>>();
+    errors.iter()
+          .filter(|err| err.severity == Severity::Error && !other.contains(&key(err)))
+          .collect()
+}
+
+/// Compares the errors found in the `old` and `new` versions of the docs.
+pub fn diff<'a>(old: &'a [LinkError], new: &'a [LinkError]) -> Drift<'a> {
+    Drift {
+        broken: only_in(new, old),
+        fixed: only_in(old, new),
+    }
+}
+
+impl<'a> Drift<'a> {
+    /// Lists the newly broken and the newly fixed links, followed by a count
+    /// of both. Their paths are printed the way the checkers of the `old` and
+    /// `new` versions print them, see `Checker::display_error`.
+    pub fn lines(&self, old: &Checker, new: &Checker) -> Vec<String> {
+        let broken = self.broken.iter().map(|err| {
+            format!("newly broken: {}", new.display_error(err))
+        });
+        let fixed = self.fixed.iter().map(|err| {
+            format!("newly fixed: {}", old.display_error(err))
+        });
+        let mut lines = broken.chain(fixed).collect::<Vec<_>>();
+        lines.push(format!("{} newly broken, {} newly fixed",
+                           self.broken.len(),
+                           self.fixed.len()));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use linkchecker::{Checker, ErrorKind, LinkError, Options, Severity};
+
+    use super::diff;
+
+    #[test]
+    fn drift() {
+        let broken = |file, line, target| {
+            LinkError::new(ErrorKind::BrokenLink, Path::new(file), line, 1, target)
+        };
+        let mut warning = broken("c.html", 1, "x.html");
+        warning.severity = Severity::Warning;
+        let old = [broken("a.html", 3, "x.html"), broken("b.html", 1, "y.html")];
+        let new = [broken("a.html", 5, "x.html"), broken("b.html", 1, "z.html"), warning];
+        let checker = |root: &str| {
+            let options = Options {
+                relative_to: Some(PathBuf::from("/repo")),
+                ..Options::default()
+            };
+            Checker::new(Path::new(root), options)
+        };
+        let lines = diff(&old, &new).lines(&checker("/repo/stable"), &checker("/repo/nightly"));
+        assert_eq!(lines, [
+            "newly broken: nightly/b.html:1:1: broken link - z.html",
+            "newly fixed: stable/b.html:1:1: broken link - y.html",
+            "1 newly broken, 1 newly fixed",
+        ]);
+    }
+}
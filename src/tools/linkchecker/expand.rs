@@ -0,0 +1,76 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Expansion of environment variables in paths, for `--expand-env`.
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Replaces `$VAR` and `${VAR}` in `s` by the value `lookup` gives for `VAR`,
+/// as a shell would. A `$` not followed by a name is left alone. Fails with
+/// the name of the first variable `lookup` doesn't know.
+pub fn vars<F>(s: &str, lookup: F) -> Result<String, String>
+    where F: Fn(&str) -> Option<String>
+{
+    let mut expanded = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        expanded.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let (name, len) = if after.starts_with('{') {
+            match after.find('}') {
+                Some(end) => (&after[1..end], end + 1),
+                None => ("", 0),
+            }
+        } else {
+            let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+            (&after[..end], end)
+        };
+        if name.is_empty() || !name.chars().all(is_name_char) {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        match lookup(name) {
+            Some(value) => expanded.push_str(&value),
+            None => return Err(name.to_string()),
+        }
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::vars;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "BUILD_DIR" => Some("/tmp/build".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expanded() {
+        assert_eq!(vars("$BUILD_DIR/doc", lookup), Ok("/tmp/build/doc".to_string()));
+        assert_eq!(vars("${BUILD_DIR}-x/doc", lookup), Ok("/tmp/build-x/doc".to_string()));
+        assert_eq!(vars("$MISSING/doc", lookup), Err("MISSING".to_string()));
+    }
+
+    #[test]
+    fn unexpanded() {
+        assert_eq!(vars("build/doc", lookup), Ok("build/doc".to_string()));
+        assert_eq!(vars("a$/b$", lookup), Ok("a$/b$".to_string()));
+        assert_eq!(vars("${oops/doc", lookup), Ok("${oops/doc".to_string()));
+    }
+}
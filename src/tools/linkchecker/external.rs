@@ -0,0 +1,472 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checking of links to external `http:` and `https:` URLs.
+//!
+//! Requests are made by running `curl` rather than building an HTTP client
+//! into the checker. Networks are flaky, so failures that may go away by
+//! themselves (timeouts, 5xx responses, rate limiting) are retried with an
+//! exponential backoff, and URLs which worked recently can be remembered in a
+//! cache file so that later runs don't need to fetch them again.
+//!
+//! URLs are checked by a few threads at once, but never more than
+//! `Limits::max_per_host` at a time on the same host and never faster than
+//! `Limits::per_second` overall, so that big trees linking to the same few
+//! sites don't get rate limited.
+
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json;
+
+/// The result of a single attempt to fetch a URL.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Ok,
+    /// A failure which may go away when trying again later.
+    Transient(String),
+    Failed(String),
+}
+
+/// Returns whether `url` is something `fetch` knows how to check.
+pub fn is_http(url: &str) -> bool {
+    url.starts_with("http:") || url.starts_with("https:")
+}
+
+/// Interprets the exit code of `curl` and the HTTP status it printed.
+pub fn classify(curl_exit_code: Option<i32>, http_status: u32) -> Outcome {
+    match curl_exit_code {
+        Some(0) => {}
+        // Couldn't resolve or connect to the host, timeouts, TLS handshake
+        // failures and connections closed half way through.
+        Some(code @ 5..=7) |
+        Some(code @ 28) |
+        Some(code @ 35) |
+        Some(code @ 52) |
+        Some(code @ 55..=56) => return Outcome::Transient(format!("curl error {}", code)),
+        Some(code) => return Outcome::Failed(format!("curl error {}", code)),
+        None => return Outcome::Transient("curl was killed".to_string()),
+    }
+    match http_status {
+        200..=399 => Outcome::Ok,
+        429 | 500..=599 => Outcome::Transient(format!("HTTP {}", http_status)),
+        _ => Outcome::Failed(format!("HTTP {}", http_status)),
+    }
+}
+
+/// Fetches `url` once, following redirects.
+///
+/// Only the headers are requested unless the server doesn't allow that.
+pub fn fetch(url: &str) -> Outcome {
+    let null = if cfg!(windows) { "NUL" } else { "/dev/null" };
+    let mut outcome = Outcome::Failed(String::new());
+    for &head in &[true, false] {
+        let mut cmd = Command::new("curl");
+        cmd.arg("--silent")
+           .arg("--location")
+           .arg("--max-time").arg("30")
+           .arg("--output").arg(null)
+           .arg("--write-out").arg("%{http_code}");
+        if head {
+            cmd.arg("--head");
+        }
+        let output = match cmd.arg(url).output() {
+            Ok(output) => output,
+            Err(e) => return Outcome::Failed(format!("failed to run curl: {}", e)),
+        };
+        let status = String::from_utf8_lossy(&output.stdout).trim().parse().unwrap_or(0);
+        outcome = classify(output.status.code(), status);
+        // Some servers don't implement `HEAD`, so try again with a `GET`.
+        if !head || (status != 405 && status != 501) {
+            break;
+        }
+    }
+    outcome
+}
+
+/// The longest wait before retrying a URL.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Checks `url` with `fetch`, retrying transient failures up to `retries`
+/// times. The delay before a retry starts at `delay` and doubles each time, up
+/// to `MAX_RETRY_DELAY`.
+pub fn check_with_retries<F>(url: &str, retries: u32, delay: Duration, mut fetch: F)
+                             -> Result<(), String>
+    where F: FnMut(&str) -> Outcome
+{
+    let mut attempt = 0;
+    loop {
+        match fetch(url) {
+            Outcome::Ok => return Ok(()),
+            Outcome::Failed(reason) => return Err(reason),
+            Outcome::Transient(reason) => {
+                if attempt >= retries {
+                    return Err(reason);
+                }
+                let delay = retry_delay(delay, attempt);
+                debug!("{} failed with {}, retrying in {:?}", url, reason, delay);
+                thread::sleep(delay);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// How long to wait before the retry following `attempt` earlier ones.
+fn retry_delay(delay: Duration, attempt: u32) -> Duration {
+    let factor = 2u32.checked_pow(attempt).unwrap_or(u32::MAX);
+    cmp::min(delay.saturating_mul(factor), MAX_RETRY_DELAY)
+}
+
+/// The most threads checking URLs at once, whatever the limits allow.
+const MAX_THREADS: usize = 16;
+
+/// How hard external hosts may be hit.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Requests in flight to the same host at once.
+    pub max_per_host: usize,
+    /// Requests started per second across all hosts, or 0 for no limit.
+    pub per_second: u32,
+}
+
+/// Returns the host part of `url`, lowercased, which is what `Limits` are
+/// applied to.
+pub fn host(url: &str) -> String {
+    let rest = url.split_once("://").map_or("", |(_, rest)| rest);
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    rest[..end].to_lowercase()
+}
+
+/// Hands out queued URLs to the threads checking them, respecting
+/// `Limits::max_per_host`.
+struct Queue {
+    state: Mutex<QueueState>,
+    cond: Condvar,
+    max_per_host: usize,
+}
+
+struct QueueState {
+    pending: HashMap<String, VecDeque<String>>,
+    active: HashMap<String, usize>,
+    results: Vec<(String, Result<(), String>)>,
+}
+
+impl Queue {
+    /// Takes the next URL whose host isn't busy, waiting for one to finish if
+    /// need be. Returns `None` once nothing is left.
+    fn next(&self) -> Option<(String, String)> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.pending.is_empty() {
+                return None;
+            }
+            let host = {
+                let active = &state.active;
+                let max = self.max_per_host;
+                state.pending.keys()
+                             .find(|host| active.get(*host).cloned().unwrap_or(0) < max)
+                             .cloned()
+            };
+            if let Some(host) = host {
+                let url = {
+                    let urls = state.pending.get_mut(&host).unwrap();
+                    urls.pop_front().unwrap()
+                };
+                if state.pending[&host].is_empty() {
+                    state.pending.remove(&host);
+                }
+                *state.active.entry(host.clone()).or_insert(0) += 1;
+                return Some((host, url));
+            }
+            state = self.cond.wait(state).unwrap();
+        }
+    }
+
+    fn done(&self, host: &str, url: String, res: Result<(), String>) {
+        let mut state = self.state.lock().unwrap();
+        *state.active.get_mut(host).unwrap() -= 1;
+        state.results.push((url, res));
+        self.cond.notify_all();
+    }
+}
+
+/// Spaces out requests so that at most `per_second` start each second.
+struct RateLimiter {
+    interval: Option<Duration>,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(per_second: u32) -> RateLimiter {
+        let interval = 1_000_000_000u32.checked_div(per_second).map(|nanos| {
+            Duration::new(0, nanos)
+        });
+        RateLimiter { interval, next: Mutex::new(Instant::now()) }
+    }
+
+    fn wait(&self) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        let now = Instant::now();
+        let start = {
+            let mut next = self.next.lock().unwrap();
+            let start = cmp::max(*next, now);
+            *next = start + interval;
+            start
+        };
+        if start > now {
+            thread::sleep(start - now);
+        }
+    }
+}
+
+/// Checks all of `urls` with `check_with_retries`, spreading them over a few
+/// threads within `limits`. Every attempt, including retries, counts against
+/// the limits. Returns the result for each URL, in no particular order.
+pub fn check_all<F>(urls: Vec<String>,
+                    limits: Limits,
+                    retries: u32,
+                    delay: Duration,
+                    fetch: F)
+                    -> Vec<(String, Result<(), String>)>
+    where F: Fn(&str) -> Outcome + Send + Sync + 'static
+{
+    let mut pending = HashMap::new();
+    for url in urls {
+        pending.entry(host(&url)).or_insert_with(VecDeque::new).push_back(url);
+    }
+    let max_per_host = cmp::max(limits.max_per_host, 1);
+    let threads = pending.values()
+                         .map(|urls| cmp::min(urls.len(), max_per_host))
+                         .sum::<usize>();
+    let queue = Arc::new(Queue {
+        state: Mutex::new(QueueState {
+            pending,
+            active: HashMap::new(),
+            results: Vec::new(),
+        }),
+        cond: Condvar::new(),
+        max_per_host,
+    });
+    let limiter = Arc::new(RateLimiter::new(limits.per_second));
+    let fetch = Arc::new(fetch);
+
+    let handles = (0..cmp::min(threads, MAX_THREADS)).map(|_| {
+        let queue = queue.clone();
+        let limiter = limiter.clone();
+        let fetch = fetch.clone();
+        thread::spawn(move || {
+            while let Some((host, url)) = queue.next() {
+                let res = check_with_retries(&url, retries, delay, |url| {
+                    limiter.wait();
+                    fetch(url)
+                });
+                queue.done(&host, url, res);
+            }
+        })
+    }).collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let mut state = queue.state.lock().unwrap();
+    mem::take(&mut state.results)
+}
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// URLs which were successfully checked recently, persisted between runs.
+pub struct Cache {
+    path: PathBuf,
+    ttl: u64,
+    /// When each URL was last found to work, in seconds since the epoch.
+    checked: BTreeMap<String, u64>,
+}
+
+impl Cache {
+    /// Loads the cache stored at `path`, whose entries are valid for `ttl`
+    /// seconds. A missing or unreadable file just means an empty cache.
+    pub fn load(path: &Path, ttl: u64) -> Cache {
+        let mut contents = String::new();
+        let checked = match File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+            Ok(_) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!("ignoring corrupt external link cache {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Cache {
+            path: path.to_path_buf(),
+            ttl,
+            checked,
+        }
+    }
+
+    /// Returns whether `url` worked less than `ttl` seconds before `now`.
+    pub fn is_fresh(&self, url: &str, now: u64) -> bool {
+        match self.checked.get(url) {
+            Some(&checked) => now.saturating_sub(checked) < self.ttl,
+            None => false,
+        }
+    }
+
+    pub fn insert(&mut self, url: &str, now: u64) {
+        self.checked.insert(url.to_string(), now);
+    }
+
+    /// Writes the cache back to disk, dropping entries which have expired.
+    pub fn save(&mut self, now: u64) -> io::Result<()> {
+        let ttl = self.ttl;
+        let checked = self.checked.iter()
+                                  .filter(|&(_, &checked)| now.saturating_sub(checked) < ttl)
+                                  .map(|(url, &checked)| (url.clone(), checked))
+                                  .collect::<BTreeMap<_, _>>();
+        let json = serde_json::to_string_pretty(&checked).unwrap();
+        File::create(&self.path)?.write_all(json.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{Cache, Limits, Outcome, check_all, check_with_retries, classify, host};
+    use super::{MAX_RETRY_DELAY, retry_delay};
+
+    #[test]
+    fn classify_statuses() {
+        assert_eq!(classify(Some(0), 200), Outcome::Ok);
+        assert_eq!(classify(Some(0), 301), Outcome::Ok);
+        assert_eq!(classify(Some(0), 404), Outcome::Failed("HTTP 404".to_string()));
+        assert_eq!(classify(Some(0), 429), Outcome::Transient("HTTP 429".to_string()));
+        assert_eq!(classify(Some(0), 503), Outcome::Transient("HTTP 503".to_string()));
+        assert_eq!(classify(Some(28), 0), Outcome::Transient("curl error 28".to_string()));
+        assert_eq!(classify(Some(3), 0), Outcome::Failed("curl error 3".to_string()));
+    }
+
+    #[test]
+    fn transient_failures_are_retried() {
+        let mut attempts = 0;
+        let res = check_with_retries("http://example.com", 3, Duration::from_secs(0), |_| {
+            attempts += 1;
+            if attempts < 3 { Outcome::Transient("HTTP 503".to_string()) } else { Outcome::Ok }
+        });
+        assert_eq!(res, Ok(()));
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let res = check_with_retries("http://example.com", 2, Duration::from_secs(0), |_| {
+            attempts += 1;
+            Outcome::Transient("HTTP 503".to_string())
+        });
+        assert_eq!(res, Err("HTTP 503".to_string()));
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let res = check_with_retries("http://example.com", 2, Duration::from_secs(0), |_| {
+            attempts += 1;
+            Outcome::Failed("HTTP 404".to_string())
+        });
+        assert_eq!(res, Err("HTTP 404".to_string()));
+        assert_eq!(attempts, 1);
+
+        // However many retries, none waits longer than the cap.
+        let mut attempts = 0;
+        let res = check_with_retries("http://example.com", 40, Duration::from_secs(0), |_| {
+            attempts += 1;
+            Outcome::Transient("HTTP 503".to_string())
+        });
+        assert_eq!(res, Err("HTTP 503".to_string()));
+        assert_eq!(attempts, 41);
+        assert_eq!(retry_delay(Duration::from_secs(1), 3), Duration::from_secs(8));
+        assert_eq!(retry_delay(Duration::from_secs(1), 6), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(Duration::from_secs(1), 39), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn hosts() {
+        assert_eq!(host("https://Docs.rs/foo"), "docs.rs");
+        assert_eq!(host("http://example.com:8080?x#y"), "example.com:8080");
+        assert_eq!(host("https://example.com"), "example.com");
+    }
+
+    #[test]
+    fn per_host_limit() {
+        let urls = (0..4).map(|i| format!("https://a.com/{}", i))
+                         .chain((0..4).map(|i| format!("https://b.com/{}", i)))
+                         .collect::<Vec<_>>();
+        // In flight and most ever in flight, per host.
+        let counts = Arc::new(Mutex::new(HashMap::new()));
+        let counts2 = counts.clone();
+        let limits = Limits { max_per_host: 2, per_second: 0 };
+        let results = check_all(urls, limits, 0, Duration::from_secs(0), move |url| {
+            let host = host(url);
+            {
+                let mut counts = counts2.lock().unwrap();
+                let count = counts.entry(host.clone()).or_insert((0, 0));
+                count.0 += 1;
+                count.1 = count.1.max(count.0);
+            }
+            thread::sleep(Duration::from_millis(20));
+            counts2.lock().unwrap().get_mut(&host).unwrap().0 -= 1;
+            Outcome::Ok
+        });
+        assert_eq!(results.len(), 8);
+        assert!(results.iter().all(|(_, res)| res.is_ok()));
+        let counts = counts.lock().unwrap();
+        assert!(counts["a.com"].1 <= 2);
+        assert!(counts["b.com"].1 <= 2);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let urls = (0..5).map(|i| format!("https://host{}.com/", i)).collect();
+        let limits = Limits { max_per_host: 1, per_second: 50 };
+        let start = Instant::now();
+        check_all(urls, limits, 0, Duration::from_secs(0), |_| Outcome::Ok);
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let path = env::temp_dir().join("linkchecker-test-external-cache.json");
+        let _ = fs::remove_file(&path);
+
+        let mut cache = Cache::load(&path, 100);
+        assert!(!cache.is_fresh("http://a.com", 1000));
+        cache.insert("http://a.com", 1000);
+        cache.insert("http://old.com", 800);
+        cache.save(1000).unwrap();
+
+        let cache = Cache::load(&path, 100);
+        assert!(cache.is_fresh("http://a.com", 1050));
+        assert!(!cache.is_fresh("http://a.com", 1100));
+        assert!(!cache.is_fresh("http://old.com", 1000));
+        fs::remove_file(&path).unwrap();
+    }
+}
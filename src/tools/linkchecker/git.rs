@@ -0,0 +1,96 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Asking git which pages changed, for `--git-base`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Returns the files below `dir` which git says changed since the commit
+/// `base`, including uncommitted changes and untracked files which aren't
+/// ignored, leaving out the ones which were deleted.
+pub fn changed_files(dir: &Path, base: &str) -> Result<Vec<PathBuf>, String> {
+    // `--relative` limits the diff to `dir`, giving paths relative to it, and
+    // `-z` keeps unusual names from being quoted.
+    let changed = git(dir, &["diff", "--name-only", "--relative", "-z", base, "--", "."])?;
+    let untracked = git(dir, &["ls-files", "--others", "--exclude-standard", "-z", "--", "."])?;
+    let mut files = paths(&changed, dir);
+    files.extend(paths(&untracked, dir));
+    files.retain(|path| path.exists());
+    Ok(files)
+}
+
+/// Runs git with `args` in `dir`, returning what it printed.
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git").args(args)
+                                    .current_dir(dir)
+                                    .output()
+                                    .map_err(|err| format!("failed to run git: {}", err))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parses the NUL-separated names printed by `git diff -z --name-only` or
+/// `git ls-files -z` into paths below `dir`.
+fn paths(names: &str, dir: &Path) -> Vec<PathBuf> {
+    names.split('\0').filter(|name| !name.is_empty()).map(|name| dir.join(name)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::prelude::*;
+    use std::path::{Path, PathBuf};
+
+    use super::{changed_files, git, paths};
+
+    #[test]
+    fn names() {
+        let dir = Path::new("/docs");
+        assert_eq!(paths("std/index.html\0a \"b\".html\0", dir),
+                   [PathBuf::from("/docs/std/index.html"), PathBuf::from("/docs/a \"b\".html")]);
+        assert!(paths("", dir).is_empty());
+    }
+
+    #[test]
+    fn changed_and_untracked_files() {
+        let repo = env::temp_dir().join("linkchecker-test-git");
+        let _ = fs::remove_dir_all(&repo);
+        let write = |path: &str, contents: &str| {
+            let path = repo.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            File::create(path).unwrap().write_all(contents.as_bytes()).unwrap();
+        };
+        for &(path, contents) in &[("docs/changed.html", "old"), ("docs/same.html", ""),
+                                   ("docs/deleted.html", ""), ("outside.html", "old"),
+                                   (".gitignore", "ignored.html\n")] {
+            write(path, contents);
+        }
+        git(&repo, &["init", "-q"]).unwrap();
+        git(&repo, &["add", "."]).unwrap();
+        git(&repo, &["-c", "user.name=test", "-c", "user.email=test@example.com",
+                     "commit", "-q", "-m", "docs"]).unwrap();
+        write("docs/changed.html", "new");
+        write("docs/new/untracked.html", "");
+        write("docs/ignored.html", "");
+        write("outside.html", "new");
+        fs::remove_file(repo.join("docs/deleted.html")).unwrap();
+
+        let docs = repo.join("docs");
+        let mut files = changed_files(&docs, "HEAD").unwrap();
+        files.sort();
+        assert_eq!(files, [docs.join("changed.html"), docs.join("new/untracked.html")]);
+        assert!(changed_files(&docs, "no-such-ref").is_err());
+        let _ = fs::remove_dir_all(&repo);
+    }
+}
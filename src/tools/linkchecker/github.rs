@@ -0,0 +1,73 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reports as GitHub Actions workflow commands, which show up as annotations
+//! on the lines of a pull request.
+
+use std::path::Path;
+
+use linkchecker::{LinkError, Severity};
+
+use sarif;
+
+/// Escapes the message of a workflow command.
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Escapes a property of a workflow command, like the file name.
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Formats `err`, whose path is relative to `root`, as an `::error` or
+/// `::warning` command. `root` itself is relative to the repository root, or
+/// absolute if it's outside of it.
+pub fn annotation(err: &LinkError, root: &Path) -> String {
+    let command = match err.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let file = root.join(&err.file);
+    let file = if file.is_absolute() {
+        file.display().to_string()
+    } else {
+        sarif::uri(&file)
+    };
+    let mut props = format!("file={}", escape_property(&file));
+    if err.line != 0 {
+        props.push_str(&format!(",line={}", err.line));
+        if err.column != 0 {
+            props.push_str(&format!(",col={}", err.column));
+        }
+    }
+    format!("::{} {}::{}", command, props, escape_data(&err.message()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use linkchecker::{ErrorKind, LinkError};
+
+    use super::annotation;
+
+    #[test]
+    fn annotations() {
+        let file = Path::new("std/a,b.html");
+        let mut err = LinkError::new(ErrorKind::BrokenLink, file, 4, 9, "std/100%.html");
+        assert_eq!(annotation(&err, Path::new("build/doc")),
+                   "::error file=build/doc/std/a%2Cb.html,line=4,col=9::\
+                    broken link - std/100%25.html");
+        err.line = 0;
+        assert_eq!(annotation(&err, Path::new("")),
+                   "::error file=std/a%2Cb.html::broken link - std/100%25.html");
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The ids of each page, persisted between runs so that pages which haven't
+//! changed don't need to be scanned for them again.
+//!
+//! A page is taken to be unchanged if its size and a hash of its contents are
+//! the same as when its ids were collected. Modification times aren't
+//! trusted, as copies and archives keep them and a quick rewrite may not
+//! change them. Only the pages seen by a run are written back, so entries for
+//! pages which are gone don't pile up.
+
+use std::collections::{BTreeMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+/// The size of a page and a hash of its contents, see `stamp`.
+pub type Stamp = (u64, u64);
+
+/// Stamps the contents of a page. The hash may change with the compiler the
+/// checker was built with, which only costs scanning every page once more.
+pub fn stamp(contents: &str) -> Stamp {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(contents.as_bytes());
+    (contents.len() as u64, hasher.finish())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    stamp: Stamp,
+    pub ids: Vec<String>,
+    /// The line, column and value of each duplicate id, which need to be
+    /// reported again.
+    pub duplicates: Vec<(usize, usize, String)>,
+}
+
+pub struct IdCache {
+    path: PathBuf,
+    /// By the page's path relative to the docs root.
+    entries: BTreeMap<String, Entry>,
+    seen: HashSet<String>,
+}
+
+impl IdCache {
+    /// Loads the cache stored at `path`. A missing or unreadable file just
+    /// means an empty cache.
+    pub fn load(path: &Path) -> IdCache {
+        let mut contents = String::new();
+        let entries = match File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+            Ok(_) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!("ignoring corrupt id cache {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        IdCache {
+            path: path.to_path_buf(),
+            entries,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Returns the ids of `page` if it hasn't changed since they were stored.
+    pub fn get(&mut self, page: &Path, stamp: Stamp) -> Option<&Entry> {
+        let key = page.to_string_lossy().into_owned();
+        match self.entries.get(&key) {
+            Some(entry) if entry.stamp == stamp => {}
+            _ => return None,
+        }
+        self.seen.insert(key.clone());
+        self.entries.get(&key)
+    }
+
+    pub fn insert(&mut self, page: &Path, stamp: Stamp, ids: Vec<String>,
+                  duplicates: Vec<(usize, usize, String)>) {
+        let key = page.to_string_lossy().into_owned();
+        self.seen.insert(key.clone());
+        self.entries.insert(key, Entry { stamp, ids, duplicates });
+    }
+
+    /// Writes the entries of the pages seen since loading back to disk.
+    pub fn save(&mut self) -> io::Result<()> {
+        let seen = &self.seen;
+        let entries = self.entries.iter()
+                                  .filter(|&(page, _)| seen.contains(page))
+                                  .collect::<BTreeMap<_, _>>();
+        let json = serde_json::to_string(&entries).unwrap();
+        File::create(&self.path)?.write_all(json.as_bytes())
+    }
+}
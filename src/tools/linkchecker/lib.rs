@@ -0,0 +1,6308 @@
+// Copyright 2016 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Library to check the validity of `href` links in our HTML documentation.
+//!
+//! In the past we've been quite error prone to writing in broken links as most
+//! of them are manually rather than automatically added. As files move over
+//! time or apis change old links become stale or broken. The purpose of this
+//! library is to check all relative links in our documentation to make sure
+//! they actually point to a valid place.
+//!
+//! Currently this doesn't actually do any HTML parsing or anything fancy like
+//! that, it just has a simple "regex" to search for `href` and `id` tags.
+//! These values are then translated to file URLs if possible and then the
+//! destination is asserted to exist.
+//!
+//! Fragments are looked up in the `id` and `<a name>` attributes of the
+//! linked page, except for a few special cases: a bare `#` is a placeholder
+//! which is never checked, fragments made of digits like `#12-20` are left to
+//! javascript, and `#top` is always fine as browsers scroll to the top of the
+//! page for it, unless `Options::strict_fragments` is set. Both fragments and
+//! ids are compared in Unicode NFC, so an accented letter matches whether it
+//! was written precomposed or as a base letter plus combining mark. The cases
+//! relying on javascript can be listed with
+//! `Options::verify_no_javascript_dependency`.
+//!
+//! A few whitelisted exceptions are allowed as there's known bugs in rustdoc,
+//! but this should catch the majority of "broken link" cases. Pages can also
+//! opt out themselves: the links of a page containing
+//! `<!-- linkcheck:ignore-file -->` aren't checked, and neither is the first
+//! link following a `<!-- linkcheck:ignore-next -->` comment.
+//!
+//! With `Options::markdown`, Markdown sources are checked as well, with the
+//! `markdown` module finding their links and the ids of their headings.
+//!
+//! Diagnostics are reported through the `log` crate: broken links are logged
+//! as errors and lints as warnings, so embedders can install their own logger.
+//!
+//! The `linkchecker` binary is a thin wrapper around `Checker`, which can also
+//! be used directly by other docs tooling, for example to query the anchors a
+//! generated page defines through `Checker::ids_for`. Links the checker
+//! can't make sense of by itself, like shorthands a preprocessor expands, can
+//! be decided on by a `LinkValidator` before the checker's own checks.
+
+extern crate flate2;
+extern crate glob;
+#[macro_use]
+extern crate log;
+extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate tar;
+extern crate unicode_normalization;
+
+use std::borrow::Cow;
+use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::mem;
+use std::path::{Path, PathBuf, Component};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use glob::{MatchOptions, Pattern};
+use serde::Serializer;
+use unicode_normalization::UnicodeNormalization;
+
+use Redirect::*;
+
+pub mod external;
+mod id_cache;
+mod lru;
+pub mod markdown;
+pub mod rewrite;
+mod single_flight;
+pub mod vfs;
+
+use id_cache::IdCache;
+use lru::{Lru, Weigh};
+use rewrite::Rewrite;
+use single_flight::SingleFlight;
+use vfs::{FileSystem, StdFs};
+
+/// Why a file couldn't be checked.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file couldn't be read. This is reported as an
+    /// `ErrorKind::UnreadableFile` too.
+    IOError(std::io::Error),
+    /// The file is a redirect to the given file, which couldn't be read.
+    /// Only happens when following redirects, like for the target of a link.
+    BrokenRedirect(PathBuf, std::io::Error),
+    /// Following redirects led back to the given redirect page.
+    RedirectLoop(PathBuf),
+    /// A redirect leads to the given file outside of the docs root, which
+    /// isn't read.
+    RedirectOutsideRoot(PathBuf),
+    /// The file is a redirect. Redirect pages have nothing to check of their
+    /// own, so this is no problem.
+    IsRedirect,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::IOError(ref err) => write!(f, "{}", err),
+            LoadError::BrokenRedirect(ref target, ref err) => {
+                write!(f, "broken redirect to {}: {}", target.display(), err)
+            }
+            LoadError::RedirectLoop(ref page) => {
+                write!(f, "redirect loop through {}", page.display())
+            }
+            LoadError::RedirectOutsideRoot(ref target) => {
+                write!(f, "redirect to {} outside of the docs root", target.display())
+            }
+            LoadError::IsRedirect => write!(f, "the page is a redirect"),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn description(&self) -> &str {
+        match *self {
+            LoadError::IOError(_) => "file couldn't be read",
+            LoadError::BrokenRedirect(..) => "broken redirect",
+            LoadError::RedirectLoop(_) => "redirect loop",
+            LoadError::RedirectOutsideRoot(_) => "redirect outside of the docs root",
+            LoadError::IsRedirect => "the page is a redirect",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LoadError::IOError(ref err) |
+            LoadError::BrokenRedirect(_, ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Why docs couldn't be checked at all, as opposed to the problems found in
+/// them, which are `LinkError`s.
+#[derive(Debug)]
+pub enum LinkCheckError {
+    /// The directory to walk, or the file to check, couldn't be read.
+    UnreadableDocs(PathBuf, io::Error),
+    /// The single file given to `Checker::run` couldn't be checked.
+    Load(PathBuf, LoadError),
+    /// A thread checking pages for `Checker::walk` panicked.
+    WorkerPanicked,
+}
+
+impl fmt::Display for LinkCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LinkCheckError::UnreadableDocs(ref docs, ref err) => {
+                write!(f, "failed to read '{}': {}", docs.display(), err)
+            }
+            LinkCheckError::Load(ref file, ref err) => {
+                write!(f, "failed to check '{}': {}", file.display(), err)
+            }
+            LinkCheckError::WorkerPanicked => write!(f, "a thread checking pages panicked"),
+        }
+    }
+}
+
+impl Error for LinkCheckError {
+    fn description(&self) -> &str {
+        match *self {
+            LinkCheckError::UnreadableDocs(..) => "docs couldn't be read",
+            LinkCheckError::Load(..) => "file couldn't be checked",
+            LinkCheckError::WorkerPanicked => "a thread checking pages panicked",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LinkCheckError::UnreadableDocs(_, ref err) => Some(err),
+            LinkCheckError::Load(_, ref err) => Some(err),
+            LinkCheckError::WorkerPanicked => None,
+        }
+    }
+}
+
+/// What `Checker::run` found.
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    pub errors: Vec<LinkError>,
+    pub stats: Stats,
+}
+
+impl Report {
+    /// Whether any of the problems found is an error rather than a warning.
+    pub fn has_errors(&self) -> bool {
+        self.errors.iter().any(|err| err.severity == Severity::Error)
+    }
+}
+
+enum Redirect {
+    SkipRedirect,
+    FollowRedirect,
+}
+
+struct FileEntry {
+    /// The file the page was read from. Another file may map to the same
+    /// entry, see `ErrorKind::PageCollision`.
+    file: PathBuf,
+    /// Shared with the callers of `load_file`, which can't borrow it from the
+    /// cache while they're checking its links.
+    source: Rc<String>,
+    /// The ids of the page, along with the other forms a link may give them
+    /// in, like percent-encoded. These aliases aren't ids themselves, so
+    /// duplicates are looked for among the ids alone. Shared with the other
+    /// threads of a parallel walk, see `Checker::load_target`.
+    ids: Arc<HashSet<String>>,
+    /// Whether `ids` has been filled in, which can leave it empty.
+    parsed: bool,
+    /// With `Options::lazy_ids`, how much of `source` has been scanned for
+    /// ids so far, when not all of it has been parsed.
+    scanned: usize,
+    /// What else links may give the ids as, indexed the first time it's
+    /// needed, see `FileEntry::has_loosely`.
+    loose_ids: Option<LooseIds>,
+}
+
+/// The ids of a page as links may give them with `Options::fuzzy_fragments`
+/// and `Options::case_insensitive_fragments`.
+#[derive(Default)]
+struct LooseIds {
+    /// With `fuzzy`, what the ids with a numeric suffix like `-1` are
+    /// disambiguated from.
+    disambiguated: HashSet<String>,
+    /// With `ignore_case`, the ids in lowercase, along with what they're
+    /// disambiguated from with `fuzzy`.
+    lowercase: HashSet<String>,
+}
+
+impl LooseIds {
+    fn new(ids: &HashSet<String>, fuzzy: bool, ignore_case: bool) -> LooseIds {
+        let mut loose = LooseIds::default();
+        for id in ids {
+            let base = if fuzzy { disambiguated_from(id) } else { None };
+            if let Some(base) = base {
+                loose.disambiguated.insert(base.to_string());
+            }
+            if ignore_case {
+                loose.lowercase.insert(id.to_lowercase());
+                if let Some(base) = base {
+                    loose.lowercase.insert(base.to_lowercase());
+                }
+            }
+        }
+        loose
+    }
+}
+
+/// How many bytes of a page `FileEntry::scan_ids_until` scans at once.
+const ID_SCAN_CHUNK: usize = 64 * 1024;
+
+/// How many bytes of a page are read at once, see `Options::stream_threshold`.
+const STREAM_CHUNK: usize = 1024 * 1024;
+
+/// The ids and `<a name>`s of a page found so far, as they're written, for
+/// `FileEntry::add_ids` to find duplicates.
+#[derive(Default)]
+struct SeenIds {
+    ids: HashSet<String>,
+    names: HashSet<String>,
+}
+
+type Cache = Lru<PathBuf, FileEntry>;
+
+/// A page one thread of a parallel walk loaded for all of them as a link
+/// target, see `Checker::load_target`: the page the link leads to after the
+/// redirects on the way, and its ids. `None` if it couldn't be loaded, which
+/// the other threads find out for themselves.
+type SharedPage = Option<(PathBuf, Vec<(PathBuf, Option<String>)>, Arc<HashSet<String>>)>;
+
+impl Weigh for FileEntry {
+    fn weight(&self) -> usize {
+        let ids = self.ids.iter().map(|id| id.len() + mem::size_of::<String>()).sum::<usize>();
+        self.source.len() + ids + mem::size_of::<FileEntry>()
+    }
+}
+
+/// What, if anything, a resolved link target is on disk.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Missing,
+    File,
+    Dir,
+}
+
+/// Settings controlling which files are checked and how.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Options {
+    /// Files to skip entirely, matched against the trailing components of
+    /// their path (e.g. `std/string/struct.String.html`).
+    pub ignore: Vec<String>,
+    /// Warn about root-absolute (`/`-leading) `href` and `src` values, which
+    /// break when the docs are deployed under a subpath.
+    pub report_absolute_links: bool,
+    /// Warn about scripts, stylesheets, images and other assets loaded over
+    /// plain `http:`, which browsers block on pages served over `https:`.
+    pub report_mixed_content: bool,
+    /// Warn about links without any text for screen readers to read out,
+    /// like an icon without an `alt` or `aria-label`.
+    pub check_link_text: bool,
+    /// Skip symbolic links while walking instead of following them.
+    pub no_follow_symlinks: bool,
+    /// Warn about structural problems like empty `id` attributes, which are
+    /// almost always mistakes in hand-written docs. Empty `href` and `src`
+    /// attributes are always warned about.
+    pub lint_structure: bool,
+    /// Kinds of warnings not to report.
+    pub allow: Vec<ErrorKind>,
+    /// Check `http:` and `https:` links, see `Checker::check_external`.
+    pub check_external: bool,
+    /// How often to retry an external link which failed in a way that may go
+    /// away by itself, like a timeout.
+    pub external_retries: u32,
+    /// File remembering which external links worked recently, so they don't
+    /// need to be fetched again.
+    pub external_cache: Option<PathBuf>,
+    /// How many seconds an entry in `external_cache` stays valid.
+    pub external_cache_ttl: u64,
+    /// How many requests may be made to the same host at once.
+    pub external_max_per_host: usize,
+    /// How many requests may be started per second overall, or 0 for no
+    /// limit.
+    pub external_rate: u32,
+    /// Check that `<link rel="canonical">` targets are well formed. Relative
+    /// ones are checked for existence like any other `href`.
+    pub check_canonical: bool,
+    /// Warn about `rel="next"` and `rel="prev"` links which don't form a
+    /// consistent reading order, like book-style docs have, see
+    /// `Checker::report_navigation`.
+    pub check_navigation: bool,
+    /// Warn about pages no other page links to, see `Checker::report_orphans`.
+    pub report_orphans: bool,
+    /// Where shared assets live when the docs were built with rustdoc's
+    /// `--static-root-path`. Links to files other than HTML pages which don't
+    /// resolve against the root are looked up here by file name instead, as
+    /// rustdoc puts all of them directly into that directory.
+    pub static_root: Option<PathBuf>,
+    /// Report links which only lead to a page through a redirect stub, for
+    /// hosts which don't support `<meta http-equiv="refresh">`.
+    pub treat_redirect_as_error: bool,
+    /// Require an element with the id `top` for `#top` fragments, instead of
+    /// relying on browsers scrolling to the top of the page without one.
+    pub strict_fragments: bool,
+    /// Warn about links within a page to an id inside of an element which is
+    /// `hidden` or styled `display: none`, which browsers can't scroll to.
+    /// Meant for docs rendered as a single page.
+    pub check_hidden_fragments: bool,
+    /// Suggest the closest file name in the same directory for broken links,
+    /// to help with typos.
+    pub fix_suggestions: bool,
+    /// When a link's fragment isn't on the page it points to, look for the
+    /// id on the other pages in the same directory and name them in the
+    /// error, for books whose chapters have been split into several pages.
+    pub suggest_fragment_pages: bool,
+    /// Warn about ids defined on more than one page, for docs whose pages
+    /// share a namespace of anchors, like chapters which are also rendered
+    /// all on one page.
+    pub cross_file_ids: bool,
+    /// Attributes besides `href` holding links, like the `data-*` attributes
+    /// which rustdoc's javascript turns into navigation.
+    pub extra_attrs: Vec<String>,
+    /// Pages whose fragments aren't checked, matched against their path
+    /// relative to the root, like `src/**/*.html` for the source view whose
+    /// line anchors are made by javascript. Links to them must still lead to
+    /// an existing page, and their ids aren't collected at all.
+    #[serde(serialize_with = "serialize_patterns")]
+    pub exclude_fragments_for: Vec<Pattern>,
+    /// Warn about relative links with a query. The page is still looked up
+    /// without it, but opened from disk it won't see the query either.
+    pub warn_query_links: bool,
+    /// File remembering the ids of each page, so that pages which haven't
+    /// changed since the last walk aren't scanned for them again. Not used
+    /// with `cross_file_ids`, which needs to see every id.
+    pub id_cache: Option<PathBuf>,
+    /// Accept a fragment if the page has it with a `-N` suffix, as rustdoc
+    /// adds to tell apart several anchors of the same name, so that `#method.foo`
+    /// finds `method.foo-1`.
+    pub fuzzy_fragments: bool,
+    /// Compare fragments and ids in lowercase, for deployments whose server
+    /// lowercases anchors, so that `#Method.Foo` finds `method.foo`.
+    pub case_insensitive_fragments: bool,
+    /// Also check Markdown sources (`.md` files), experimentally, to catch
+    /// broken links before the docs are rendered. Their inline links and
+    /// reference definitions are resolved like the `href`s of pages, and
+    /// fragments pointing into them are looked up among the ids renderers give
+    /// their headings; see the `markdown` module.
+    pub markdown: bool,
+    /// Report the links which are only assumed to work because javascript
+    /// takes care of them: `javascript:` URLs, bare `#` links, fragments
+    /// made of digits and links in `data-*` attributes. This shows how much
+    /// of the docs doesn't work with javascript disabled.
+    pub verify_no_javascript_dependency: bool,
+    /// How many threads `Checker::walk` checks pages on. With more than one,
+    /// problems are reported once the walk is done, sorted by page and line,
+    /// so that the output is the same on every run. `cross_file_ids` needs
+    /// pages to be checked one after the other, so it turns this off, and
+    /// `id_cache` isn't used by the threads.
+    pub jobs: usize,
+    /// How many levels of directories below the one being walked to descend
+    /// into, with 0 checking just the pages directly in it. Pages deeper down
+    /// are still looked up as link targets, but their own links aren't
+    /// checked.
+    pub max_depth: Option<usize>,
+    /// URL schemes to leave alone besides the usual ones like `https` and
+    /// `javascript`, for example `rust` for intra-doc URIs. Without this,
+    /// a link like `rust:std::vec` would be taken for a relative path.
+    #[serde(rename = "skip-scheme")]
+    pub skip_schemes: Vec<String>,
+    /// Extensions, without the dot, of files which may be missing, like `map`
+    /// for source maps which only some builds generate. Links to them are
+    /// only checked if the file exists.
+    #[serde(rename = "ignore-extension")]
+    pub ignore_extensions: Vec<String>,
+    /// Only scan a page a link points into as far as needed to find the
+    /// fragment, instead of collecting all of its ids and reporting its
+    /// duplicates. Pages are still scanned fully when they're checked
+    /// themselves. This speeds up checking a few pages which link into big
+    /// ones, like the source view, but slows down walks, which check every
+    /// page anyway.
+    pub lazy_ids: bool,
+    /// Collect every `http:`, `https:`, `ftp:` and `irc:` URL linked to into
+    /// `Stats::external_urls`, whether it's checked or not, to review which
+    /// sites the docs point at.
+    pub list_external: bool,
+    /// Directory to print the paths of pages relative to, instead of the docs
+    /// root, like the root of the repository. A page outside of it is given
+    /// with `..` rather than as an absolute path. Only what's printed changes,
+    /// the errors returned by `Checker::errors` are still relative to the
+    /// root. It should be absolute, or relative to the same directory as the
+    /// root.
+    pub relative_to: Option<PathBuf>,
+    /// Extension to try appending to relative links without one which don't
+    /// lead anywhere, like `html`, for docs served with content negotiation
+    /// whose links leave it out (`guide` for `guide.html`).
+    pub assume_extension: Option<String>,
+    /// Stop walking at the first directory which can't be read, reporting it
+    /// as an error, instead of warning about it and going on with the rest of
+    /// the docs.
+    pub strict_io: bool,
+    /// Read pages bigger than this many bytes a chunk at a time instead of
+    /// whole, so that huge pages like single-page docs don't need to fit in
+    /// memory, also when other pages link to them. Their ids are collected in
+    /// a first pass, and their links checked in a second one. Only the links
+    /// in `href`, `xlink:href` and `extra_attrs` of these pages are checked,
+    /// the lints for the rest of their markup aren't run, and `id_cache` isn't
+    /// used for them.
+    pub stream_threshold: Option<u64>,
+    /// File name of the page a browser shows for a directory, which links
+    /// ending in a slash point to, and which isn't reported as an orphan.
+    /// rustdoc writes `index.html`, other generators may use something like
+    /// `README.html`.
+    pub index_name: String,
+    /// Warn about links whose path leads back to the page they're on, like
+    /// `struct.Foo.html` on `struct.Foo.html`, which rustdoc has been known
+    /// to generate by mistake. Links made of just a fragment are fine.
+    pub warn_self_links: bool,
+    /// Rules rewriting the target of each link once it's resolved, as a path
+    /// relative to the docs root, applied one after the other, for docs
+    /// served with URLs which differ from the files on disk. See the
+    /// `rewrite` module.
+    #[serde(rename = "rewrite")]
+    pub rewrites: Vec<Rewrite>,
+    /// Keep every link which resolved to a file, along with its fragment if
+    /// that was found, for `Checker::resolved_links`. This is a lot of links
+    /// for big docs.
+    pub record_links: bool,
+    /// How serious problems on some pages are, as globs matched against the
+    /// path of the page relative to the root, each with the severity for the
+    /// problems found on the pages it matches. The first glob which matches
+    /// wins. This only changes problems which are errors by default, so that
+    /// `src/**` can be made to warn about broken links while `std/**` still
+    /// fails the check.
+    #[serde(rename = "severity", serialize_with = "serialize_severities")]
+    pub severities: Vec<(Pattern, Severity)>,
+    /// Keep at most about this many bytes of pages and their ids in memory,
+    /// dropping those used least recently beyond that and reading them again
+    /// when they're linked to once more. This bounds memory on huge trees at
+    /// the cost of reading and parsing pages several times. A parallel walk
+    /// splits the budget between its threads. `Checker::id_index` only knows
+    /// the pages which are still kept.
+    pub cache_budget: Option<u64>,
+}
+
+/// Serializes globs as the patterns they were made from.
+fn serialize_patterns<S: Serializer>(patterns: &[Pattern], serializer: S)
+                                     -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(patterns.iter().map(|pattern| pattern.as_str()))
+}
+
+/// Serializes severity rules the way they're written on the command line.
+fn serialize_severities<S: Serializer>(rules: &[(Pattern, Severity)], serializer: S)
+                                       -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(rules.iter().map(|&(ref pattern, severity)| {
+        format!("{}={}", pattern.as_str(), severity.name())
+    }))
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            ignore: Vec::new(),
+            report_absolute_links: false,
+            report_mixed_content: false,
+            check_link_text: false,
+            no_follow_symlinks: false,
+            lint_structure: false,
+            allow: Vec::new(),
+            check_external: false,
+            external_retries: 3,
+            external_cache: None,
+            external_cache_ttl: 24 * 60 * 60,
+            external_max_per_host: 2,
+            external_rate: 10,
+            check_canonical: false,
+            check_navigation: false,
+            report_orphans: false,
+            static_root: None,
+            treat_redirect_as_error: false,
+            strict_fragments: false,
+            check_hidden_fragments: false,
+            fix_suggestions: false,
+            suggest_fragment_pages: false,
+            cross_file_ids: false,
+            extra_attrs: Vec::new(),
+            exclude_fragments_for: Vec::new(),
+            warn_query_links: false,
+            id_cache: None,
+            fuzzy_fragments: false,
+            case_insensitive_fragments: false,
+            markdown: false,
+            verify_no_javascript_dependency: false,
+            jobs: 1,
+            max_depth: None,
+            skip_schemes: Vec::new(),
+            ignore_extensions: Vec::new(),
+            lazy_ids: false,
+            list_external: false,
+            relative_to: None,
+            assume_extension: None,
+            strict_io: false,
+            stream_threshold: None,
+            index_name: "index.html".to_string(),
+            warn_self_links: false,
+            rewrites: Vec::new(),
+            record_links: false,
+            severities: Vec::new(),
+            cache_budget: None,
+        }
+    }
+}
+
+/// Configures a `Checker` one setting at a time, starting from the same
+/// defaults as `Options::default()`. Created by `Checker::builder`.
+pub struct CheckerBuilder {
+    root: PathBuf,
+    options: Options,
+    fs: Option<Box<dyn FileSystem>>,
+    validators: Vec<Arc<dyn LinkValidator>>,
+}
+
+impl CheckerBuilder {
+    /// Replaces all of the settings made so far.
+    pub fn options(mut self, options: Options) -> CheckerBuilder {
+        self.options = options;
+        self
+    }
+
+    /// Reads the docs from `fs` instead of the real file system.
+    pub fn file_system(mut self, fs: Box<dyn FileSystem>) -> CheckerBuilder {
+        self.fs = Some(fs);
+        self
+    }
+
+    /// Sets `Options::ignore`.
+    pub fn ignore(mut self, ignore: Vec<String>) -> CheckerBuilder {
+        self.options.ignore = ignore;
+        self
+    }
+
+    /// Sets `Options::report_absolute_links`.
+    pub fn report_absolute_links(mut self, report_absolute_links: bool) -> CheckerBuilder {
+        self.options.report_absolute_links = report_absolute_links;
+        self
+    }
+
+    /// Sets `Options::report_mixed_content`.
+    pub fn report_mixed_content(mut self, report_mixed_content: bool) -> CheckerBuilder {
+        self.options.report_mixed_content = report_mixed_content;
+        self
+    }
+
+    /// Sets `Options::check_link_text`.
+    pub fn check_link_text(mut self, check_link_text: bool) -> CheckerBuilder {
+        self.options.check_link_text = check_link_text;
+        self
+    }
+
+    /// Sets `Options::no_follow_symlinks`.
+    pub fn no_follow_symlinks(mut self, no_follow_symlinks: bool) -> CheckerBuilder {
+        self.options.no_follow_symlinks = no_follow_symlinks;
+        self
+    }
+
+    /// Sets `Options::lint_structure`.
+    pub fn lint_structure(mut self, lint_structure: bool) -> CheckerBuilder {
+        self.options.lint_structure = lint_structure;
+        self
+    }
+
+    /// Sets `Options::allow`.
+    pub fn allow(mut self, allow: Vec<ErrorKind>) -> CheckerBuilder {
+        self.options.allow = allow;
+        self
+    }
+
+    /// Sets `Options::check_external`.
+    pub fn check_external(mut self, check_external: bool) -> CheckerBuilder {
+        self.options.check_external = check_external;
+        self
+    }
+
+    /// Sets `Options::external_retries`.
+    pub fn external_retries(mut self, external_retries: u32) -> CheckerBuilder {
+        self.options.external_retries = external_retries;
+        self
+    }
+
+    /// Sets `Options::external_cache`.
+    pub fn external_cache<P: Into<PathBuf>>(mut self, path: P) -> CheckerBuilder {
+        self.options.external_cache = Some(path.into());
+        self
+    }
+
+    /// Sets `Options::external_cache_ttl`.
+    pub fn external_cache_ttl(mut self, external_cache_ttl: u64) -> CheckerBuilder {
+        self.options.external_cache_ttl = external_cache_ttl;
+        self
+    }
+
+    /// Sets `Options::external_max_per_host`.
+    pub fn external_max_per_host(mut self, external_max_per_host: usize) -> CheckerBuilder {
+        self.options.external_max_per_host = external_max_per_host;
+        self
+    }
+
+    /// Sets `Options::external_rate`.
+    pub fn external_rate(mut self, external_rate: u32) -> CheckerBuilder {
+        self.options.external_rate = external_rate;
+        self
+    }
+
+    /// Sets `Options::check_canonical`.
+    pub fn check_canonical(mut self, check_canonical: bool) -> CheckerBuilder {
+        self.options.check_canonical = check_canonical;
+        self
+    }
+
+    /// Sets `Options::check_navigation`.
+    pub fn check_navigation(mut self, check_navigation: bool) -> CheckerBuilder {
+        self.options.check_navigation = check_navigation;
+        self
+    }
+
+    /// Sets `Options::report_orphans`.
+    pub fn report_orphans(mut self, report_orphans: bool) -> CheckerBuilder {
+        self.options.report_orphans = report_orphans;
+        self
+    }
+
+    /// Sets `Options::static_root`.
+    pub fn static_root<P: Into<PathBuf>>(mut self, path: P) -> CheckerBuilder {
+        self.options.static_root = Some(path.into());
+        self
+    }
+
+    /// Sets `Options::treat_redirect_as_error`.
+    pub fn treat_redirect_as_error(mut self, treat_redirect_as_error: bool) -> CheckerBuilder {
+        self.options.treat_redirect_as_error = treat_redirect_as_error;
+        self
+    }
+
+    /// Sets `Options::strict_fragments`.
+    pub fn strict_fragments(mut self, strict_fragments: bool) -> CheckerBuilder {
+        self.options.strict_fragments = strict_fragments;
+        self
+    }
+
+    /// Sets `Options::check_hidden_fragments`.
+    pub fn check_hidden_fragments(mut self, check: bool) -> CheckerBuilder {
+        self.options.check_hidden_fragments = check;
+        self
+    }
+
+    /// Sets `Options::fix_suggestions`.
+    pub fn fix_suggestions(mut self, fix_suggestions: bool) -> CheckerBuilder {
+        self.options.fix_suggestions = fix_suggestions;
+        self
+    }
+
+    /// Sets `Options::suggest_fragment_pages`.
+    pub fn suggest_fragment_pages(mut self, suggest: bool) -> CheckerBuilder {
+        self.options.suggest_fragment_pages = suggest;
+        self
+    }
+
+    /// Sets `Options::cross_file_ids`.
+    pub fn cross_file_ids(mut self, cross_file_ids: bool) -> CheckerBuilder {
+        self.options.cross_file_ids = cross_file_ids;
+        self
+    }
+
+    /// Sets `Options::extra_attrs`.
+    pub fn extra_attrs(mut self, extra_attrs: Vec<String>) -> CheckerBuilder {
+        self.options.extra_attrs = extra_attrs;
+        self
+    }
+
+    /// Sets `Options::exclude_fragments_for`.
+    pub fn exclude_fragments_for(mut self, exclude_fragments_for: Vec<Pattern>) -> CheckerBuilder {
+        self.options.exclude_fragments_for = exclude_fragments_for;
+        self
+    }
+
+    /// Sets `Options::warn_query_links`.
+    pub fn warn_query_links(mut self, warn_query_links: bool) -> CheckerBuilder {
+        self.options.warn_query_links = warn_query_links;
+        self
+    }
+
+    /// Sets `Options::id_cache`.
+    pub fn id_cache<P: Into<PathBuf>>(mut self, path: P) -> CheckerBuilder {
+        self.options.id_cache = Some(path.into());
+        self
+    }
+
+    /// Sets `Options::fuzzy_fragments`.
+    pub fn fuzzy_fragments(mut self, fuzzy_fragments: bool) -> CheckerBuilder {
+        self.options.fuzzy_fragments = fuzzy_fragments;
+        self
+    }
+
+    /// Sets `Options::case_insensitive_fragments`.
+    pub fn case_insensitive_fragments(mut self, case_insensitive: bool) -> CheckerBuilder {
+        self.options.case_insensitive_fragments = case_insensitive;
+        self
+    }
+
+    /// Sets `Options::markdown`.
+    pub fn markdown(mut self, markdown: bool) -> CheckerBuilder {
+        self.options.markdown = markdown;
+        self
+    }
+
+    /// Sets `Options::verify_no_javascript_dependency`.
+    pub fn verify_no_javascript_dependency(mut self,
+                                           verify_no_javascript_dependency: bool)
+                                           -> CheckerBuilder {
+        self.options.verify_no_javascript_dependency = verify_no_javascript_dependency;
+        self
+    }
+
+    /// Sets `Options::jobs`.
+    pub fn jobs(mut self, jobs: usize) -> CheckerBuilder {
+        self.options.jobs = jobs;
+        self
+    }
+
+    /// Sets `Options::max_depth`.
+    pub fn max_depth(mut self, max_depth: usize) -> CheckerBuilder {
+        self.options.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Sets `Options::skip_schemes`.
+    pub fn skip_schemes(mut self, skip_schemes: Vec<String>) -> CheckerBuilder {
+        self.options.skip_schemes = skip_schemes;
+        self
+    }
+
+    /// Sets `Options::ignore_extensions`.
+    pub fn ignore_extensions(mut self, extensions: Vec<String>) -> CheckerBuilder {
+        self.options.ignore_extensions = extensions;
+        self
+    }
+
+    /// Sets `Options::lazy_ids`.
+    pub fn lazy_ids(mut self, lazy_ids: bool) -> CheckerBuilder {
+        self.options.lazy_ids = lazy_ids;
+        self
+    }
+
+    /// Sets `Options::list_external`.
+    pub fn list_external(mut self, list_external: bool) -> CheckerBuilder {
+        self.options.list_external = list_external;
+        self
+    }
+
+    /// Sets `Options::relative_to`.
+    pub fn relative_to<P: Into<PathBuf>>(mut self, dir: P) -> CheckerBuilder {
+        self.options.relative_to = Some(dir.into());
+        self
+    }
+
+    /// Sets `Options::assume_extension`.
+    pub fn assume_extension(mut self, extension: &str) -> CheckerBuilder {
+        self.options.assume_extension = Some(extension.to_string());
+        self
+    }
+
+    /// Sets `Options::strict_io`.
+    pub fn strict_io(mut self, strict_io: bool) -> CheckerBuilder {
+        self.options.strict_io = strict_io;
+        self
+    }
+
+    /// Sets `Options::stream_threshold`.
+    pub fn stream_threshold(mut self, bytes: u64) -> CheckerBuilder {
+        self.options.stream_threshold = Some(bytes);
+        self
+    }
+
+    /// Sets `Options::index_name`.
+    pub fn index_name(mut self, name: &str) -> CheckerBuilder {
+        self.options.index_name = name.to_string();
+        self
+    }
+
+    /// Sets `Options::warn_self_links`.
+    pub fn warn_self_links(mut self, warn: bool) -> CheckerBuilder {
+        self.options.warn_self_links = warn;
+        self
+    }
+
+    /// Sets `Options::rewrites`.
+    pub fn rewrites(mut self, rewrites: Vec<Rewrite>) -> CheckerBuilder {
+        self.options.rewrites = rewrites;
+        self
+    }
+
+    /// Sets `Options::record_links`.
+    pub fn record_links(mut self, record: bool) -> CheckerBuilder {
+        self.options.record_links = record;
+        self
+    }
+
+    /// Sets `Options::severities`.
+    pub fn severities(mut self, severities: Vec<(Pattern, Severity)>) -> CheckerBuilder {
+        self.options.severities = severities;
+        self
+    }
+
+    /// Sets `Options::cache_budget`.
+    pub fn cache_budget(mut self, bytes: u64) -> CheckerBuilder {
+        self.options.cache_budget = Some(bytes);
+        self
+    }
+
+    /// Adds a validator to be asked about each link, after those added
+    /// before it.
+    pub fn validator<V: LinkValidator + 'static>(mut self, validator: V) -> CheckerBuilder {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+
+    pub fn build(self) -> Checker {
+        let mut checker = match self.fs {
+            Some(fs) => Checker::with_file_system(&self.root, self.options, fs),
+            None => Checker::new(&self.root, self.options),
+        };
+        checker.validators = self.validators;
+        checker
+    }
+}
+
+/// What a `LinkValidator` makes of a link.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Validation {
+    /// The link works, so the checker leaves it alone.
+    Valid,
+    /// The link is broken, for the given reason.
+    Invalid(String),
+    /// The validator doesn't know about this kind of link, so the next one
+    /// is asked.
+    Continue,
+}
+
+/// Checks links the checker can't make sense of by itself, like `rfc:1234`
+/// shorthands which a preprocessor expands.
+///
+/// Validators are registered with `CheckerBuilder::validator` and asked about
+/// every link the checker finds, in `href` as well as the other attributes it
+/// looks at, like `Options::extra_attrs` and the `src` of a `<source>`. They
+/// take turns until one doesn't return `Validation::Continue`, and a link
+/// one of them decides on isn't looked at any further. They only stand in
+/// front of the checker's own handling of links, which isn't done through
+/// this trait: when no validator knows a link, it's checked as usual, with
+/// external schemes skipped and anything else having to exist below the docs
+/// root. Lints about the page itself, like duplicate ids, aren't affected.
+pub trait LinkValidator: Send + Sync {
+    /// Validates `url`, the value of the attribute `attr` on `page`, whose
+    /// path is relative to the docs root.
+    fn validate(&self, page: &Path, attr: &str, url: &str) -> Validation;
+}
+
+/// Checks the links of all HTML files underneath a documentation root.
+///
+/// Files are loaded lazily and cached by their path relative to the root, so
+/// checking a whole tree reads each file at most once.
+pub struct Checker {
+    root: PathBuf,
+    fs: Arc<dyn FileSystem>,
+    options: Options,
+    /// Set for the checkers of a parallel walk, whose problems are logged
+    /// once they've all been collected.
+    quiet: bool,
+    cache: Cache,
+    stats: Stats,
+    timings: Timings,
+    errors: Vec<LinkError>,
+    /// Canonical paths of the files and directories walked so far.
+    visited: HashSet<PathBuf>,
+    /// Link targets which have already been looked up on disk. The same
+    /// targets (especially missing ones) tend to be linked from many pages.
+    file_kinds: HashMap<PathBuf, FileKind>,
+    /// With `Options::report_orphans`, the pages checked so far and the
+    /// files other pages link to, relative to the root.
+    pages: Vec<PathBuf>,
+    linked: HashSet<PathBuf>,
+    /// External links found so far, by URL, waiting to be checked.
+    external: BTreeMap<String, Vec<(PathBuf, usize, usize)>>,
+    /// With `Options::fix_suggestions`, the names of the files in the
+    /// directories broken links point into.
+    listings: HashMap<PathBuf, Vec<String>>,
+    /// With `Options::suggest_fragment_pages`, the pages of a directory each
+    /// id is defined on, for the directories missing fragments pointed into.
+    dir_ids: HashMap<PathBuf, HashMap<String, Vec<PathBuf>>>,
+    /// With `Options::cross_file_ids`, the page each id was first seen on.
+    id_origins: HashMap<String, PathBuf>,
+    /// Loaded from `Options::id_cache`, and saved after each walk.
+    id_cache: Option<IdCache>,
+    /// With `Options::relative_to`, the root relative to that directory.
+    printed_root: Option<PathBuf>,
+    /// See `LinkValidator`.
+    validators: Vec<Arc<dyn LinkValidator>>,
+    /// Files reported as `ErrorKind::PageCollision`, so that's only done once.
+    collisions: HashSet<PathBuf>,
+    /// While `walk` checks pages one after the other because of
+    /// `Options::cross_file_ids` despite `Options::jobs`, the problems found
+    /// so far, which are recorded once it's done, sorted like those of a
+    /// parallel walk.
+    held: Option<Vec<LinkError>>,
+    /// For the checkers of a parallel walk, the pages loaded by any of them,
+    /// so that a page linked into from pages checked on several threads is
+    /// only read and parsed once, see `load_target`. The sets of ids are the
+    /// ones in the caches of the threads, not copies of them.
+    shared_ids: Option<Arc<SingleFlight<PathBuf, SharedPage>>>,
+    /// With `Options::record_links`, the links which resolved so far.
+    links: BTreeSet<ResolvedLink>,
+    /// With `Options::check_navigation`, the `next` and `prev` links of each
+    /// page checked so far.
+    navigation: BTreeMap<PathBuf, Navigation>,
+    /// With `Options::cache_budget`, the pages whose duplicate ids have been
+    /// reported, so that they aren't again when the page is parsed anew.
+    duplicates_reported: HashSet<PathBuf>,
+}
+
+/// The `rel="next"` and `rel="prev"` links of a page, the first of each.
+#[derive(Clone, Debug, Default)]
+struct Navigation {
+    next: Option<NavigationLink>,
+    prev: Option<NavigationLink>,
+}
+
+#[derive(Clone, Debug)]
+struct NavigationLink {
+    /// The page linked to, relative to the root.
+    target: PathBuf,
+    href: String,
+    line: usize,
+    column: usize,
+}
+
+/// How serious a `LinkError` is. Only errors make a check fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// The name of the severity, as used by `--severity`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Severity, String> {
+        match s {
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => Err(format!("unknown severity `{}`, expected `error` or `warning`", s)),
+        }
+    }
+}
+
+/// The kinds of problems reported by a `Checker`. New kinds go at the end, and
+/// in `ErrorKind::ALL`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// The linked file doesn't exist.
+    BrokenLink,
+    /// The linked file exists but doesn't define the linked fragment.
+    BrokenFragment,
+    /// The link points at a directory rather than a page.
+    DirectoryLink,
+    /// The link leads to a redirect page whose target doesn't exist.
+    BrokenRedirect,
+    /// An id is defined more than once in the same file.
+    DuplicateId,
+    /// The link is relative to the server root; see
+    /// `Options::report_absolute_links`.
+    AbsoluteLink,
+    /// A symbolic link points back at one of its parent directories.
+    SymlinkCycle,
+    /// An attribute like `id` or `href` has an empty value. The target is the
+    /// name of the attribute.
+    EmptyAttribute,
+    /// The link only has a query (`?search=foo`), which does nothing without
+    /// a server to interpret it.
+    QueryOnlyLink,
+    /// A relative link has a query (`page.html?tab=x`), which is dropped when
+    /// the docs are read offline; see `Options::warn_query_links`.
+    QueryLink,
+    /// An external URL couldn't be fetched.
+    BrokenExternalLink,
+    /// A `<link rel="canonical">` is malformed; see `Options::check_canonical`.
+    BrokenCanonical,
+    /// No other page links to this one; see `Options::report_orphans`.
+    OrphanPage,
+    /// A page, or the target of a link, couldn't be read.
+    UnreadableFile,
+    /// A page isn't valid UTF-8. The invalid bytes are replaced with U+FFFD
+    /// and the page checked anyway.
+    InvalidUtf8,
+    /// The link leads to a redirect stub rather than a page; see
+    /// `Options::treat_redirect_as_error`. The target is `stub -> page`.
+    RedirectedLink,
+    /// The link only leads to a page through more than one redirect stub.
+    /// The target is the whole chain, like `a -> b -> page`.
+    RedirectChain,
+    /// An id is also defined on another page, which is the target; see
+    /// `Options::cross_file_ids`.
+    CrossFileDuplicateId,
+    /// The link only works with javascript, so it was assumed to be fine; see
+    /// `Options::verify_no_javascript_dependency`. The note says why.
+    JavascriptDependency,
+    /// The link resolves to an absolute path, like a root-absolute `/page.html`
+    /// or a Windows path with a drive, which can't be found in the docs
+    /// without knowing where they're served from.
+    UnresolvableLink,
+    /// An asset is loaded over plain `http:`; see
+    /// `Options::report_mixed_content`.
+    MixedContent,
+    /// A link has no text, label or image description; see
+    /// `Options::check_link_text`. The target is the link's `href`.
+    EmptyLinkText,
+    /// Another file maps to the same path relative to the docs root as a page
+    /// already read, like a page outside of the root given by a relative
+    /// path. The target is the other file, which isn't read; the page read
+    /// first is used in its place.
+    PageCollision,
+    /// A directory couldn't be listed while walking, so the pages in it
+    /// weren't checked. An error with `Options::strict_io`.
+    UnreadableDir,
+    /// A link has whitespace around it inside the quotes, like
+    /// `href="page.html "`, which browsers ignore. The target is the link as
+    /// written.
+    StrayWhitespace,
+    /// A link within a page points to an element which is hidden; see
+    /// `Options::check_hidden_fragments`.
+    HiddenFragment,
+    /// A link's path leads back to the page it's on; see
+    /// `Options::warn_self_links`. The target is the link as written.
+    SelfLink,
+    /// A `rel="next"` or `rel="prev"` link doesn't fit in with the others;
+    /// see `Checker::report_navigation`.
+    BrokenNavigation,
+}
+
+impl ErrorKind {
+    /// Every kind of error, in the order they're declared.
+    pub const ALL: &'static [ErrorKind] = &[
+        ErrorKind::BrokenLink,
+        ErrorKind::BrokenFragment,
+        ErrorKind::DirectoryLink,
+        ErrorKind::BrokenRedirect,
+        ErrorKind::DuplicateId,
+        ErrorKind::AbsoluteLink,
+        ErrorKind::SymlinkCycle,
+        ErrorKind::EmptyAttribute,
+        ErrorKind::QueryOnlyLink,
+        ErrorKind::QueryLink,
+        ErrorKind::BrokenExternalLink,
+        ErrorKind::BrokenCanonical,
+        ErrorKind::OrphanPage,
+        ErrorKind::UnreadableFile,
+        ErrorKind::InvalidUtf8,
+        ErrorKind::RedirectedLink,
+        ErrorKind::RedirectChain,
+        ErrorKind::CrossFileDuplicateId,
+        ErrorKind::JavascriptDependency,
+        ErrorKind::UnresolvableLink,
+        ErrorKind::MixedContent,
+        ErrorKind::EmptyLinkText,
+        ErrorKind::PageCollision,
+        ErrorKind::UnreadableDir,
+        ErrorKind::StrayWhitespace,
+        ErrorKind::HiddenFragment,
+        ErrorKind::SelfLink,
+        ErrorKind::BrokenNavigation,
+    ];
+
+    /// How serious problems of this kind are.
+    pub fn severity(&self) -> Severity {
+        match *self {
+            ErrorKind::AbsoluteLink |
+            ErrorKind::SymlinkCycle |
+            ErrorKind::EmptyAttribute |
+            ErrorKind::QueryOnlyLink |
+            ErrorKind::OrphanPage |
+            ErrorKind::InvalidUtf8 |
+            ErrorKind::RedirectChain |
+            ErrorKind::CrossFileDuplicateId |
+            ErrorKind::QueryLink |
+            ErrorKind::JavascriptDependency |
+            ErrorKind::MixedContent |
+            ErrorKind::EmptyLinkText |
+            ErrorKind::UnreadableDir |
+            ErrorKind::StrayWhitespace |
+            ErrorKind::HiddenFragment |
+            ErrorKind::SelfLink |
+            ErrorKind::BrokenNavigation => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    /// The name of this kind on the command line and in reports.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ErrorKind::BrokenLink => "broken-link",
+            ErrorKind::BrokenFragment => "broken-fragment",
+            ErrorKind::DirectoryLink => "directory-link",
+            ErrorKind::BrokenRedirect => "broken-redirect",
+            ErrorKind::DuplicateId => "duplicate-id",
+            ErrorKind::AbsoluteLink => "absolute-link",
+            ErrorKind::SymlinkCycle => "symlink-cycle",
+            ErrorKind::EmptyAttribute => "empty-attribute",
+            ErrorKind::QueryOnlyLink => "query-only-link",
+            ErrorKind::BrokenExternalLink => "broken-external-link",
+            ErrorKind::BrokenCanonical => "broken-canonical",
+            ErrorKind::OrphanPage => "orphan-page",
+            ErrorKind::UnreadableFile => "unreadable-file",
+            ErrorKind::InvalidUtf8 => "invalid-utf8",
+            ErrorKind::RedirectedLink => "redirected-link",
+            ErrorKind::RedirectChain => "redirect-chain",
+            ErrorKind::CrossFileDuplicateId => "cross-file-duplicate-id",
+            ErrorKind::QueryLink => "query-link",
+            ErrorKind::JavascriptDependency => "javascript-dependency",
+            ErrorKind::UnresolvableLink => "unresolvable-link",
+            ErrorKind::MixedContent => "mixed-content",
+            ErrorKind::EmptyLinkText => "empty-link-text",
+            ErrorKind::PageCollision => "page-collision",
+            ErrorKind::UnreadableDir => "unreadable-dir",
+            ErrorKind::StrayWhitespace => "stray-whitespace",
+            ErrorKind::HiddenFragment => "hidden-fragment",
+            ErrorKind::SelfLink => "self-link",
+            ErrorKind::BrokenNavigation => "broken-navigation",
+        }
+    }
+
+    /// How several problems of this kind are referred to in prose, like
+    /// `broken links`.
+    pub fn plural(&self) -> &'static str {
+        match *self {
+            ErrorKind::BrokenLink => "broken links",
+            ErrorKind::BrokenFragment => "broken fragments",
+            ErrorKind::DirectoryLink => "directory links",
+            ErrorKind::BrokenRedirect => "broken redirects",
+            ErrorKind::DuplicateId => "duplicate ids",
+            ErrorKind::AbsoluteLink => "absolute links",
+            ErrorKind::SymlinkCycle => "symlink cycles",
+            ErrorKind::EmptyAttribute => "empty attributes",
+            ErrorKind::QueryOnlyLink => "query only links",
+            ErrorKind::BrokenExternalLink => "broken external links",
+            ErrorKind::BrokenCanonical => "broken canonicals",
+            ErrorKind::OrphanPage => "orphan pages",
+            ErrorKind::UnreadableFile => "unreadable files",
+            ErrorKind::InvalidUtf8 => "invalid utf8",
+            ErrorKind::RedirectedLink => "redirected links",
+            ErrorKind::RedirectChain => "redirect chains",
+            ErrorKind::CrossFileDuplicateId => "cross file duplicate ids",
+            ErrorKind::QueryLink => "query links",
+            ErrorKind::JavascriptDependency => "javascript dependencies",
+            ErrorKind::UnresolvableLink => "unresolvable links",
+            ErrorKind::MixedContent => "mixed content",
+            ErrorKind::EmptyLinkText => "empty link texts",
+            ErrorKind::PageCollision => "page collisions",
+            ErrorKind::UnreadableDir => "unreadable dirs",
+            ErrorKind::StrayWhitespace => "stray whitespace",
+            ErrorKind::HiddenFragment => "hidden fragments",
+            ErrorKind::SelfLink => "self links",
+            ErrorKind::BrokenNavigation => "broken navigation",
+        }
+    }
+}
+
+impl FromStr for ErrorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ErrorKind, String> {
+        ErrorKind::ALL.iter()
+                      .find(|kind| kind.name() == s)
+                      .cloned()
+                      .ok_or_else(|| format!("unknown error kind `{}`", s))
+    }
+}
+
+/// A problem found while checking a file.
+#[derive(Clone, Debug, Serialize)]
+pub struct LinkError {
+    pub kind: ErrorKind,
+    pub severity: Severity,
+    /// The file containing the link, relative to the docs root.
+    pub file: PathBuf,
+    /// The 1-based line of the link, or 0 if the problem is with the file as
+    /// a whole.
+    pub line: usize,
+    /// The 1-based byte column where the link's value starts, or 0 if
+    /// unknown. Tells apart several links on the same line.
+    pub column: usize,
+    /// What the link points at, relative to the docs root where possible.
+    pub target: String,
+    pub fragment: Option<String>,
+    /// More details about the problem, like why a URL couldn't be fetched.
+    pub note: Option<String>,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: ", self.file.display())?;
+        } else if self.column == 0 {
+            write!(f, "{}:{}: ", self.file.display(), self.line)?;
+        } else {
+            write!(f, "{}:{}:{}: ", self.file.display(), self.line, self.column)?;
+        }
+        if self.severity == Severity::Warning {
+            write!(f, "warning: ")?;
+        }
+        write!(f, "{}", self.message())
+    }
+}
+
+impl LinkError {
+    /// A problem of `kind` with the severity it usually has, and no fragment
+    /// or note.
+    pub fn new<T: ToString>(kind: ErrorKind, file: &Path, line: usize, column: usize, target: T)
+                        -> LinkError {
+        LinkError {
+            kind,
+            severity: kind.severity(),
+            file: file.to_path_buf(),
+            line,
+            column,
+            target: target.to_string(),
+            fragment: None,
+            note: None,
+        }
+    }
+
+    /// Describes the problem, without the location or severity.
+    pub fn message(&self) -> String {
+        let fragment = self.fragment.as_ref().map(|s| &s[..]).unwrap_or("");
+        let mut msg = match self.kind {
+            ErrorKind::BrokenLink => format!("broken link - {}", self.target),
+            ErrorKind::BrokenFragment => {
+                format!("broken link fragment `#{}` pointing to `{}`", fragment, self.target)
+            }
+            ErrorKind::DirectoryLink => format!("directory link - {}", self.target),
+            ErrorKind::BrokenRedirect => format!("broken redirect to {}", self.target),
+            ErrorKind::DuplicateId => format!("id is not unique: `{}`", fragment),
+            ErrorKind::AbsoluteLink => format!("root-absolute link - {}", self.target),
+            ErrorKind::SymlinkCycle => format!("symlink cycle - {}", self.target),
+            ErrorKind::EmptyAttribute => format!("empty `{}` attribute", self.target),
+            ErrorKind::QueryOnlyLink => format!("query-only link - {}", self.target),
+            ErrorKind::BrokenExternalLink => format!("broken external link - {}", self.target),
+            ErrorKind::BrokenCanonical => format!("broken canonical link - {}", self.target),
+            ErrorKind::OrphanPage => "no other page links here".to_string(),
+            ErrorKind::UnreadableFile => format!("unreadable file - {}", self.target),
+            ErrorKind::InvalidUtf8 => "not valid UTF-8, invalid bytes were replaced".to_string(),
+            ErrorKind::RedirectedLink => format!("link resolves via redirect: {}", self.target),
+            ErrorKind::RedirectChain => {
+                format!("link resolves via a chain of redirects: {}", self.target)
+            }
+            ErrorKind::CrossFileDuplicateId => {
+                format!("id `{}` is also defined in {}", fragment, self.target)
+            }
+            ErrorKind::QueryLink => format!("query is ignored offline - {}", self.target),
+            ErrorKind::JavascriptDependency => {
+                format!("link depends on javascript - {}", self.target)
+            }
+            ErrorKind::UnresolvableLink => format!("can't resolve link offline - {}", self.target),
+            ErrorKind::MixedContent => {
+                format!("insecure asset is blocked on https pages - {}", self.target)
+            }
+            ErrorKind::EmptyLinkText => {
+                format!("link has no text for screen readers - {}", self.target)
+            }
+            ErrorKind::PageCollision => {
+                format!("another file maps to the same page - {}", self.target)
+            }
+            ErrorKind::UnreadableDir => format!("cannot read directory - {}", self.target),
+            ErrorKind::StrayWhitespace => {
+                format!("stray whitespace around link - `{}`", self.target)
+            }
+            ErrorKind::HiddenFragment => {
+                format!("link fragment `#{}` points into a hidden element", fragment)
+            }
+            ErrorKind::SelfLink => format!("link to the page it's on - {}", self.target),
+            ErrorKind::BrokenNavigation => {
+                format!("broken navigation link - {}", self.target)
+            }
+        };
+        if let Some(ref note) = self.note {
+            msg.push_str(&format!(" ({})", note));
+        }
+        msg
+    }
+
+    fn with_fragment(mut self, fragment: &str) -> LinkError {
+        self.fragment = Some(fragment.to_owned());
+        self
+    }
+
+    fn with_note<T: ToString>(mut self, note: T) -> LinkError {
+        self.note = Some(note.to_string());
+        self
+    }
+}
+
+/// A link which resolved to a file, see `Options::record_links`. Paths are
+/// relative to the docs root.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct ResolvedLink {
+    /// The page the link is on.
+    pub source: PathBuf,
+    /// The file the link leads to, after following redirects.
+    pub target: PathBuf,
+    pub fragment: Option<String>,
+}
+
+/// Counters describing the work done by a `Checker`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Stats {
+    /// HTML files whose links were checked.
+    pub files_checked: usize,
+    /// Relative links resolved against the docs tree.
+    pub links_checked: usize,
+    /// Links to external URLs, which aren't checked.
+    pub external_skipped: usize,
+    /// Links to external URLs checked with `Checker::check_external`.
+    pub external_checked: usize,
+    pub errors: usize,
+    pub warnings: usize,
+    /// The links checked by `Checker::check_external`, by host.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub external_hosts: BTreeMap<String, HostStats>,
+    /// With `Options::list_external`, every external URL linked to, without
+    /// its fragment.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub external_urls: BTreeSet<String>,
+    /// With `Options::cache_budget`, how many times a page was dropped from
+    /// memory to stay within it.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub pages_evicted: usize,
+}
+
+impl Stats {
+    /// Adds the counts of `other`, like those of another thread's walk.
+    pub fn merge(&mut self, other: Stats) {
+        self.files_checked += other.files_checked;
+        self.links_checked += other.links_checked;
+        self.external_skipped += other.external_skipped;
+        self.external_checked += other.external_checked;
+        self.errors += other.errors;
+        self.warnings += other.warnings;
+        for (host, stats) in other.external_hosts {
+            let merged = self.external_hosts.entry(host).or_default();
+            merged.links += stats.links;
+            merged.failed += stats.failed;
+        }
+        self.external_urls.extend(other.external_urls);
+        self.pages_evicted += other.pages_evicted;
+    }
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// How many external links point at a host, and how many of them are broken.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct HostStats {
+    pub links: usize,
+    pub failed: usize,
+}
+
+/// Where a `Checker` spent its time. Each part excludes the others, so
+/// `scan` is the time spent finding and resolving links minus the time spent
+/// reading files and parsing ids along the way.
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+    /// Time spent in `Checker::walk`, which includes all of the below.
+    pub walk: Duration,
+    /// Reading files and looking up link targets on disk.
+    pub io: Duration,
+    /// Finding and resolving links.
+    pub scan: Duration,
+    /// Collecting the ids of pages.
+    pub ids: Duration,
+}
+
+fn secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "walk {:.3}s (io {:.3}s, scanning {:.3}s, ids {:.3}s)",
+               secs(self.walk),
+               secs(self.io),
+               secs(self.scan),
+               secs(self.ids))
+    }
+}
+
+/// Normalizes ids and fragments to NFC, so that they match whichever form of
+/// an accented character they were written in.
+fn nfc<'a>(s: &'a str) -> Cow<'a, str> {
+    // ASCII is in NFC already, and that's what almost all ids are.
+    if s.is_ascii() {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(s.nfc().collect())
+    }
+}
+
+/// Whether `url` starts with `scheme:`, ignoring case like browsers do.
+fn has_scheme(url: &str, scheme: &str) -> bool {
+    url.len() > scheme.len() && url.as_bytes()[scheme.len()] == b':' &&
+        url[..scheme.len()].eq_ignore_ascii_case(scheme)
+}
+
+/// What `id` is disambiguated from by a numeric suffix like `-1`, if it has
+/// one.
+fn disambiguated_from(id: &str) -> Option<&str> {
+    match id.rfind('-') {
+        Some(i) if i + 1 < id.len() && id[i + 1..].bytes().all(|b| b.is_ascii_digit()) => {
+            Some(&id[..i])
+        }
+        _ => None,
+    }
+}
+
+fn small_url_encode(s: &str) -> String {
+    s.replace("<", "%3C")
+     .replace(">", "%3E")
+     .replace(" ", "%20")
+     .replace("?", "%3F")
+     .replace("'", "%27")
+     .replace("&", "%26")
+     .replace(",", "%2C")
+     .replace(":", "%3A")
+     .replace(";", "%3B")
+     .replace("[", "%5B")
+     .replace("]", "%5D")
+     .replace("\"", "%22")
+     .replace("#", "%23")
+}
+
+impl FileEntry {
+    /// Whether a link may give one of the ids as `fragment` with
+    /// `Options::fuzzy_fragments` or `Options::case_insensitive_fragments`.
+    /// Only asked once all the ids are known, which are indexed for it the
+    /// first time.
+    fn has_loosely(&mut self, fragment: &str, fuzzy: bool, ignore_case: bool) -> bool {
+        let ids = &self.ids;
+        let loose = self.loose_ids.get_or_insert_with(|| LooseIds::new(ids, fuzzy, ignore_case));
+        fuzzy && loose.disambiguated.contains(fragment) ||
+            ignore_case && loose.lowercase.contains(&fragment.to_lowercase())
+    }
+
+    /// Collects the ids of the page `file` if that hasn't happened yet,
+    /// returning the duplicates. With `origins`, ids are also checked against
+    /// the first page each was seen on.
+    fn parse_ids(&mut self,
+                 file: &Path,
+                 contents: &str,
+                 origins: Option<&mut HashMap<String, PathBuf>>)
+                 -> Vec<LinkError> {
+        let mut errors = Vec::new();
+        if !self.parsed {
+            self.parsed = true;
+            let mut seen = SeenIds::default();
+            self.add_ids(file, contents, (0, 0), &mut seen, origins, &mut errors);
+            if markdown::is_markdown(file) {
+                let ids = Arc::make_mut(&mut self.ids);
+                markdown::with_heading_ids(contents, |id| add_id(ids, nfc(&id).into_owned()));
+            }
+        }
+        errors
+    }
+
+    /// Adds the ids of `chunk`, the part of the page `file` from `start` on as
+    /// given by `with_chunks_of_file`, looking for duplicates among the ids
+    /// `seen` before.
+    fn add_ids(&mut self,
+               file: &Path,
+               chunk: &str,
+               start: (usize, usize),
+               seen: &mut SeenIds,
+               mut origins: Option<&mut HashMap<String, PathBuf>>,
+               errors: &mut Vec<LinkError>) {
+        with_attrs_in_source(chunk, "id", |fragment, i, col, _| {
+            let (line, col) = chunk_position(start, i, col);
+            let frag = nfc(fragment).into_owned();
+            if !seen.ids.insert(frag.clone()) {
+                errors.push(LinkError::new(ErrorKind::DuplicateId,
+                                           file,
+                                           line,
+                                           col,
+                                           file.display())
+                                .with_fragment(fragment));
+            } else {
+                if let Some(ref mut origins) = origins {
+                    if let Some(first) = origins.get(&frag) {
+                        errors.push(LinkError::new(ErrorKind::CrossFileDuplicateId,
+                                                   file,
+                                                   line,
+                                                   col,
+                                                   first.display())
+                                        .with_fragment(fragment));
+                    } else {
+                        origins.insert(frag.clone(), file.to_path_buf());
+                    }
+                }
+            }
+            add_id(Arc::make_mut(&mut self.ids), frag);
+        });
+        // Older pages use `<a name="...">` as fragment targets. An anchor
+        // often has an `id` of the same name too, so names are only checked
+        // for duplicates among themselves.
+        with_tags_in_source(chunk, "<a", |attrs, i, col| {
+            let (line, col) = chunk_position(start, i, col);
+            let name = match attr_value(attrs, "name") {
+                Some(name) => nfc(name).into_owned(),
+                None => return,
+            };
+            if !seen.names.insert(name.clone()) {
+                errors.push(LinkError::new(ErrorKind::DuplicateId,
+                                           file,
+                                           line,
+                                           col,
+                                           file.display())
+                                .with_fragment(&name));
+            }
+            add_id(Arc::make_mut(&mut self.ids), name);
+        });
+    }
+
+    /// Collects the ids of the page a chunk of lines at a time, until `found`
+    /// is happy with them or the whole page has been scanned. Duplicates
+    /// aren't looked for; see `Options::lazy_ids`.
+    fn scan_ids_until<F>(&mut self, contents: &str, found: F)
+        where F: Fn(&HashSet<String>) -> bool
+    {
+        while !self.parsed && self.scanned < contents.len() && !found(&self.ids) {
+            let rest = &contents[self.scanned..];
+            // Ids are found line by line, so a chunk can end after any line.
+            let end = rest.as_bytes()
+                          .iter()
+                          .skip(ID_SCAN_CHUNK)
+                          .position(|&b| b == b'\n')
+                          .map_or(rest.len(), |i| ID_SCAN_CHUNK + i + 1);
+            let chunk = &rest[..end];
+            let ids = Arc::make_mut(&mut self.ids);
+            with_attrs_in_source(chunk, "id", |id, _, _, _| add_id(ids, nfc(id).into_owned()));
+            with_tags_in_source(chunk, "<a", |attrs, _, _| {
+                if let Some(name) = attr_value(attrs, "name") {
+                    add_id(ids, nfc(name).into_owned());
+                }
+            });
+            self.scanned += end;
+        }
+    }
+}
+
+/// Adds `id` to the ids of a page, along with the forms a link may give it
+/// in: percent-encoded, just in case, and without leading `#`s, which used to
+/// be all that was added. An id may contain `#` like any other character,
+/// linked to as `##id` or `#%23id`.
+fn add_id(ids: &mut HashSet<String>, id: String) {
+    ids.insert(small_url_encode(&id));
+    if id.starts_with('#') {
+        ids.insert(id.trim_start_matches('#').to_string());
+    }
+    ids.insert(id);
+}
+
+impl Checker {
+    /// Starts configuring a checker for the docs in `root`, for example:
+    ///
+    /// ```no_run
+    /// use linkchecker::Checker;
+    ///
+    /// # fn main() { run().unwrap() }
+    /// # fn run() -> Result<(), linkchecker::LinkCheckError> {
+    /// let mut checker = Checker::builder("build/doc").report_orphans(true).jobs(4).build();
+    /// let report = checker.run("build/doc".as_ref())?;
+    /// println!("{} problems", report.errors.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder<P: Into<PathBuf>>(root: P) -> CheckerBuilder {
+        CheckerBuilder {
+            root: root.into(),
+            options: Options::default(),
+            fs: None,
+            validators: Vec::new(),
+        }
+    }
+
+    /// Creates a checker resolving links against the docs in `root`.
+    pub fn new(root: &Path, options: Options) -> Checker {
+        Checker::with_file_system(root, options, Box::new(StdFs))
+    }
+
+    /// Creates a checker reading the docs in `root` from `fs`, for example
+    /// from a tarball.
+    pub fn with_file_system(root: &Path, options: Options, fs: Box<dyn FileSystem>) -> Checker {
+        Checker::with_shared_file_system(root, options, Arc::from(fs))
+    }
+
+    fn with_shared_file_system(root: &Path, options: Options, fs: Arc<dyn FileSystem>) -> Checker {
+        let id_cache = match options.id_cache {
+            Some(ref path) if !options.cross_file_ids => {
+                if options.jobs > 1 {
+                    warn!("the id cache {} isn't used by the threads of a walk with more \
+                           than one job", path.display());
+                }
+                Some(IdCache::load(path))
+            }
+            _ => None,
+        };
+        let printed_root = options.relative_to.as_ref().map(|base| {
+            relative_path(base, &fs.canonicalize(root).unwrap_or(root.to_path_buf()))
+        });
+        Checker {
+            root: root.to_path_buf(),
+            fs,
+            cache: Lru::new(options.cache_budget.map(|bytes| bytes as usize)),
+            options,
+            quiet: false,
+            stats: Stats::default(),
+            timings: Timings::default(),
+            errors: Vec::new(),
+            visited: HashSet::new(),
+            file_kinds: HashMap::new(),
+            pages: Vec::new(),
+            linked: HashSet::new(),
+            external: BTreeMap::new(),
+            listings: HashMap::new(),
+            dir_ids: HashMap::new(),
+            id_origins: HashMap::new(),
+            id_cache,
+            printed_root,
+            collisions: HashSet::new(),
+            validators: Vec::new(),
+            held: None,
+            shared_ids: None,
+            links: BTreeSet::new(),
+            navigation: BTreeMap::new(),
+            duplicates_reported: HashSet::new(),
+        }
+    }
+
+    /// Returns `err` with its path as it's printed, see
+    /// `Options::relative_to`.
+    pub fn display_error<'a>(&self, err: &'a LinkError) -> Cow<'a, LinkError> {
+        match self.printed_root {
+            Some(ref root) => {
+                let file = if err.file.is_absolute() {
+                    relative_path(self.options.relative_to.as_ref().unwrap(), &err.file)
+                } else {
+                    root.join(&err.file)
+                };
+                Cow::Owned(LinkError { file, ..err.clone() })
+            }
+            None => Cow::Borrowed(err),
+        }
+    }
+
+    /// Returns everything reported so far, including warnings, in the order
+    /// it was found. A walk on several threads records its problems once it's
+    /// done, sorted by page and position.
+    pub fn errors(&self) -> &[LinkError] {
+        &self.errors
+    }
+
+    /// Returns whether any broken links have been found so far.
+    pub fn has_errors(&self) -> bool {
+        self.stats.errors > 0
+    }
+
+    /// Returns the settings the checker was created with.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Returns the counters accumulated so far.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn timings(&self) -> &Timings {
+        &self.timings
+    }
+
+    /// Returns the set of ids defined by `file`, loading and parsing it if it
+    /// hasn't been seen yet.
+    ///
+    /// `file` may be absolute or relative to the docs root. Redirect pages are
+    /// followed to the page they point at.
+    pub fn ids_for(&mut self, file: &Path) -> Result<&HashSet<String>, LoadError> {
+        let file = self.root.join(file);
+        let (pretty_path, contents) = self.load_file(&file, FollowRedirect, &mut Vec::new())?;
+        self.parse_ids(&pretty_path, &contents);
+        Ok(&*self.cache[&pretty_path].ids)
+    }
+
+    /// Returns the ids defined by each page seen so far, by its path relative
+    /// to the docs root. Pages whose ids weren't collected, because of
+    /// `Options::exclude_fragments_for`, are left out.
+    pub fn id_index(&self) -> BTreeMap<PathBuf, Vec<String>> {
+        self.cache.iter().filter(|&(_, entry)| entry.parsed).map(|(path, entry)| {
+            let mut ids = entry.ids.iter().cloned().collect::<Vec<_>>();
+            ids.sort();
+            (path.clone(), ids)
+        }).collect()
+    }
+
+    /// Returns the links which resolved to a file so far, if
+    /// `Options::record_links` is set.
+    pub fn resolved_links(&self) -> &BTreeSet<ResolvedLink> {
+        &self.links
+    }
+
+    /// Collects the ids of the cached page `pretty_path` if that hasn't
+    /// happened yet, reporting duplicates. On a walk on several threads, the
+    /// first thread to get to a page does it for all of them, and reports its
+    /// duplicates.
+    fn parse_ids(&mut self, pretty_path: &Path, contents: &str) {
+        let shared = match self.shared_ids {
+            Some(ref shared) if !self.cache[pretty_path].parsed => shared.clone(),
+            _ => return self.collect_ids(pretty_path, contents),
+        };
+        let page = shared.get(pretty_path.to_path_buf(), || {
+            self.collect_ids(pretty_path, contents);
+            Some((pretty_path.to_path_buf(), Vec::new(), self.cache[pretty_path].ids.clone()))
+        });
+        match page {
+            Some((_, _, ids)) => {
+                let entry = self.cache.get_mut(pretty_path).unwrap();
+                if !entry.parsed {
+                    entry.ids = ids;
+                    entry.parsed = true;
+                }
+            }
+            // The thread which got to the page as a link target couldn't
+            // read it.
+            None => self.collect_ids(pretty_path, contents),
+        }
+    }
+
+    /// Collects the ids of the cached page `pretty_path` like `parse_ids`,
+    /// on this thread alone.
+    fn collect_ids(&mut self, pretty_path: &Path, contents: &str) {
+        let start = Instant::now();
+        let origins = if self.options.cross_file_ids {
+            Some(&mut self.id_origins)
+        } else {
+            None
+        };
+        let entry = self.cache.get_mut(pretty_path).unwrap();
+        let stamp = match self.id_cache {
+            Some(_) if !entry.parsed => Some(id_cache::stamp(contents)),
+            _ => None,
+        };
+        let errors = match (stamp, self.id_cache.as_mut()) {
+            (Some(stamp), Some(cache)) => {
+                let cached = cache.get(pretty_path, stamp).map(|cached| {
+                    entry.ids = Arc::new(cached.ids.iter().cloned().collect());
+                    entry.parsed = true;
+                    cached.duplicates.iter().map(|&(line, col, ref id)| {
+                        LinkError::new(ErrorKind::DuplicateId,
+                                       pretty_path,
+                                       line,
+                                       col,
+                                       pretty_path.display())
+                            .with_fragment(id)
+                    }).collect()
+                });
+                match cached {
+                    Some(errors) => errors,
+                    None => {
+                        let errors = entry.parse_ids(pretty_path, contents, origins);
+                        let mut ids = entry.ids.iter().cloned().collect::<Vec<_>>();
+                        ids.sort();
+                        let duplicates = errors.iter().map(|err| {
+                            (err.line, err.column, err.fragment.clone().unwrap_or_default())
+                        }).collect();
+                        cache.insert(pretty_path, stamp, ids, duplicates);
+                        errors
+                    }
+                }
+            }
+            _ => entry.parse_ids(pretty_path, contents, origins),
+        };
+        self.timings.ids += start.elapsed();
+        if self.options.cache_budget.is_some() && !errors.is_empty() &&
+           !self.duplicates_reported.insert(pretty_path.to_path_buf()) {
+            return;
+        }
+        for err in errors {
+            self.record(err);
+        }
+    }
+
+    /// Reports a link assumed to work thanks to javascript, if asked to by
+    /// `Options::verify_no_javascript_dependency`.
+    fn javascript_dependency(&mut self, file: &Path, line: usize, col: usize, url: &str,
+                             why: &str) {
+        if self.options.verify_no_javascript_dependency {
+            self.record(LinkError::new(ErrorKind::JavascriptDependency, file, line, col, url)
+                            .with_note(why));
+        }
+    }
+
+    /// Whether the fragments of links to the page `pretty_path` go unchecked,
+    /// see `Options::exclude_fragments_for`.
+    fn fragments_excluded(&self, pretty_path: &Path) -> bool {
+        let options = MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        self.options.exclude_fragments_for.iter()
+            .any(|pattern| pattern.matches_path_with(pretty_path, &options))
+    }
+
+    /// The severity `options.severities` gives to problems on `pretty_path`
+    /// which are errors by default, if any.
+    fn severity_for(&self, pretty_path: &Path) -> Option<Severity> {
+        let options = MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        self.options.severities.iter()
+            .find(|&(pattern, _)| pattern.matches_path_with(pretty_path, &options))
+            .map(|&(_, severity)| severity)
+    }
+
+    fn record(&mut self, mut err: LinkError) {
+        if err.kind.severity() == Severity::Error {
+            if let Some(severity) = self.severity_for(&err.file) {
+                err.severity = severity;
+            }
+        }
+        if err.severity == Severity::Warning && self.options.allow.contains(&err.kind) {
+            return;
+        }
+        if let Some(ref mut held) = self.held {
+            held.push(err);
+            return;
+        }
+        match err.severity {
+            Severity::Error => {
+                self.stats.errors += 1;
+                if !self.quiet {
+                    error!("{}", self.display_error(&err));
+                }
+            }
+            Severity::Warning => {
+                self.stats.warnings += 1;
+                if !self.quiet {
+                    warn!("{}", self.display_error(&err));
+                }
+            }
+        }
+        self.errors.push(err);
+    }
+
+    /// Recursively checks every HTML file in `dir`.
+    ///
+    /// Symbolic links are followed unless `Options::no_follow_symlinks` is
+    /// set, but each file and directory is only checked once no matter how
+    /// many links lead to it, and links back to a parent directory are reported
+    /// instead of being followed.
+    ///
+    /// Pages are checked on `Options::jobs` threads. With more than one, the
+    /// problems found are recorded once the walk is done, sorted by page and
+    /// position, so that the output doesn't depend on which thread got to a
+    /// page first. On a single thread they're recorded as they're found.
+    pub fn walk(&mut self, dir: &Path) -> Result<(), LinkCheckError> {
+        if self.options.jobs <= 1 {
+            return self.walk_streaming(dir, |_| true).map(|_| ());
+        }
+        if self.options.cross_file_ids {
+            self.held = Some(Vec::new());
+            let walked = self.walk_streaming(dir, |_| true);
+            let errors = self.held.take().unwrap();
+            self.record_sorted(errors);
+            return walked.map(|_| ());
+        }
+        let start = Instant::now();
+        let real_dir = self.canonicalize_docs(dir)?;
+        let mut files = Vec::new();
+        self.walk_dir(dir, real_dir, &mut Vec::new(), &mut |_, file| {
+            files.push(file.to_path_buf());
+            true
+        });
+        let checked = self.check_in_parallel(files);
+        self.timings.walk += start.elapsed();
+        checked
+    }
+
+    /// Checks the docs in `docs`, a directory to walk or a single page to
+    /// check against the others, then reports orphans and checks external
+    /// links as `Options` asks for. A single page which is a redirect has
+    /// nothing to check, which is no error.
+    pub fn run(&mut self, docs: &Path) -> Result<Report, LinkCheckError> {
+        let is_dir = self.fs.is_dir(docs).map_err(|err| {
+            LinkCheckError::UnreadableDocs(docs.to_path_buf(), err)
+        })?;
+        if is_dir {
+            self.walk(docs)?;
+            self.report_orphans();
+            self.report_navigation();
+        } else {
+            match self.check_file(docs) {
+                Ok(()) | Err(LoadError::IsRedirect) => {}
+                Err(err) => return Err(LinkCheckError::Load(docs.to_path_buf(), err)),
+            }
+        }
+        self.check_external();
+        Ok(Report {
+            errors: self.errors.clone(),
+            stats: self.stats.clone(),
+        })
+    }
+
+    fn canonicalize_docs(&self, dir: &Path) -> Result<PathBuf, LinkCheckError> {
+        self.fs.canonicalize(dir).map_err(|err| {
+            LinkCheckError::UnreadableDocs(dir.to_path_buf(), err)
+        })
+    }
+
+    /// Checks `files` on `Options::jobs` threads, each taking the next
+    /// unchecked file when it's done with one, and then records what they
+    /// found, sorted.
+    fn check_in_parallel(&mut self, files: Vec<PathBuf>) -> Result<(), LinkCheckError> {
+        let files = Arc::new(files);
+        let next = Arc::new(AtomicUsize::new(0));
+        // Each thread needs to see every id with `Options::cross_file_ids`,
+        // and the shared ids would be kept beyond `Options::cache_budget`.
+        let shared_ids = if self.options.cross_file_ids || self.options.cache_budget.is_some() {
+            None
+        } else {
+            Some(Arc::new(SingleFlight::new()))
+        };
+        let cache_budget = self.options.cache_budget.map(|bytes| bytes / self.options.jobs as u64);
+        let workers = (0..self.options.jobs).map(|_| {
+            let files = files.clone();
+            let next = next.clone();
+            let shared_ids = shared_ids.clone();
+            let root = self.root.clone();
+            let fs = self.fs.clone();
+            let options = Options {
+                id_cache: None,
+                cache_budget,
+                ..self.options.clone()
+            };
+            let validators = self.validators.clone();
+            thread::spawn(move || {
+                let mut checker = Checker::with_shared_file_system(&root, options, fs);
+                checker.quiet = true;
+                checker.validators = validators;
+                checker.shared_ids = shared_ids;
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    if i >= files.len() {
+                        break;
+                    }
+                    // Problems reading files are recorded as errors already.
+                    let _ = checker.check_file(&files[i]);
+                }
+                // The sources can't leave the thread, but the ids are kept
+                // for `id_index`.
+                let ids = checker.cache.into_iter()
+                                       .filter(|(_, entry)| entry.parsed)
+                                       .map(|(path, entry)| (path, entry.ids))
+                                       .collect::<Vec<_>>();
+                (checker.errors, checker.stats, checker.timings, checker.pages,
+                 checker.linked, checker.external, ids, checker.links, checker.navigation)
+            })
+        }).collect::<Vec<_>>();
+
+        let mut errors = Vec::new();
+        let mut panicked = false;
+        for worker in workers {
+            let (found, stats, timings, pages, linked, external, ids, links, navigation) =
+                match worker.join() {
+                    Ok(found) => found,
+                    Err(_) => {
+                        // The other threads are still waited for, so that none
+                        // outlives the walk.
+                        panicked = true;
+                        continue;
+                    }
+                };
+            errors.extend(found);
+            // The problems are counted again as they're recorded, once each.
+            self.stats.merge(Stats { errors: 0, warnings: 0, ..stats });
+            self.timings.io += timings.io;
+            self.timings.scan += timings.scan;
+            self.timings.ids += timings.ids;
+            self.pages.extend(pages);
+            self.linked.extend(linked);
+            self.links.extend(links);
+            self.navigation.extend(navigation);
+            for (url, sources) in external {
+                self.external.entry(url).or_default().extend(sources);
+            }
+            for (path, ids) in ids {
+                if self.cache.contains_key(&path) {
+                    continue;
+                }
+                let file = self.root.join(&path);
+                self.cache.insert(path, FileEntry {
+                    file,
+                    source: Rc::new(String::new()),
+                    ids,
+                    parsed: true,
+                    scanned: 0,
+                    loose_ids: None,
+                });
+            }
+        }
+        self.stats.pages_evicted += self.cache.take_evicted();
+        for sources in self.external.values_mut() {
+            sources.sort();
+        }
+        self.record_sorted(errors);
+        if panicked {
+            return Err(LinkCheckError::WorkerPanicked);
+        }
+        Ok(())
+    }
+
+    /// Records `errors` sorted by page and position.
+    fn record_sorted(&mut self, mut errors: Vec<LinkError>) {
+        // The duplicate ids of a page are found by every thread which reads
+        // it, but only reported once.
+        errors.sort_by(|a, b| {
+            (&a.file, a.line, a.column, a.kind, &a.target, &a.fragment)
+                .cmp(&(&b.file, b.line, b.column, b.kind, &b.target, &b.fragment))
+        });
+        errors.dedup_by(|a, b| {
+            (&a.file, a.line, a.column, a.kind, &a.target, &a.fragment) ==
+                (&b.file, b.line, b.column, b.kind, &b.target, &b.fragment)
+        });
+        for err in errors {
+            self.record(err);
+        }
+    }
+
+    /// Like `walk`, but hands each problem to `f` as soon as the page it's
+    /// found on has been checked. The walk stops early if `f` returns `false`,
+    /// in which case `Ok(false)` is returned.
+    ///
+    /// Problems are still collected in `errors` as well. Those found after the
+    /// walk, by `report_orphans` and `check_external`, aren't passed to `f`.
+    pub fn walk_streaming<F>(&mut self, dir: &Path, mut f: F) -> Result<bool, LinkCheckError>
+        where F: FnMut(&LinkError) -> bool
+    {
+        let start = Instant::now();
+        let real_dir = self.canonicalize_docs(dir)?;
+        // Problems found outside of pages, like symlink cycles, are passed on
+        // along with the next page's.
+        let mut streamed = self.errors.len();
+        let mut completed = self.walk_dir(dir, real_dir, &mut Vec::new(), &mut |checker, file| {
+            // Problems reading files are recorded as errors already.
+            let _ = checker.check_file(file);
+            let go_on = checker.stream(streamed, &mut f);
+            streamed = checker.errors.len();
+            go_on
+        });
+        if completed {
+            completed = self.stream(streamed, &mut f);
+        }
+        self.timings.walk += start.elapsed();
+        if let Some(ref mut cache) = self.id_cache {
+            if let Err(e) = cache.save() {
+                warn!("failed to save id cache: {}", e);
+            }
+        }
+        Ok(completed)
+    }
+
+    /// Passes the errors recorded since index `start` to `f`, returning
+    /// whether the walk should go on.
+    fn stream(&self, start: usize, f: &mut dyn FnMut(&LinkError) -> bool) -> bool {
+        self.errors[start..].iter().all(f)
+    }
+
+    /// Hands each file below `dir` to `visit`, stopping early once it returns
+    /// `false`.
+    fn walk_dir(&mut self,
+                dir: &Path,
+                real_dir: PathBuf,
+                ancestors: &mut Vec<PathBuf>,
+                visit: &mut dyn FnMut(&mut Checker, &Path) -> bool) -> bool {
+        if ancestors.contains(&real_dir) {
+            let pretty_dir = PathBuf::from(dir.strip_prefix(&self.root).unwrap_or(dir));
+            self.record(LinkError::new(ErrorKind::SymlinkCycle,
+                                       &pretty_dir,
+                                       0,
+                                       0,
+                                       real_dir.display()));
+            return true;
+        }
+        if !self.visited.insert(real_dir.clone()) {
+            debug!("already walked {}", real_dir.display());
+            return true;
+        }
+        info!("walking {}", dir.display());
+        let entries = match self.fs.read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => return self.unreadable_dir(dir, &err),
+        };
+        ancestors.push(real_dir.clone());
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    if self.unreadable_dir(dir, &err) {
+                        continue;
+                    }
+                    ancestors.pop();
+                    return false;
+                }
+            };
+            let path = entry.path;
+            let mut is_dir = entry.is_dir;
+            // Only symlinks need the expensive canonicalization, everything
+            // else is where its directory says it is.
+            let mut real_path = real_dir.join(path.file_name().unwrap());
+            if entry.is_symlink {
+                if self.options.no_follow_symlinks {
+                    debug!("skipping symlink {}", path.display());
+                    continue;
+                }
+                match (self.fs.canonicalize(&path), self.fs.is_dir(&path)) {
+                    (Ok(real), Ok(dir)) => {
+                        real_path = real;
+                        is_dir = dir;
+                    }
+                    _ => {
+                        debug!("skipping dangling symlink {}", path.display());
+                        continue;
+                    }
+                }
+            }
+            if is_dir && self.options.max_depth.is_some_and(|max| ancestors.len() > max) {
+                debug!("not walking {}: too deep", path.display());
+                continue;
+            }
+            let completed = if is_dir {
+                self.walk_dir(&path, real_path, ancestors, visit)
+            } else {
+                if !self.visited.insert(real_path) {
+                    debug!("already checked {}", path.display());
+                    continue;
+                }
+                visit(self, &path)
+            };
+            if !completed {
+                debug!("walk stopped early in {}", dir.display());
+                ancestors.pop();
+                return false;
+            }
+        }
+        ancestors.pop();
+        true
+    }
+
+    /// Reports that `dir`, or one of its entries, couldn't be read, returning
+    /// whether the walk goes on; see `Options::strict_io`.
+    fn unreadable_dir(&mut self, dir: &Path, err: &io::Error) -> bool {
+        let pretty_dir = PathBuf::from(dir.strip_prefix(&self.root).unwrap_or(dir));
+        let mut err = LinkError::new(ErrorKind::UnreadableDir,
+                                     &pretty_dir,
+                                     0,
+                                     0,
+                                     pretty_dir.display())
+                          .with_note(err);
+        if self.options.strict_io {
+            err.severity = Severity::Error;
+        }
+        self.record(err);
+        !self.options.strict_io
+    }
+
+    /// Checks the links in a single HTML file, which is expected to be
+    /// somewhere below the docs root.
+    ///
+    /// Files which aren't HTML or are ignored through `Options::ignore` are
+    /// skipped. Redirect pages aren't checked either, returning
+    /// `LoadError::IsRedirect`.
+    pub fn check_file(&mut self, file: &Path) -> Result<(), LoadError> {
+        let pretty_path = self.check(file);
+        self.stats.pages_evicted += self.cache.take_evicted();
+        let pretty_path = pretty_path?;
+        // With `Options::cache_budget` the page may be gone already.
+        if let Some(entry) = pretty_path.and_then(|path| self.cache.get_mut(&path)) {
+            // we don't need the source anymore,
+            // so drop to reduce memory-usage
+            entry.source = Rc::new(String::new());
+        }
+        Ok(())
+    }
+
+    /// Forgets what was read from `file`, absolute or relative to the docs
+    /// root, after it changed on disk, so that it's read again the next time
+    /// it's checked or linked to, and the problems found on it. Which files
+    /// exist is looked up again too, for files which were added or removed.
+    ///
+    /// Returns the pages, relative to the root, whose links to `file` may
+    /// fare differently now: those with a problem pointing to it, and those
+    /// whose links to it resolved if `Options::record_links` is set. They
+    /// need to be forgotten and checked again as well.
+    pub fn forget(&mut self, file: &Path) -> Vec<PathBuf> {
+        let file = self.root.join(file);
+        let pretty_file = file.strip_prefix(&self.root).unwrap_or(&file).to_path_buf();
+        let mut linking = self.links.iter()
+                                    .filter(|link| link.target == pretty_file)
+                                    .map(|link| link.source.clone())
+                                    .collect::<BTreeSet<_>>();
+        linking.extend(self.errors.iter()
+                                  .filter(|err| Path::new(&err.target) == pretty_file)
+                                  .map(|err| err.file.clone()));
+        linking.remove(&pretty_file);
+        let (forgotten, kept) = mem::take(&mut self.errors)
+            .into_iter()
+            .partition::<Vec<_>, _>(|err| err.file == pretty_file);
+        self.errors = kept;
+        for err in forgotten {
+            match err.severity {
+                Severity::Error => self.stats.errors -= 1,
+                Severity::Warning => self.stats.warnings -= 1,
+            }
+        }
+        self.pages.retain(|page| *page != pretty_file);
+        self.cache.remove(&pretty_file);
+        self.file_kinds.clear();
+        self.listings.clear();
+        self.dir_ids.clear();
+        self.id_origins.retain(|_, page| *page != pretty_file);
+        self.navigation.remove(&pretty_file);
+        self.duplicates_reported.remove(&pretty_file);
+        self.links = mem::take(&mut self.links)
+            .into_iter()
+            .filter(|link| link.source != pretty_file)
+            .collect();
+        linking.into_iter().collect()
+    }
+
+    /// Checks the links in `contents` as if it were the page `file` below the
+    /// docs root, whether that exists or not. This is for pages which haven't
+    /// been written to disk, like one piped in from an editor.
+    pub fn check_html(&mut self, file: &Path, contents: &str) -> Result<(), LoadError> {
+        if maybe_redirect(contents).is_some() {
+            return Err(LoadError::IsRedirect);
+        }
+        let pretty_file = file.strip_prefix(&self.root).unwrap_or(file).to_path_buf();
+        self.cache.insert(pretty_file, FileEntry {
+            file: file.to_path_buf(),
+            source: Rc::new(contents.to_owned()),
+            ids: Arc::default(),
+            parsed: false,
+            scanned: 0,
+            loose_ids: None,
+        });
+        // Links within the page lead to it even if it's not on disk.
+        self.file_kinds.insert(file.to_path_buf(), FileKind::File);
+        // The page on disk, if any, isn't what's being checked, so its ids
+        // mustn't come from the cache.
+        let id_cache = self.id_cache.take();
+        let res = self.check_file(file);
+        self.id_cache = id_cache;
+        res
+    }
+
+    fn check(&mut self, file: &Path) -> Result<Option<PathBuf>, LoadError> {
+        // Ignore none HTML files.
+        let is_markdown = self.options.markdown && markdown::is_markdown(file);
+        if !is_html(file) && !is_markdown {
+            return Ok(None);
+        }
+        debug!("checking {}", file.display());
+
+        if is_whitelisted(file) {
+            debug!("skipping {}: whitelisted", file.display());
+            return Ok(None);
+        }
+        if self.options.ignore.iter().any(|pattern| file.ends_with(pattern)) {
+            debug!("skipping {}: ignored", file.display());
+            return Ok(None);
+        }
+
+        if self.is_huge(file) {
+            return self.check_streaming(file);
+        }
+
+        let res = self.load_file(file, SkipRedirect, &mut Vec::new());
+        let (pretty_file, contents) = match res {
+            Ok(res) => res,
+            Err(LoadError::IOError(err)) => {
+                let pretty_file = file.strip_prefix(&self.root).unwrap_or(file).to_path_buf();
+                self.record(LinkError::new(ErrorKind::UnreadableFile,
+                                           &pretty_file,
+                                           0,
+                                           0,
+                                           pretty_file.display())
+                                .with_note(&err));
+                return Err(LoadError::IOError(err));
+            }
+            Err(err) => {
+                debug!("skipping {}: {}", file.display(), err);
+                return Err(err);
+            }
+        };
+        self.stats.files_checked += 1;
+        if self.options.report_orphans {
+            self.pages.push(pretty_file.clone());
+        }
+        if !self.fragments_excluded(&pretty_file) {
+            self.parse_ids(&pretty_file, &contents);
+        }
+
+        // The ids above are still needed for links into this page.
+        if contents.contains(IGNORE_FILE) {
+            debug!("skipping links of {}: {}", file.display(), IGNORE_FILE);
+            return Ok(Some(pretty_file));
+        }
+        let mut ignored = IgnoreNext::new(&contents);
+
+        if self.options.lint_structure {
+            with_attrs_in_source(&contents, "id", |id, i, col, _| {
+                if id.is_empty() {
+                    self.record(LinkError::new(ErrorKind::EmptyAttribute,
+                                               &pretty_file,
+                                               i + 1,
+                                               col,
+                                               "id"));
+                }
+            });
+        }
+
+        if self.options.check_canonical {
+            self.check_canonical(&pretty_file, &contents);
+        }
+        if self.options.check_navigation {
+            let navigation = self.navigation_links(&pretty_file, &contents);
+            self.navigation.insert(pretty_file.clone(), navigation);
+        }
+
+        // Search for anything that's the regex 'href[ ]*=[ ]*".*?"', and the
+        // same for `Options::extra_attrs`.
+        let extra_attrs = self.options.extra_attrs.clone();
+        let start = Instant::now();
+        let nested = self.timings.io + self.timings.ids;
+        let mut page = Page { file, pretty_file: &pretty_file, hidden: None };
+        let mut check_link = |attr: &str, url: &str, i: usize, col: usize, base: &str| {
+            if ignored.skip(i, col) {
+                debug!("{}:{}:{}: skipping {}", pretty_file.display(), i + 1, col, IGNORE_NEXT);
+                return;
+            }
+            self.check_link(&mut page, attr, url, i, col, base);
+        };
+        // Inline SVG may still use the older `xlink:href` to refer to symbols.
+        let mut attrs = vec!["href"];
+        if contents.contains("xlink:href") {
+            attrs.push("xlink:href");
+        }
+        attrs.extend(extra_attrs.iter().map(|a| &a[..]));
+        // Media elements refer to their files with `poster` and the `src` of
+        // their `<source>`s, which are checked unlike other assets.
+        if contents.contains("poster") {
+            attrs.push("poster");
+        }
+        let mut sources = Vec::new();
+        if !is_markdown && contents.contains("<source") {
+            with_tags_in_source(&contents, "<source", |tag, i, col| {
+                with_attrs_in_source(tag, "src", |_, _, src_col, _| {
+                    sources.push((i, col + "<source".len() + src_col - 1));
+                });
+            });
+        }
+        if is_markdown {
+            markdown::with_links(&contents, |url, i, col| check_link("href", url, i, col, ""));
+        } else if attrs.len() == 1 && sources.is_empty() {
+            with_attrs_in_source(&contents, "href", |url, i, col, base| {
+                check_link("href", url, i, col, base)
+            });
+        } else {
+            // Links are checked in order, for `IGNORE_NEXT` to find them.
+            let mut links = Vec::new();
+            for attr in attrs {
+                with_attrs_in_source(&contents, attr, |url, i, col, base| {
+                    links.push((i, col, attr, url, base));
+                });
+            }
+            with_attrs_in_source(&contents, "src", |url, i, col, base| {
+                if sources.contains(&(i, col)) {
+                    links.push((i, col, "src", url, base));
+                }
+            });
+            links.sort();
+            for (i, col, attr, url, base) in links {
+                check_link(attr, url, i, col, base);
+            }
+        }
+        let nested = self.timings.io + self.timings.ids - nested;
+        self.timings.scan += start.elapsed() - nested;
+
+        // The rest is about HTML, which Markdown only has bits of.
+        if is_markdown {
+            for (label, i, col) in markdown::undefined_references(&contents) {
+                self.record(LinkError::new(ErrorKind::BrokenLink,
+                                           &pretty_file,
+                                           i + 1,
+                                           col,
+                                           format!("[{}]", label))
+                                .with_note("no such reference definition"));
+            }
+            return Ok(Some(pretty_file));
+        }
+
+        // Assets aren't checked, but an empty `src` loads the page itself
+        // again, like an empty `href`.
+        with_attrs_in_source(&contents, "src", |url, i, col, _| {
+            if url.is_empty() && !sources.contains(&(i, col)) {
+                self.record(LinkError::new(ErrorKind::EmptyAttribute,
+                                           &pretty_file,
+                                           i + 1,
+                                           col,
+                                           "src"));
+            }
+        });
+
+        if self.options.report_absolute_links {
+            with_attrs_in_source(&contents, "src", |url, i, col, _| {
+                if is_root_absolute(url) && !sources.contains(&(i, col)) {
+                    self.record(LinkError::new(ErrorKind::AbsoluteLink,
+                                               &pretty_file,
+                                               i + 1,
+                                               col,
+                                               url));
+                }
+            });
+        }
+        if self.options.report_mixed_content {
+            self.check_mixed_content(&pretty_file, &contents);
+        }
+        if self.options.check_link_text {
+            self.check_link_text(&pretty_file, &contents);
+        }
+        Ok(Some(pretty_file))
+    }
+
+    /// Whether the page `file` is bigger than `Options::stream_threshold`, and
+    /// so is read a chunk at a time. Markdown pages are always read whole.
+    fn is_huge(&self, file: &Path) -> bool {
+        match self.options.stream_threshold {
+            Some(threshold) if !markdown::is_markdown(file) => {
+                self.fs.size(file).is_ok_and(|size| size > threshold)
+            }
+            _ => false,
+        }
+    }
+
+    /// Collects the ids of the page `file` a chunk at a time, see
+    /// `Options::stream_threshold`, and caches them without its source.
+    /// Returns whether the page has `IGNORE_FILE`.
+    fn stream_ids(&mut self, file: &Path, pretty_file: &Path) -> io::Result<bool> {
+        let fs = self.fs.clone();
+        // The page may have been read whole already as a link target.
+        let cached = self.cache.get(pretty_file).map(|entry| entry.parsed);
+        let parse_ids = cached != Some(true) && !self.fragments_excluded(pretty_file);
+        let mut entry = FileEntry {
+            file: file.to_path_buf(),
+            source: Rc::new(String::new()),
+            ids: Arc::default(),
+            parsed: true,
+            scanned: 0,
+            loose_ids: None,
+        };
+        let mut seen = SeenIds::default();
+        let mut errors = Vec::new();
+        let mut ignore_file = false;
+        let started = Instant::now();
+        let res = {
+            let cross_file_ids = self.options.cross_file_ids;
+            let origins = &mut self.id_origins;
+            with_chunks_of_file(&*fs, file, |chunk, start| {
+                ignore_file |= chunk.contains(IGNORE_FILE);
+                if parse_ids {
+                    let origins = if cross_file_ids { Some(&mut *origins) } else { None };
+                    entry.add_ids(pretty_file, chunk, start, &mut seen, origins, &mut errors);
+                }
+            })
+        };
+        self.timings.ids += started.elapsed();
+        let valid = res?;
+        if cached == Some(true) {
+            if let Some(entry) = self.cache.get_mut(pretty_file) {
+                entry.source = Rc::new(String::new());
+            }
+        } else {
+            if !valid && cached.is_none() {
+                self.record(LinkError::new(ErrorKind::InvalidUtf8,
+                                           pretty_file,
+                                           0,
+                                           0,
+                                           pretty_file.display()));
+            }
+            self.cache.insert(pretty_file.to_path_buf(), entry);
+        }
+        for err in errors {
+            self.record(err);
+        }
+        Ok(ignore_file)
+    }
+
+    /// Checks the page `file` a chunk at a time, see
+    /// `Options::stream_threshold`. Nothing of it is left in the cache but its
+    /// ids, so there's no source for the caller to drop.
+    fn check_streaming(&mut self, file: &Path) -> Result<Option<PathBuf>, LoadError> {
+        let pretty_file = file.strip_prefix(&self.root).unwrap_or(file).to_path_buf();
+        debug!("streaming {}", pretty_file.display());
+        let fs = self.fs.clone();
+
+        // The ids come first, as links within the page may point further
+        // down.
+        let ignore_file = match self.stream_ids(file, &pretty_file) {
+            Ok(ignore_file) => ignore_file,
+            Err(err) => {
+                self.record(LinkError::new(ErrorKind::UnreadableFile,
+                                           &pretty_file,
+                                           0,
+                                           0,
+                                           pretty_file.display())
+                                .with_note(&err));
+                return Err(LoadError::IOError(err));
+            }
+        };
+        self.stats.files_checked += 1;
+        if self.options.report_orphans {
+            self.pages.push(pretty_file.clone());
+        }
+        if ignore_file {
+            debug!("skipping links of {}: {}", file.display(), IGNORE_FILE);
+            return Ok(None);
+        }
+
+        let mut attrs = vec!["href".to_string(), "xlink:href".to_string()];
+        attrs.extend(self.options.extra_attrs.iter().cloned());
+        let mut page = Page { file, pretty_file: &pretty_file, hidden: None };
+        // Like when the page is read whole, a `<base>` applies to the links
+        // after it, including those in later chunks.
+        let mut base = String::new();
+        let res = with_chunks_of_file(&*fs, file, |chunk, start| {
+            let mut bases = Vec::new();
+            with_tags_in_source(chunk, "<base", |tag, i, col| {
+                if let Some(href) = attr_value(tag, "href") {
+                    bases.push((i, col, href.to_string()));
+                }
+            });
+            // A marker at the very end of a chunk doesn't carry over to the
+            // next one, which is good enough for pages too big to edit.
+            let mut ignored = IgnoreNext::new(chunk);
+            let mut links = Vec::new();
+            for attr in &attrs {
+                with_attrs_in_source(chunk, attr, |url, i, col, _| {
+                    links.push((i, col, &attr[..], url));
+                });
+            }
+            links.sort();
+            for (i, col, attr, url) in links {
+                let (line, col_in_line) = chunk_position(start, i, col);
+                if ignored.skip(i, col) {
+                    debug!("{}:{}:{}: skipping {}",
+                           pretty_file.display(), line, col_in_line, IGNORE_NEXT);
+                    continue;
+                }
+                let chunk_base = bases.iter().rev().find(|&&(j, base_col, _)| {
+                    (j, base_col) < (i, col)
+                });
+                let base = chunk_base.map_or(&base[..], |(_, _, href)| href);
+                self.check_link(&mut page, attr, url, line - 1, col_in_line, base);
+            }
+            if let Some((_, _, href)) = bases.pop() {
+                base = href;
+            }
+        });
+        match res {
+            Ok(_) => Ok(None),
+            Err(err) => Err(LoadError::IOError(err)),
+        }
+    }
+
+    /// Checks a link of `page`, in the attribute `attr` on its 0-based line
+    /// `i` at the 1-based byte column `col`, and resolved against `base`.
+    fn check_link(&mut self, page: &mut Page, attr: &str, url: &str, i: usize, col: usize,
+                  base: &str) {
+        let (file, pretty_file) = (page.file, page.pretty_file);
+        // Browsers strip whitespace from around URLs, so the link works
+        // but the source could use a cleanup.
+        let untrimmed = url;
+        let url = url.trim_matches([' ', '\t', '\n', '\r', '\x0c']);
+        if url.len() != untrimmed.len() && !url.is_empty() {
+            self.record(LinkError::new(ErrorKind::StrayWhitespace,
+                                       pretty_file,
+                                       i + 1,
+                                       col,
+                                       untrimmed));
+        }
+        let validation = self.validators
+                             .iter()
+                             .map(|validator| validator.validate(pretty_file, attr, url))
+                             .find(|validation| *validation != Validation::Continue);
+        if let Some(validation) = validation {
+            self.stats.links_checked += 1;
+            if let Validation::Invalid(reason) = validation {
+                self.record(LinkError::new(ErrorKind::BrokenLink,
+                                           pretty_file,
+                                           i + 1,
+                                           col,
+                                           url)
+                                .with_note(reason));
+            }
+            return;
+        }
+        if self.options.list_external &&
+           ["http:", "https:", "ftp:", "irc:"].iter().any(|s| url.starts_with(s)) {
+            let url = url.split('#').next().unwrap();
+            self.stats.external_urls.insert(url.to_string());
+        }
+        if self.options.check_external && external::is_http(url) {
+            let url = url.split('#').next().unwrap().to_string();
+            self.external.entry(url)
+                         .or_default()
+                         .push((pretty_file.to_path_buf(), i + 1, col));
+            return;
+        }
+        if url.starts_with("javascript:") {
+            self.javascript_dependency(pretty_file, i + 1, col, url, "javascript: URL");
+        }
+        // Ignore external URLs, including protocol-relative ones and
+        // relative ones on a page whose base is external.
+        if url.starts_with("http:") || url.starts_with("https:") || url.starts_with("//") ||
+           url.starts_with("javascript:") || url.starts_with("ftp:") ||
+           url.starts_with("irc:") || url.starts_with("data:") ||
+           self.options.skip_schemes.iter().any(|scheme| has_scheme(url, scheme)) ||
+           base.starts_with("http:") || base.starts_with("https:") ||
+           base.starts_with("//") {
+            self.stats.external_skipped += 1;
+            return;
+        }
+        self.stats.links_checked += 1;
+        if attr.starts_with("data-") {
+            self.javascript_dependency(pretty_file, i + 1, col, url,
+                                       "only followed by javascript");
+        }
+        // An empty link just reloads the current page, unlike a
+        // fragment-only one like `#`, which at worst scrolls to the top.
+        if url.is_empty() {
+            self.record(LinkError::new(ErrorKind::EmptyAttribute,
+                                       pretty_file,
+                                       i + 1,
+                                       col,
+                                       attr));
+            return;
+        }
+        if self.options.report_absolute_links && is_root_absolute(url) {
+            self.record(LinkError::new(ErrorKind::AbsoluteLink,
+                                       pretty_file,
+                                       i + 1,
+                                       col,
+                                       url));
+            return;
+        }
+        let href = url;
+        let (url, query, fragment) = split_url(href);
+        if url.is_empty() && query.is_some() {
+            self.record(LinkError::new(ErrorKind::QueryOnlyLink,
+                                       pretty_file,
+                                       i + 1,
+                                       col,
+                                       href));
+            return;
+        }
+        if query.is_some() && self.options.warn_query_links {
+            self.record(LinkError::new(ErrorKind::QueryLink,
+                                       pretty_file,
+                                       i + 1,
+                                       col,
+                                       href));
+        }
+
+        if is_root_absolute(url) && self.static_asset(url) {
+            return;
+        }
+
+        // Once we've plucked out the URL, parse it using our base url and
+        // then try to extract a file path.
+        let mut path = file.to_path_buf();
+        if !base.is_empty() || !url.is_empty() {
+            path.pop();
+            // As in a browser, a link resolves against the directory of
+            // the base, while a fragment-only link points into the base
+            // itself.
+            let mut base = if url.is_empty() {
+                base
+            } else {
+                &base[..base.rfind('/').map(|i| i + 1).unwrap_or(0)]
+            };
+            // There's no server to tell where a root-absolute base points
+            // to, so it's taken to be the docs root.
+            if is_root_absolute(base) {
+                path = self.root.clone();
+                base = &base[1..];
+            }
+            for part in Path::new(base).join(url).components() {
+                match part {
+                    Component::Prefix(_) |
+                    Component::RootDir => {
+                        self.record(LinkError::new(ErrorKind::UnresolvableLink,
+                                                   pretty_file,
+                                                   i + 1,
+                                                   col,
+                                                   href));
+                        return;
+                    }
+                    Component::CurDir => {}
+                    Component::ParentDir => { path.pop(); }
+                    Component::Normal(s) => { path.push(s); }
+                }
+            }
+        }
+        if !self.options.rewrites.is_empty() {
+            self.rewrite(&mut path, url.ends_with('/'));
+        }
+
+        // A trailing slash explicitly refers to the directory's index page,
+        // which is what a browser would load, so check that instead.
+        if url.ends_with('/') && self.file_kind(&path) == FileKind::Dir {
+            path.push(&self.options.index_name);
+        }
+
+        if self.options.warn_self_links && !url.is_empty() && path == file {
+            self.record(LinkError::new(ErrorKind::SelfLink,
+                                       pretty_file,
+                                       i + 1,
+                                       col,
+                                       href));
+        }
+
+        if let Some(extension) = self.options.assume_extension.clone() {
+            if path.extension().is_none() && self.file_kind(&path) == FileKind::Missing {
+                let mut with_extension = path.clone().into_os_string();
+                with_extension.push(".");
+                with_extension.push(&extension);
+                let with_extension = PathBuf::from(with_extension);
+                if self.file_kind(&with_extension) != FileKind::Missing {
+                    path = with_extension;
+                }
+            }
+        }
+
+        // Alright, if we've found a file name then this file had better
+        // exist! If it doesn't then we register and print an error.
+        let kind = self.file_kind(&path);
+        if kind == FileKind::Missing {
+            let optional = path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| {
+                self.options.ignore_extensions.iter().any(|i| i.eq_ignore_ascii_case(ext))
+            });
+            if optional {
+                return;
+            }
+        }
+        if kind != FileKind::Missing {
+            if kind == FileKind::Dir {
+                // Links to directories show as directory listings when viewing
+                // the docs offline so it's best to avoid them.
+                let err = {
+                    let pretty_path = path.strip_prefix(&self.root).unwrap_or(&path);
+                    LinkError::new(ErrorKind::DirectoryLink,
+                                   pretty_file,
+                                   i + 1,
+                                   col,
+                                   pretty_path.display())
+                };
+                self.record(err);
+                return;
+            }
+            if self.options.report_orphans {
+                let pretty_path = path.strip_prefix(&self.root).unwrap_or(&path).to_path_buf();
+                if pretty_path != pretty_file {
+                    self.linked.insert(pretty_path);
+                }
+            }
+            // Ignore none HTML files.
+            if path.extension().is_some() && !is_html(&path) &&
+               !(self.options.markdown && markdown::is_markdown(&path)) {
+                if self.options.record_links {
+                    let target = path.strip_prefix(&self.root).unwrap_or(&path).to_path_buf();
+                    self.links.insert(ResolvedLink {
+                        source: pretty_file.to_path_buf(),
+                        target,
+                        fragment: None,
+                    });
+                }
+                return;
+            }
+            let mut redirects = Vec::new();
+            let res = self.load_target(&path, &mut redirects);
+            let (pretty_path, contents) = match res {
+                Ok(res) => res,
+                Err(LoadError::IOError(err)) => {
+                    let pretty_path = path.strip_prefix(&self.root)
+                                          .unwrap_or(&path)
+                                          .to_path_buf();
+                    self.record(LinkError::new(ErrorKind::UnreadableFile,
+                                               pretty_file,
+                                               i + 1,
+                                               col,
+                                               pretty_path.display())
+                                    .with_note(&err));
+                    return;
+                }
+                Err(LoadError::BrokenRedirect(target, _)) => {
+                    self.record(LinkError::new(ErrorKind::BrokenRedirect,
+                                               pretty_file,
+                                               i + 1,
+                                               col,
+                                               target.display()));
+                    return;
+                }
+                Err(LoadError::RedirectLoop(page)) => {
+                    self.record(LinkError::new(ErrorKind::BrokenRedirect,
+                                               pretty_file,
+                                               i + 1,
+                                               col,
+                                               page.display())
+                                    .with_note("redirect loop"));
+                    return;
+                }
+                Err(LoadError::RedirectOutsideRoot(target)) => {
+                    self.record(LinkError::new(ErrorKind::BrokenRedirect,
+                                               pretty_file,
+                                               i + 1,
+                                               col,
+                                               target.display())
+                                    .with_note("outside of the docs root"));
+                    return;
+                }
+                Err(LoadError::IsRedirect) => unreachable!(),
+            };
+            if !redirects.is_empty() {
+                let chain = redirects.iter()
+                    .map(|(page, _)| page)
+                    .chain(Some(&pretty_path))
+                    .map(|page| page.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                if self.options.treat_redirect_as_error {
+                    self.record(LinkError::new(ErrorKind::RedirectedLink,
+                                               pretty_file,
+                                               i + 1,
+                                               col,
+                                               &chain));
+                }
+                if redirects.len() > 1 {
+                    self.record(LinkError::new(ErrorKind::RedirectChain,
+                                               pretty_file,
+                                               i + 1,
+                                               col,
+                                               &chain));
+                }
+            }
+            // The page a redirect stub leads to is linked too.
+            if self.options.report_orphans && pretty_path != pretty_file {
+                self.linked.insert(pretty_path.clone());
+            }
+            // Like an HTTP redirect, a stub redirecting to a fragment
+            // replaces the link's own one, and it has to exist just as well.
+            let redirected = redirects.iter()
+                                      .rev()
+                                      .filter_map(|(page, fragment)| {
+                                          fragment.as_ref().map(|fragment| (page, fragment))
+                                      })
+                                      .next();
+            let fragment = redirected.map(|(_, fragment)| &fragment[..]).or(fragment);
+            // Taken back below if the fragment turns out to be missing. Links
+            // with the same fragment to the same page all fare the same.
+            let resolved = if self.options.record_links {
+                let link = ResolvedLink {
+                    source: pretty_file.to_path_buf(),
+                    target: pretty_path.clone(),
+                    fragment: fragment.map(|fragment| fragment.to_string()),
+                };
+                self.links.insert(link.clone());
+                Some(link)
+            } else {
+                None
+            };
+
+            if let Some(fragment) = fragment {
+                // A bare `#` is a placeholder, usually for a button driven
+                // by javascript.
+                if fragment.is_empty() {
+                    self.javascript_dependency(pretty_file, i + 1, col, href,
+                                               "placeholder for a javascript button");
+                    return;
+                }
+                // Browsers scroll to the top of the page for `#top` when
+                // nothing has that id.
+                if !self.options.strict_fragments && fragment.eq_ignore_ascii_case("top") {
+                    return;
+                }
+                // Fragments like `#1-6` are most likely line numbers to be
+                // interpreted by javascript, so we're ignoring these
+                if fragment.splitn(2, '-')
+                           .all(|f| f.chars().all(|c| c.is_numeric())) {
+                    self.javascript_dependency(pretty_file, i + 1, col, href,
+                                               "line numbers highlighted by javascript");
+                    return;
+                }
+                if self.fragments_excluded(&pretty_path) {
+                    return;
+                }
+
+                let normalized = nfc(fragment);
+                let fuzzy = self.options.fuzzy_fragments;
+                let ignore_case = self.options.case_insensitive_fragments;
+                let found = |ids: &HashSet<String>| ids.contains(&*normalized);
+                if self.options.lazy_ids && !markdown::is_markdown(&pretty_path) {
+                    let start = Instant::now();
+                    self.cache.get_mut(&pretty_path)
+                              .unwrap()
+                              .scan_ids_until(&contents, found);
+                    self.timings.ids += start.elapsed();
+                } else {
+                    self.parse_ids(&pretty_path, &contents);
+                }
+                let found = {
+                    let entry = self.cache.get_mut(&pretty_path).unwrap();
+                    // Not found by scanning lazily, so all of the ids are in.
+                    found(&entry.ids) ||
+                        (fuzzy || ignore_case) &&
+                            entry.has_loosely(&normalized, fuzzy, ignore_case)
+                };
+
+                if !found {
+                    if let Some(ref link) = resolved {
+                        self.links.remove(link);
+                    }
+                    let mut err = LinkError::new(ErrorKind::BrokenFragment,
+                                                 pretty_file,
+                                                 i + 1,
+                                                 col,
+                                                 pretty_path.display())
+                                      .with_fragment(fragment);
+                    let mut notes = Vec::new();
+                    if let Some((stub, _)) = redirected {
+                        notes.push(format!("redirected to by {}", stub.display()));
+                    }
+                    if self.options.suggest_fragment_pages {
+                        let pages = self.pages_with_id(&pretty_path, &normalized);
+                        if !pages.is_empty() {
+                            let pages = pages.iter()
+                                             .map(|page| page.display().to_string())
+                                             .collect::<Vec<_>>();
+                            notes.push(format!("found on {}", pages.join(", ")));
+                        }
+                    }
+                    if !notes.is_empty() {
+                        err = err.with_note(notes.join("; "));
+                    }
+                    self.record(err);
+                } else if self.options.check_hidden_fragments && pretty_path == pretty_file {
+                    let hidden = page.hidden.get_or_insert_with(|| hidden_ids(&contents));
+                    if hidden.contains(&*normalized) {
+                        self.record(LinkError::new(ErrorKind::HiddenFragment,
+                                                   pretty_file,
+                                                   i + 1,
+                                                   col,
+                                                   pretty_path.display())
+                                        .with_fragment(fragment));
+                    }
+                }
+            }
+        } else if !self.static_asset(url) {
+            let mut err = {
+                let pretty_path = path.strip_prefix(&self.root).unwrap_or(&path);
+                LinkError::new(ErrorKind::BrokenLink,
+                               pretty_file,
+                               i + 1,
+                               col,
+                               pretty_path.display())
+            };
+            if self.options.fix_suggestions {
+                if let Some(name) = self.suggest(&path) {
+                    err = err.with_note(format!("did you mean {}?", name));
+                }
+            }
+            self.record(err);
+        }
+    }
+
+    /// Warns about the links of `file` which screen readers have nothing to
+    /// read out for: no text once tags are left out, no `aria-label`,
+    /// `aria-labelledby` or `title`, and no image with an `alt` inside.
+    fn check_link_text(&mut self, pretty_file: &Path, contents: &str) {
+        let mut empty = Vec::new();
+        with_anchors_in_source(contents, |attrs, inner, i, col| {
+            let href = match attr_value(attrs, "href") {
+                Some(href) => href,
+                None => return,
+            };
+            let labelled = ["aria-label", "aria-labelledby", "title"].iter().any(|attr| {
+                attr_value(attrs, attr).is_some_and(|label| !label.trim().is_empty())
+            });
+            let mut described = false;
+            for attr in &["alt", "aria-label"] {
+                with_attrs_in_source(inner, attr, |label, _, _, _| {
+                    described |= !label.trim().is_empty();
+                });
+            }
+            if !labelled && !described && text_content(inner).trim().is_empty() {
+                empty.push((i, col, href.to_string()));
+            }
+        });
+        for (i, col, href) in empty {
+            self.record(LinkError::new(ErrorKind::EmptyLinkText, pretty_file, i + 1, col, href));
+        }
+    }
+
+    /// Warns about the assets of `file` loaded over plain `http:`: anything
+    /// in a `src` attribute, and stylesheets, icons and the like in `<link>`
+    /// tags. Links to other pages are fine, they just leave the `https:` site.
+    fn check_mixed_content(&mut self, pretty_file: &Path, contents: &str) {
+        let mut assets = Vec::new();
+        with_attrs_in_source(contents, "src", |url, i, col, _| {
+            if has_scheme(url, "http") {
+                assets.push((i, col, url.to_string()));
+            }
+        });
+        with_tags_in_source(contents, "<link", |tag, i, col| {
+            let rel = attr_value(tag, "rel").unwrap_or("").to_lowercase();
+            let is_asset = rel.split_whitespace().any(|rel| {
+                rel == "stylesheet" || rel == "icon" || rel == "preload" ||
+                    rel == "modulepreload" || rel == "manifest"
+            });
+            match attr_value(tag, "href") {
+                Some(href) if is_asset && has_scheme(href, "http") => {
+                    assets.push((i, col, href.to_string()))
+                }
+                _ => {}
+            }
+        });
+        assets.sort();
+        for (i, col, url) in assets {
+            self.record(LinkError::new(ErrorKind::MixedContent, pretty_file, i + 1, col, url));
+        }
+    }
+
+    /// Checks the `<link rel="canonical">` tags of `file`.
+    ///
+    /// Rustdoc points these at the page's authoritative URL, usually an
+    /// absolute `https:` one. The `href` scan skips those, or fetches them
+    /// with `Options::check_external`, but neither notices a URL which can't
+    /// work at all.
+    fn check_canonical(&mut self, pretty_file: &Path, contents: &str) {
+        with_tags_in_source(contents, "<link", |tag, i, col| {
+            if attr_value(tag, "rel").map(|rel| rel.to_lowercase()) != Some("canonical".into()) {
+                return;
+            }
+            let href = match attr_value(tag, "href") {
+                Some(href) => href,
+                None => {
+                    self.record(LinkError::new(ErrorKind::BrokenCanonical,
+                                               pretty_file,
+                                               i + 1,
+                                               col,
+                                               "")
+                                    .with_note("no href"));
+                    return;
+                }
+            };
+            let malformed = if external::is_http(href) {
+                let host = href.split_once("://").and_then(|(_, rest)| rest.split('/').next());
+                match host {
+                    Some(host) if !host.is_empty() && !host.contains(' ') => None,
+                    _ => Some("no host"),
+                }
+            } else if href.contains(':') {
+                Some("unsupported scheme")
+            } else if href.is_empty() || href.starts_with('/') {
+                Some("not an absolute URL or a relative path")
+            } else {
+                None
+            };
+            if let Some(reason) = malformed {
+                self.record(LinkError::new(ErrorKind::BrokenCanonical,
+                                           pretty_file,
+                                           i + 1,
+                                           col,
+                                           href)
+                                .with_note(reason));
+            }
+        });
+    }
+
+    /// Finds the `rel="next"` and `rel="prev"` links of `file`, in `<link>`
+    /// tags and in `<a>` elements.
+    fn navigation_links(&self, pretty_file: &Path, contents: &str) -> Navigation {
+        let mut links = Vec::new();
+        with_tags_in_source(contents, "<link", |tag, i, col| {
+            links.push((i, col, tag.to_string()))
+        });
+        with_anchors_in_source(contents, |tag, _, i, col| links.push((i, col, tag.to_string())));
+        links.sort();
+        let mut navigation = Navigation::default();
+        for (i, col, tag) in links {
+            let (rel, href) = match (attr_value(&tag, "rel"), attr_value(&tag, "href")) {
+                (Some(rel), Some(href)) => (rel.to_lowercase(), href),
+                _ => continue,
+            };
+            let target = match self.navigation_target(pretty_file, href) {
+                Some(target) => target,
+                None => continue,
+            };
+            let link = NavigationLink {
+                target,
+                href: href.to_string(),
+                line: i + 1,
+                column: col,
+            };
+            for rel in rel.split_whitespace() {
+                match rel {
+                    "next" if navigation.next.is_none() => navigation.next = Some(link.clone()),
+                    "prev" if navigation.prev.is_none() => navigation.prev = Some(link.clone()),
+                    _ => {}
+                }
+            }
+        }
+        navigation
+    }
+
+    /// Resolves a navigation link on `pretty_file` to the page it leads to,
+    /// relative to the root. External links lead nowhere in the docs.
+    fn navigation_target(&self, pretty_file: &Path, href: &str) -> Option<PathBuf> {
+        let (url, _, _) = split_url(href);
+        if url.is_empty() || url.starts_with("//") ||
+           url.split('/').next().is_some_and(|first| first.contains(':')) {
+            return None;
+        }
+        let mut path = if is_root_absolute(url) {
+            PathBuf::new()
+        } else {
+            pretty_file.parent().map(Path::to_path_buf).unwrap_or_default()
+        };
+        for part in Path::new(url).components() {
+            match part {
+                Component::ParentDir => { path.pop(); }
+                Component::Normal(s) => path.push(s),
+                _ => {}
+            }
+        }
+        if url.ends_with('/') {
+            path.push(&self.options.index_name);
+        }
+        Some(path)
+    }
+
+    /// Warns about the `rel="next"` and `rel="prev"` links of the pages
+    /// checked so far which don't form a consistent reading order, if
+    /// `Options::check_navigation` is set: links to pages which weren't
+    /// checked, `next` links without a `prev` link back from the page they
+    /// lead to and the other way around, and `next` links going around in a
+    /// cycle.
+    ///
+    /// This is only meaningful after walking the whole tree.
+    pub fn report_navigation(&mut self) {
+        let navigation = mem::take(&mut self.navigation);
+        let mut errors = Vec::new();
+        for (page, links) in &navigation {
+            let sides = [("next", &links.next, "prev"), ("prev", &links.prev, "next")];
+            for &(rel, link, back) in &sides {
+                let link = match *link {
+                    Some(ref link) => link,
+                    None => continue,
+                };
+                let target = link.target.display();
+                let note = match navigation.get(&link.target) {
+                    None => format!("`{}` leads to {}, which wasn't checked", rel, target),
+                    Some(other) => {
+                        let back_link = if back == "prev" { &other.prev } else { &other.next };
+                        match *back_link {
+                            None => {
+                                format!("`{}` leads to {}, which has no `{}` link",
+                                        rel,
+                                        target,
+                                        back)
+                            }
+                            Some(ref back_link) if back_link.target != *page => {
+                                format!("`{}` leads to {}, whose `{}` link leads to {}",
+                                        rel,
+                                        target,
+                                        back,
+                                        back_link.target.display())
+                            }
+                            Some(_) => continue,
+                        }
+                    }
+                };
+                errors.push(LinkError::new(ErrorKind::BrokenNavigation,
+                                           page,
+                                           link.line,
+                                           link.column,
+                                           &link.href)
+                                .with_note(note));
+            }
+        }
+
+        // Following the `next` links from each page in turn, every page is
+        // only visited once.
+        let mut done = HashSet::new();
+        for start in navigation.keys() {
+            let mut chain: Vec<&PathBuf> = Vec::new();
+            let mut page = start;
+            loop {
+                if done.contains(page) {
+                    break;
+                }
+                if let Some(pos) = chain.iter().position(|seen| *seen == page) {
+                    // Reported on the first page of the cycle by path.
+                    let cycle = &chain[pos..];
+                    let first = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap();
+                    let mut pages = cycle[first..].iter().chain(&cycle[..first])
+                                                  .map(|page| page.display().to_string())
+                                                  .collect::<Vec<_>>();
+                    pages.push(cycle[first].display().to_string());
+                    let link = navigation[cycle[first]].next.as_ref().unwrap();
+                    errors.push(LinkError::new(ErrorKind::BrokenNavigation,
+                                               cycle[first],
+                                               link.line,
+                                               link.column,
+                                               pages.join(" -> "))
+                                    .with_note("the `next` links go around in a cycle"));
+                    break;
+                }
+                chain.push(page);
+                match navigation.get(page).and_then(|links| links.next.as_ref()) {
+                    Some(link) if navigation.contains_key(&link.target) => page = &link.target,
+                    _ => break,
+                }
+            }
+            done.extend(chain);
+        }
+        self.navigation = navigation;
+        self.record_sorted(errors);
+    }
+
+    /// Warns about every page checked so far which no other page links to,
+    /// if `Options::report_orphans` is set. Such pages can only be found by
+    /// searching, and are often left behind when items move. Index pages,
+    /// see `Options::index_name`, are entry points and never reported.
+    ///
+    /// This is only meaningful after walking the whole tree.
+    pub fn report_orphans(&mut self) {
+        let mut pages = mem::take(&mut self.pages);
+        pages.sort();
+        for page in pages {
+            if page.ends_with(&self.options.index_name) || self.linked.contains(&page) {
+                continue;
+            }
+            self.record(LinkError::new(ErrorKind::OrphanPage, &page, 0, 0, page.display()));
+        }
+    }
+
+    /// Checks the external links found while walking, if
+    /// `Options::check_external` is set.
+    ///
+    /// Each distinct URL is only fetched once, no matter how many pages link
+    /// to it, within the limits set by `Options::external_max_per_host` and
+    /// `Options::external_rate`. See the `external` module for details.
+    pub fn check_external(&mut self) {
+        self.check_external_with(external::fetch)
+    }
+
+    fn check_external_with<F>(&mut self, fetch: F)
+        where F: Fn(&str) -> external::Outcome + Send + Sync + 'static
+    {
+        let mut cache = self.options.external_cache.as_ref().map(|path| {
+            external::Cache::load(path, self.options.external_cache_ttl)
+        });
+        let now = external::now();
+        let links = mem::take(&mut self.external);
+        let mut urls = Vec::new();
+        for (url, sources) in &links {
+            self.stats.external_checked += sources.len();
+            self.stats.external_hosts.entry(external::host(url))
+                                     .or_default()
+                                     .links += sources.len();
+            if cache.as_ref().is_some_and(|c| c.is_fresh(url, now)) {
+                debug!("{} was checked recently", url);
+            } else {
+                info!("checking {}", url);
+                urls.push(url.clone());
+            }
+        }
+        let limits = external::Limits {
+            max_per_host: self.options.external_max_per_host,
+            per_second: self.options.external_rate,
+        };
+        // Sorted so errors are reported in the same order every time.
+        let results = external::check_all(urls,
+                                          limits,
+                                          self.options.external_retries,
+                                          Duration::from_secs(1),
+                                          fetch)
+                          .into_iter()
+                          .collect::<BTreeMap<_, _>>();
+        for (url, res) in results {
+            match res {
+                Ok(()) => {
+                    if let Some(ref mut cache) = cache {
+                        cache.insert(&url, now);
+                    }
+                }
+                Err(reason) => {
+                    self.stats.external_hosts.get_mut(&external::host(&url)).unwrap().failed +=
+                        links[&url].len();
+                    for &(ref file, line, col) in &links[&url] {
+                        self.record(LinkError::new(ErrorKind::BrokenExternalLink,
+                                                   file,
+                                                   line,
+                                                   col,
+                                                   &url)
+                                        .with_note(&reason));
+                    }
+                }
+            }
+        }
+        if let Some(mut cache) = cache {
+            if let Err(e) = cache.save(now) {
+                warn!("failed to save external link cache: {}", e);
+            }
+        }
+    }
+
+    /// Applies `Options::rewrites` to the resolved link target `path`, as a
+    /// path below the docs root with `/` separators, ending in one if the
+    /// link did. Targets outside of the root are left alone.
+    fn rewrite(&self, path: &mut PathBuf, dir: bool) {
+        let mut relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => {
+                relative.components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/")
+            }
+            Err(_) => return,
+        };
+        if dir {
+            relative.push('/');
+        }
+        let mut rewritten = false;
+        for rewrite in &self.options.rewrites {
+            if let Cow::Owned(path) = rewrite.apply(&relative) {
+                debug!("rewrote {} to {} with {}", relative, path, rewrite);
+                relative = path;
+                rewritten = true;
+            }
+        }
+        if rewritten {
+            *path = self.root.join(relative);
+        }
+    }
+
+    /// Returns whether `url` is an asset found in `Options::static_root`.
+    fn static_asset(&mut self, url: &str) -> bool {
+        let path = {
+            let static_root = match self.options.static_root {
+                Some(ref dir) => dir,
+                None => return false,
+            };
+            let name = match Path::new(url).file_name() {
+                Some(name) => name,
+                None => return false,
+            };
+            if Path::new(name).extension().is_none() || is_html(Path::new(name)) {
+                return false;
+            }
+            static_root.join(name)
+        };
+        self.file_kind(&path) == FileKind::File
+    }
+
+    /// Finds the file next to the missing `path` whose name is closest to
+    /// its own, if there's one close enough to be what was meant.
+    fn suggest(&mut self, path: &Path) -> Option<String> {
+        let (dir, name) = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => (dir, name.to_string_lossy()),
+            _ => return None,
+        };
+        if !self.listings.contains_key(dir) {
+            let start = Instant::now();
+            let names = self.fs.read_dir(dir).map(|entries| {
+                entries.into_iter()
+                       .filter_map(|e| e.ok())
+                       .filter_map(|e| e.path.file_name().map(|n| n.to_string_lossy().into_owned()))
+                       .collect()
+            }).unwrap_or_default();
+            self.timings.io += start.elapsed();
+            self.listings.insert(dir.to_path_buf(), names);
+        }
+        // The same bound as rustc uses for its suggestions.
+        let max_distance = cmp::max(name.chars().count(), 3) / 3;
+        self.listings[dir].iter()
+            .map(|candidate| (edit_distance(&name, candidate), candidate))
+            .filter(|&(distance, _)| distance <= max_distance)
+            .min()
+            .map(|(_, candidate)| candidate.clone())
+    }
+
+    /// Finds the other pages next to `pretty_path` which define `id`, see
+    /// `Options::suggest_fragment_pages`. The ids of the whole directory are
+    /// collected the first time, from the cache where possible.
+    fn pages_with_id(&mut self, pretty_path: &Path, id: &str) -> Vec<PathBuf> {
+        let dir = match self.root.join(pretty_path).parent() {
+            Some(dir) => dir.to_path_buf(),
+            None => return Vec::new(),
+        };
+        if !self.dir_ids.contains_key(&dir) {
+            let start = Instant::now();
+            let mut files = self.fs.read_dir(&dir).unwrap_or_default()
+                                .into_iter()
+                                .filter_map(|entry| entry.ok())
+                                .filter(|entry| !entry.is_dir && is_html(&entry.path))
+                                .map(|entry| entry.path)
+                                .collect::<Vec<_>>();
+            files.sort();
+            let mut index = HashMap::new();
+            for file in files {
+                let pretty = file.strip_prefix(&self.root).unwrap_or(&file).to_path_buf();
+                let cached = match self.cache.get(&pretty) {
+                    Some(entry) if entry.parsed => Some(entry.ids.clone()),
+                    _ => None,
+                };
+                let ids = match cached {
+                    Some(ids) => ids,
+                    None => {
+                        let contents = match self.fs.read(&file) {
+                            Ok(bytes) => decode_html(bytes).0,
+                            Err(_) => continue,
+                        };
+                        if maybe_redirect(&contents).is_some() {
+                            continue;
+                        }
+                        let mut entry = FileEntry {
+                            file: file.clone(),
+                            source: Rc::new(String::new()),
+                            ids: Arc::default(),
+                            parsed: false,
+                            scanned: 0,
+                            loose_ids: None,
+                        };
+                        // Duplicates are reported when the page itself is
+                        // checked, not here.
+                        entry.parse_ids(&pretty, &contents, None);
+                        entry.ids
+                    }
+                };
+                for id in ids.iter() {
+                    index.entry(id.clone()).or_insert_with(Vec::new).push(pretty.clone());
+                }
+            }
+            self.timings.ids += start.elapsed();
+            self.dir_ids.insert(dir.clone(), index);
+        }
+        self.dir_ids[&dir].get(id).map(|pages| {
+            pages.iter().filter(|page| *page != pretty_path).cloned().collect()
+        }).unwrap_or_default()
+    }
+
+    /// Looks up what's at `path`, which must already be normalized so that
+    /// equivalent paths share a cache entry.
+    fn file_kind(&mut self, path: &Path) -> FileKind {
+        if let Some(&kind) = self.file_kinds.get(path) {
+            return kind;
+        }
+        let start = Instant::now();
+        let kind = match self.fs.is_dir(path) {
+            Ok(true) => FileKind::Dir,
+            Ok(false) => FileKind::File,
+            Err(_) => FileKind::Missing,
+        };
+        self.timings.io += start.elapsed();
+        self.file_kinds.insert(path.to_path_buf(), kind);
+        kind
+    }
+
+    /// Loads the page `file` a link leads to, following redirects like
+    /// `load_file`. On a walk on several threads, the first thread to get to
+    /// a page loads it and collects its ids for all of them, and the others
+    /// only keep its ids, without its source.
+    fn load_target(&mut self, file: &Path, redirects: &mut Vec<(PathBuf, Option<String>)>)
+                   -> Result<(PathBuf, Rc<String>), LoadError> {
+        let pretty_file = file.strip_prefix(&self.root).unwrap_or(file).to_path_buf();
+        let shared = match self.shared_ids {
+            Some(ref shared) if !self.options.lazy_ids &&
+                                 !self.cache.contains_key(&pretty_file) => shared.clone(),
+            _ => return self.load_file(file, FollowRedirect, redirects),
+        };
+        let mut loaded = None;
+        let page = shared.get(pretty_file.clone(), || {
+            let res = self.load_file(file, FollowRedirect, redirects);
+            let page = match res {
+                // `parse_ids` would wait for the very page being shared here.
+                Ok((ref pretty_path, ref contents)) if *pretty_path == pretty_file => {
+                    self.collect_ids(pretty_path, contents);
+                    Some((pretty_path.clone(), Vec::new(), self.cache[pretty_path].ids.clone()))
+                }
+                Ok((ref pretty_path, ref contents)) => {
+                    self.parse_ids(pretty_path, contents);
+                    let ids = self.cache[pretty_path].ids.clone();
+                    Some((pretty_path.clone(), redirects.clone(), ids))
+                }
+                Err(_) => None,
+            };
+            loaded = Some(res);
+            page
+        });
+        if let Some(res) = loaded {
+            return res;
+        }
+        match page {
+            Some((pretty_path, chain, ids)) => {
+                *redirects = chain;
+                if !self.cache.contains_key(&pretty_path) {
+                    let file = self.root.join(&pretty_path);
+                    self.cache.insert(pretty_path.clone(), FileEntry {
+                        file,
+                        source: Rc::new(String::new()),
+                        ids,
+                        parsed: true,
+                        scanned: 0,
+                        loose_ids: None,
+                    });
+                }
+                let source = self.cache[&pretty_path].source.clone();
+                Ok((pretty_path, source))
+            }
+            None => self.load_file(file, FollowRedirect, redirects),
+        }
+    }
+
+    /// Loads `file`, following redirects unless told to skip them. The
+    /// redirect pages passed through on the way are added to `redirects`,
+    /// relative to the root, along with the fragment each redirects to.
+    fn load_file(&mut self,
+                 file: &Path,
+                 redirect: Redirect,
+                 redirects: &mut Vec<(PathBuf, Option<String>)>)
+                 -> Result<(PathBuf, Rc<String>), LoadError> {
+        let pretty_file = PathBuf::from(file.strip_prefix(&self.root).unwrap_or(file));
+
+        // Only the ids of a page may be known, see `load_target`, which
+        // doesn't do for checking its own links.
+        let own_page = match redirect {
+            SkipRedirect => true,
+            FollowRedirect => false,
+        };
+        let cached = self.cache.get(&pretty_file).and_then(|entry| {
+            if own_page && entry.source.is_empty() && entry.file == file {
+                return None;
+            }
+            Some((entry.source.clone(),
+                  if entry.file != file { Some(entry.file.clone()) } else { None }))
+        });
+        if let Some((source, other)) = cached {
+            if let Some(other) = other {
+                if self.collisions.insert(file.to_path_buf()) {
+                    self.record(LinkError::new(ErrorKind::PageCollision,
+                                               &pretty_file,
+                                               0,
+                                               0,
+                                               file.display())
+                                    .with_note(format_args!("read {} instead", other.display())));
+                }
+            }
+            return Ok((pretty_file, source));
+        }
+        // Only the ids of a page too big to read whole are kept, and it can't
+        // be a redirect stub.
+        if self.is_huge(file) {
+            self.stream_ids(file, &pretty_file).map_err(LoadError::IOError)?;
+            return Ok((pretty_file, Rc::new(String::new())));
+        }
+
+        let start = Instant::now();
+        let bytes = self.fs.read(file).map_err(|err| {
+            match redirect {
+                FollowRedirect if !redirects.is_empty() &&
+                                  err.kind() == io::ErrorKind::NotFound => {
+                    LoadError::BrokenRedirect(file.to_path_buf(), err)
+                }
+                _ => LoadError::IOError(err),
+            }
+        })?;
+        let (contents, valid) = decode_html(bytes);
+        self.timings.io += start.elapsed();
+        let contents = Rc::new(contents);
+
+        // Redirect stubs are never cached, so an entry found above is
+        // always a real page and fragments are resolved against the ids
+        // of the page the stub points to, not the stub's own.
+        let redirect_url = match maybe_redirect(&contents) {
+            Some((url, fragment)) => {
+                if let SkipRedirect = redirect {
+                    return Err(LoadError::IsRedirect);
+                }
+                if redirects.iter().any(|(page, _)| *page == pretty_file) {
+                    return Err(LoadError::RedirectLoop(pretty_file));
+                }
+                redirects.push((pretty_file, fragment));
+                url
+            }
+            None => {
+                if !valid {
+                    self.record(LinkError::new(ErrorKind::InvalidUtf8,
+                                               &pretty_file,
+                                               0,
+                                               0,
+                                               pretty_file.display()));
+                }
+                self.cache.insert(pretty_file.clone(), FileEntry {
+                    file: file.to_path_buf(),
+                    source: contents.clone(),
+                    ids: Arc::default(),
+                    parsed: false,
+                    scanned: 0,
+                    loose_ids: None,
+                });
+                return Ok((pretty_file, contents));
+            }
+        };
+        // Redirects can't lead out of the docs, so that a broken one doesn't
+        // have the checker read any file at all.
+        let redirect_file = vfs::normalize(&file.parent().unwrap().join(redirect_url));
+        if !redirect_file.starts_with(vfs::normalize(&self.root)) {
+            return Err(LoadError::RedirectOutsideRoot(redirect_file));
+        }
+        debug!("following redirect {} -> {}", file.display(), redirect_file.display());
+        self.load_file(&redirect_file, FollowRedirect, redirects)
+    }
+}
+
+/// A page whose links are being checked.
+struct Page<'a> {
+    file: &'a Path,
+    pretty_file: &'a Path,
+    /// With `Options::check_hidden_fragments`, the ids of the hidden elements
+    /// of the page, found once there's a link within the page.
+    hidden: Option<HashSet<String>>,
+}
+
+const IGNORE_FILE: &str = "<!-- linkcheck:ignore-file -->";
+const IGNORE_NEXT: &str = "<!-- linkcheck:ignore-next -->";
+
+/// Finds the links following `IGNORE_NEXT` markers, as links are passed to
+/// `skip` in order.
+struct IgnoreNext {
+    /// Byte offsets of the markers, and of the start of each line.
+    markers: Vec<usize>,
+    line_starts: Vec<usize>,
+    next: usize,
+}
+
+impl IgnoreNext {
+    fn new(contents: &str) -> IgnoreNext {
+        let base = contents.as_ptr() as usize;
+        IgnoreNext {
+            markers: contents.match_indices(IGNORE_NEXT).map(|(i, _)| i).collect(),
+            line_starts: contents.lines().map(|line| line.as_ptr() as usize - base).collect(),
+            next: 0,
+        }
+    }
+
+    /// Returns whether the link at 0-based `line` and 1-based `column` is the
+    /// first one after a marker.
+    fn skip(&mut self, line: usize, column: usize) -> bool {
+        let offset = self.line_starts[line] + column - 1;
+        let mut skip = false;
+        while self.next < self.markers.len() && self.markers[self.next] < offset {
+            skip = true;
+            self.next += 1;
+        }
+        skip
+    }
+}
+
+/// Reads the file at `path` a chunk of about `STREAM_CHUNK` bytes at a time,
+/// calling `f` with each chunk and where it starts, as its 0-based line and
+/// the number of bytes before it on that line. A chunk ends before the last
+/// tag it has, which goes on in the next one. A leading byte order mark is
+/// dropped and invalid UTF-8 replaced, like with `decode_html`, returning
+/// whether there was any.
+fn with_chunks_of_file<F>(fs: &dyn FileSystem, path: &Path, mut f: F) -> io::Result<bool>
+    where F: FnMut(&str, (usize, usize))
+{
+    let mut reader = fs.open(path)?;
+    let mut bytes = Vec::new();
+    let mut start = (0, 0);
+    let mut valid = true;
+    let mut first = true;
+    loop {
+        let wanted = (STREAM_CHUNK - bytes.len()) as u64;
+        let read = reader.by_ref().take(wanted).read_to_end(&mut bytes)? as u64;
+        if bytes.is_empty() {
+            return Ok(valid);
+        }
+        if first && bytes.starts_with(b"\xef\xbb\xbf") {
+            bytes.drain(..3);
+        }
+        first = false;
+        let end = if read < wanted { bytes.len() } else { chunk_end(&bytes) };
+        let rest = bytes.split_off(end);
+        {
+            let chunk = String::from_utf8_lossy(&bytes);
+            valid &= match chunk {
+                Cow::Borrowed(_) => true,
+                Cow::Owned(_) => false,
+            };
+            f(&chunk, start);
+        }
+        start = match bytes.iter().rposition(|&b| b == b'\n') {
+            Some(i) => {
+                (start.0 + bytes.iter().filter(|&&b| b == b'\n').count(), bytes.len() - i - 1)
+            }
+            None => (start.0, start.1 + bytes.len()),
+        };
+        bytes = rest;
+    }
+}
+
+/// Where a chunk of a page which goes on after it ends, see
+/// `with_chunks_of_file`: before its last tag, or if it's all one tag or
+/// text, before its last character, which may be cut short.
+fn chunk_end(bytes: &[u8]) -> usize {
+    match bytes.iter().rposition(|&b| b == b'<') {
+        Some(i) if i > 0 => i,
+        _ => match bytes.iter().rposition(|&b| b & 0xc0 != 0x80) {
+            Some(i) if i > 0 => i,
+            _ => bytes.len(),
+        },
+    }
+}
+
+/// The 1-based line and byte column of a position in a chunk starting at
+/// `start`, as given by `with_chunks_of_file`, which is on the chunk's 0-based
+/// line `i` at its 1-based byte column `col`.
+fn chunk_position(start: (usize, usize), i: usize, col: usize) -> (usize, usize) {
+    if i == 0 {
+        (start.0 + 1, start.1 + col)
+    } else {
+        (start.0 + i + 1, col)
+    }
+}
+
+/// Decodes an HTML file, dropping a leading byte order mark. Pages which aren't
+/// valid UTF-8 are decoded lossily, returning `false` along with them.
+fn decode_html(mut bytes: Vec<u8>) -> (String, bool) {
+    if bytes.starts_with(b"\xef\xbb\xbf") {
+        bytes.drain(..3);
+    }
+    match String::from_utf8(bytes) {
+        Ok(contents) => (contents, true),
+        Err(err) => (String::from_utf8_lossy(err.as_bytes()).into_owned(), false),
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..b.len() + 1).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let substitution = diagonal + if ca == b[j] { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = cmp::min(substitution, cmp::min(row[j], row[j + 1]) + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Returns whether `file` is known to have broken links and is skipped.
+fn is_whitelisted(file: &Path) -> bool {
+    // Unfortunately we're not 100% full of valid links today to we need a few
+    // whitelists to get this past `make check` today.
+    // FIXME(#32129)
+    if file.ends_with("std/string/struct.String.html") ||
+       file.ends_with("interpret/struct.ValTy.html") ||
+       file.ends_with("symbol/struct.InternedString.html") ||
+       file.ends_with("ast/struct.ThinVec.html") ||
+       file.ends_with("util/struct.ThinVec.html") ||
+       file.ends_with("util/struct.RcSlice.html") ||
+       file.ends_with("layout/struct.TyLayout.html") ||
+       file.ends_with("ty/struct.Slice.html") ||
+       file.ends_with("ty/enum.Attributes.html") ||
+       file.ends_with("ty/struct.SymbolName.html") {
+        return true;
+    }
+    // FIXME(#32553)
+    if file.ends_with("string/struct.String.html") {
+        return true;
+    }
+    // FIXME(#32130)
+    if file.ends_with("btree_set/struct.BTreeSet.html") ||
+       file.ends_with("struct.BTreeSet.html") ||
+       file.ends_with("btree_map/struct.BTreeMap.html") ||
+       file.ends_with("hash_map/struct.HashMap.html") ||
+       file.ends_with("hash_set/struct.HashSet.html") ||
+       file.ends_with("sync/struct.Lrc.html") ||
+       file.ends_with("sync/struct.RwLock.html") {
+        return true;
+    }
+    // FIXME(#47038)
+    if file.ends_with("deriving/generic/index.html") ||
+       file.ends_with("deriving/generic/macro.vec.html") ||
+       file.ends_with("deriving/custom/macro.panic.html") ||
+       file.ends_with("proc_macro_impl/macro.panic.html") {
+        return true;
+    }
+    false
+}
+
+/// Returns `path` relative to `base`, going up with `..` where `path` isn't
+/// below it, or an empty path if they're the same. Both are taken as they
+/// are, without resolving symlinks or `..`. If only one of them is absolute,
+/// or they're on different Windows drives, `path` is returned unchanged.
+pub fn relative_path(base: &Path, path: &Path) -> PathBuf {
+    if base.has_root() != path.has_root() {
+        return path.to_path_buf();
+    }
+    let mut base = base.components().filter(|c| *c != Component::CurDir).peekable();
+    let mut rest = path.components().filter(|c| *c != Component::CurDir).peekable();
+    let mut shared = false;
+    while base.peek().is_some() && base.peek() == rest.peek() {
+        base.next();
+        rest.next();
+        shared = true;
+    }
+    if !shared && path.has_root() {
+        return path.to_path_buf();
+    }
+    let mut relative = base.map(|_| Component::ParentDir).collect::<PathBuf>();
+    relative.extend(rest);
+    relative
+}
+
+/// Returns whether `path` has the extension of an HTML page, in any case, as
+/// `.HTML` works just as well on case-insensitive file systems.
+fn is_html(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("html"))
+}
+
+/// Returns whether `url` is relative to the root of the server (`/foo.css`),
+/// as opposed to the current page or a network location (`//host/foo.css`).
+fn is_root_absolute(url: &str) -> bool {
+    url.starts_with('/') && !url.starts_with("//")
+}
+
+/// Splits a URL into its path, query and fragment.
+///
+/// As in RFC 3986 the fragment is everything after the first `#`, and the
+/// query is everything between the first `?` and the fragment. A `?` inside
+/// the fragment therefore doesn't start a query, and an escaped `%23` never
+/// starts a fragment.
+fn split_url(url: &str) -> (&str, Option<&str>, Option<&str>) {
+    let mut parts = url.splitn(2, '#');
+    let rest = parts.next().unwrap();
+    let fragment = parts.next();
+    let mut parts = rest.splitn(2, '?');
+    let path = parts.next().unwrap();
+    let query = parts.next();
+    (path, query, fragment)
+}
+
+/// Returns the URL a redirect stub page like the ones rustdoc writes points
+/// to, split from its fragment.
+fn maybe_redirect(source: &str) -> Option<(String, Option<String>)> {
+    const REDIRECT: &str = "<p>Redirecting to <a href=";
+
+    let mut lines = source.lines();
+    let redirect_line = lines.nth(6)?;
+
+    let i = redirect_line.find(REDIRECT)?;
+    let rest = redirect_line.get(i + REDIRECT.len() + 1..)?;
+    let (target, _) = rest.split_once('"')?;
+    Some(match target.split_once('#') {
+        Some((url, fragment)) => (url.to_owned(), Some(fragment.to_owned())),
+        None => (target.to_owned(), None),
+    })
+}
+
+/// Calls `f` with the attributes of every `tag` (like `"<link"`) in
+/// `contents`, the 0-based line it's on and the 1-based byte column where it
+/// starts. Tags spanning several lines aren't found.
+fn with_tags_in_source<F>(contents: &str, tag: &str, mut f: F)
+    where F: FnMut(&str, usize, usize)
+{
+    for (i, line) in contents.lines().enumerate() {
+        let mut start = 0;
+        while let Some(j) = line[start..].find(tag) {
+            let begin = start + j;
+            let rest = &line[begin + tag.len()..];
+            start = begin + tag.len();
+            // Skip longer tag names like `<linkage` for `<link`.
+            if !rest.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+                continue;
+            }
+            if let Some(end) = rest.find('>') {
+                f(&rest[..end], i, begin + 1);
+            }
+        }
+    }
+}
+
+/// Calls `f` with the attributes and the contents of every `<a>` element in
+/// `contents`, the 0-based line it starts on and the 1-based byte column of
+/// its `<a`. Unlike with `with_tags_in_source`, elements may span several
+/// lines. As in browsers, an element left open ends where the next one
+/// starts, or else at the end of the page.
+fn with_anchors_in_source<F>(contents: &str, mut f: F)
+    where F: FnMut(&str, &str, usize, usize)
+{
+    let mut line = 0;
+    let mut line_start = 0;
+    let mut counted = 0;
+    let mut start = 0;
+    while let Some(begin) = find_anchor(contents, start) {
+        start = begin + 2;
+        let rest = &contents[start..];
+        let attrs_end = match rest.find('>') {
+            Some(i) => i,
+            None => continue,
+        };
+        let inner = &rest[attrs_end + 1..];
+        let next = find_anchor(inner, 0).unwrap_or(inner.len());
+        let inner_end = inner[..next].find("</a>").unwrap_or(next);
+        for (k, b) in contents[counted..begin].bytes().enumerate() {
+            if b == b'\n' {
+                line += 1;
+                line_start = counted + k + 1;
+            }
+        }
+        counted = begin;
+        f(&rest[..attrs_end], &inner[..inner_end], line, begin - line_start + 1);
+        start += attrs_end + 1 + inner_end;
+    }
+}
+
+/// Returns the byte offset of the first `<a` tag in `contents` at or after
+/// `start`, skipping over tags like `<abbr>`.
+fn find_anchor(contents: &str, mut start: usize) -> Option<usize> {
+    while let Some(j) = contents[start..].find("<a") {
+        let begin = start + j;
+        start = begin + 2;
+        if contents[start..].starts_with(|c: char| c.is_whitespace() || c == '>') {
+            return Some(begin);
+        }
+    }
+    None
+}
+
+/// Returns the ids of the elements in `contents` which are hidden, either
+/// themselves or by one of their ancestors, through the `hidden` attribute or
+/// a `display: none` style. Elements are matched up by the name of their tags,
+/// with unclosed ones closed along with their parent.
+fn hidden_ids(contents: &str) -> HashSet<String> {
+    const VOID: &[&str] = &[
+        "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param",
+        "source", "track", "wbr",
+    ];
+    let mut ids = HashSet::new();
+    // The open elements, and whether each is hidden.
+    let mut open: Vec<(String, bool)> = Vec::new();
+    let mut rest = contents;
+    while let Some(i) = rest.find('<') {
+        rest = &rest[i + 1..];
+        if rest.starts_with("!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_lowercase();
+            if let Some(pos) = open.iter().rposition(|(open, _)| *open == name) {
+                open.truncate(pos);
+            }
+            continue;
+        }
+        let name_end = tag.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(tag.len());
+        let name = tag[..name_end].to_lowercase();
+        if name.is_empty() || name.starts_with('!') || name.starts_with('?') {
+            continue;
+        }
+        let attrs = &tag[name_end..];
+        let style = attr_value(attrs, "style").map(|style| {
+            style.to_lowercase().replace(char::is_whitespace, "")
+        });
+        let hidden = open.last().is_some_and(|&(_, hidden)| hidden) ||
+                     has_bare_attr(attrs, "hidden") ||
+                     style.is_some_and(|style| style.contains("display:none"));
+        if hidden {
+            if let Some(id) = attr_value(attrs, "id") {
+                ids.insert(nfc(id).into_owned());
+            }
+        }
+        // The contents of scripts and styles aren't markup.
+        if name == "script" || name == "style" {
+            let close = format!("</{}", name);
+            rest = rest.find(&close[..]).map_or("", |end| &rest[end..]);
+            continue;
+        }
+        if !tag.ends_with('/') && !VOID.contains(&&name[..]) {
+            open.push((name, hidden));
+        }
+    }
+    ids
+}
+
+/// Returns whether the attributes of a tag include `name`, with or without a
+/// value, like `hidden` in `<div class="x" hidden>`.
+fn has_bare_attr(attrs: &str, name: &str) -> bool {
+    let mut unquoted = String::new();
+    let mut quote = None;
+    for c in attrs.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None => unquoted.push(c),
+        }
+    }
+    unquoted.split(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .any(|attr| attr.eq_ignore_ascii_case(name))
+}
+
+/// The text of `html` with its tags left out, and non-breaking spaces turned
+/// into plain ones.
+fn text_content(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    decode_entities(&text)
+}
+
+/// Replaces the character references in `text`: the named ones pages use
+/// most, `&amp;`, `&lt;`, `&gt;`, `&quot;`, `&apos;` and `&nbsp;`, and any
+/// numeric one. Others are left as they are.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let end = rest.find(';').unwrap_or(0);
+        let name = &rest[1..cmp::max(end, 1)];
+        let c = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => match name.strip_prefix('#') {
+                Some(hex) if hex.starts_with('x') || hex.starts_with('X') => {
+                    u32::from_str_radix(&hex[1..], 16).ok().and_then(char::from_u32)
+                }
+                Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                None => None,
+            },
+        };
+        match c {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Returns the value of the quoted attribute `name` within the attributes of
+/// a tag, as found by `with_tags_in_source`.
+fn attr_value<'a>(attrs: &'a str, name: &str) -> Option<&'a str> {
+    let mut value = None;
+    with_attrs_in_source(attrs, name, |v, _, _, _| {
+        if value.is_none() {
+            value = Some(v);
+        }
+    });
+    value
+}
+
+/// Calls `f` with the value of every `attr` attribute in `contents`, the
+/// 0-based line it's on, the 1-based byte column where the value starts, and
+/// the document's `<base>` href.
+///
+/// Only whole attribute names inside of a tag are matched, so looking for
+/// `id` skips `data-id="..."` as well as `id="..."` in the text of a page.
+fn with_attrs_in_source<'a, F>(contents: &'a str, attr: &str, mut f: F)
+    where F: FnMut(&'a str, usize, usize, &'a str)
+{
+    let mut base = "";
+    for (i, full_line) in contents.lines().enumerate() {
+        let mut line = full_line;
+        while let Some(j) = line.find(attr) {
+            let before = &full_line[..full_line.len() - line.len() + j];
+            let rest = &line[j + attr.len()..];
+            line = rest;
+            if !before.is_empty() && !before.ends_with(char::is_whitespace) {
+                continue;
+            }
+            // Outside of tags if the last angle bracket closed one. A line
+            // without any may be the continuation of a tag, or a tag's
+            // attributes as passed by `attr_value`.
+            let in_text = match (before.rfind('<'), before.rfind('>')) {
+                (Some(open), Some(close)) => close > open,
+                (None, Some(_)) => true,
+                _ => false,
+            };
+            if in_text {
+                continue;
+            }
+            // The base tag should always be the first link in the document so
+            // we can get away with using one pass.
+            let is_base = before.trim_end().ends_with("<base");
+            let pos_equals = match rest.find("=") {
+                Some(i) => i,
+                None => continue,
+            };
+            if rest[..pos_equals].trim_start() != "" {
+                continue;
+            }
+
+            let rest = rest[pos_equals + 1..].trim_start();
+            let (rest, end) = match rest.chars().next() {
+                Some(quote @ '"') | Some(quote @ '\'') => {
+                    let rest = &rest[1..];
+                    match rest.find(quote) {
+                        Some(end) => (rest, end),
+                        None => continue,
+                    }
+                }
+                // Without quotes, the value ends at whitespace or the end of
+                // the tag. It can't be empty or contain quotes, so that markup
+                // escaped inside of another attribute, like `href=\"a.html\"`,
+                // isn't taken for a link.
+                Some(_) => {
+                    let end = rest.find(|c: char| c.is_whitespace() || c == '>')
+                                  .unwrap_or(rest.len());
+                    if end == 0 || rest[..end].contains(&['"', '\'', '=', '<', '`'][..]) {
+                        continue;
+                    }
+                    (rest, end)
+                }
+                None => continue,
+            };
+            let column = full_line.len() - rest.len() + 1;
+            let url = &rest[..end];
+            if is_base {
+                base = url;
+                continue;
+            }
+            f(url, i, column, base)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::error::Error;
+    use std::fs::{self, File};
+    use std::io;
+    use std::io::prelude::*;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{Checker, ErrorKind, LinkCheckError, LoadError, Options, ResolvedLink, Severity};
+    use super::external;
+    use super::{is_root_absolute, maybe_redirect, split_url};
+    use super::{edit_distance, relative_path, with_attrs_in_source};
+    use super::{LinkValidator, Validation};
+    use glob::Pattern;
+    use id_cache::{self, IdCache};
+    use rewrite::Rewrite;
+    use vfs::{FileSystem, MemoryFs};
+
+    /// A docs tree in a temporary directory, removed again when dropped.
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(name: &str, files: &[(&str, &str)]) -> Tree {
+            let root = env::temp_dir().join(format!("linkchecker-test-{}", name));
+            let _ = fs::remove_dir_all(&root);
+            for &(path, contents) in files {
+                let path = root.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+            }
+            Tree(root)
+        }
+
+        /// Checks the whole tree, returning whether any errors were found.
+        fn check(&self, options: Options) -> bool {
+            self.checker(options).has_errors()
+        }
+
+        fn checker(&self, options: Options) -> Checker {
+            let mut checker = Checker::new(&self.0, options);
+            checker.walk(&self.0).unwrap();
+            checker
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Checks a docs tree which only exists in memory, rooted at `/docs`.
+    fn check_in_memory(files: &[(&str, &str)], options: Options) -> Checker {
+        let root = Path::new("/docs");
+        let mut fs = MemoryFs::new();
+        for &(path, contents) in files {
+            fs.add_file(&root.join(path), contents.as_bytes());
+        }
+        let mut checker = Checker::with_file_system(root, options, Box::new(fs));
+        checker.walk(root).unwrap();
+        checker
+    }
+
+    /// The diagnostics of `checker` as they're printed.
+    fn messages(checker: &Checker) -> Vec<String> {
+        checker.errors().iter().map(|e| e.to_string()).collect()
+    }
+
+    fn hrefs(contents: &str) -> Vec<String> {
+        let mut urls = Vec::new();
+        with_attrs_in_source(contents, "href", |url, _, _, _| urls.push(url.to_owned()));
+        urls
+    }
+
+    #[test]
+    fn split_url_orderings() {
+        assert_eq!(split_url("foo.html"), ("foo.html", None, None));
+        assert_eq!(split_url("foo.html#bar"), ("foo.html", None, Some("bar")));
+        assert_eq!(split_url("foo.html?x=a"), ("foo.html", Some("x=a"), None));
+        assert_eq!(split_url("foo.html?x=a#b"), ("foo.html", Some("x=a"), Some("b")));
+        // A `?` after the `#` belongs to the fragment.
+        assert_eq!(split_url("foo.html#sec?note"), ("foo.html", None, Some("sec?note")));
+        // Only the first `#` starts the fragment.
+        assert_eq!(split_url("foo.html?a#b#c"), ("foo.html", Some("a"), Some("b#c")));
+        // Escaped hashes are part of the path.
+        assert_eq!(split_url("foo%23bar.html"), ("foo%23bar.html", None, None));
+        assert_eq!(split_url("?q#f"), ("", Some("q"), Some("f")));
+        assert_eq!(split_url("#f?q"), ("", None, Some("f?q")));
+        assert_eq!(split_url("foo.html?#"), ("foo.html", Some(""), Some("")));
+    }
+
+    #[test]
+    fn root_absolute_urls() {
+        assert!(is_root_absolute("/rust.css"));
+        assert!(is_root_absolute("/"));
+        assert!(!is_root_absolute("//cdn.example.com/rust.css"));
+        assert!(!is_root_absolute("rust.css"));
+        assert!(!is_root_absolute("../rust.css"));
+    }
+
+    #[test]
+    fn area_hrefs_are_scanned() {
+        let map = "<map name=\"m\">\n\
+                   <area shape=\"rect\" coords=\"0,0,1,1\" href=\"a.html#x\">\n\
+                   <area shape=\"circle\" coords=\"2,2,1\" href=\"b.html\" alt=\"b\">\n\
+                   </map>";
+        assert_eq!(hrefs(map), ["a.html#x", "b.html"]);
+    }
+
+    #[test]
+    fn unquoted_attributes() {
+        let page = "<a href=a.html>a</a> <a class=x href = b.html#y title=\"b\">b</a>\n\
+                    <a href=c.html/>c</a><a href= >d</a>\n\
+                    <script>var notable = \"<a class=\\\"x\\\" href=\\\"e.html\\\">\";</script>\n\
+                    <a href=f.html";
+        assert_eq!(hrefs(page), ["a.html", "b.html#y", "c.html/", "f.html"]);
+
+        let checker = check_in_memory(&[("index.html", "<a href=gone.html>gone</a>")],
+                                      Options::default());
+        let errors = messages(&checker);
+        assert_eq!(errors, ["index.html:1:9: broken link - gone.html"]);
+    }
+
+    #[test]
+    fn area_hrefs_are_resolved() {
+        let tree = Tree::new("area-ok", &[
+            ("index.html", "<map><area shape=\"rect\" href=\"a.html#x\"></map>"),
+            ("a.html", "<div id=\"x\"></div>"),
+        ]);
+        assert!(!tree.check(Options::default()));
+
+        let tree = Tree::new("area-missing", &[
+            ("index.html", "<map><area shape=\"rect\" href=\"missing.html\"></map>"),
+        ]);
+        assert!(tree.check(Options::default()));
+
+        let tree = Tree::new("area-fragment", &[
+            ("index.html", "<map><area shape=\"rect\" href=\"a.html#y\"></map>"),
+            ("a.html", "<div id=\"x\"></div>"),
+        ]);
+        assert!(tree.check(Options::default()));
+    }
+
+    #[test]
+    fn trailing_slash_links_use_index() {
+        let tree = Tree::new("slash-index", &[
+            ("index.html", "<a href=\"foo/\">foo</a><a href=\"foo/#x\">x</a>"),
+            ("foo/index.html", "<a href=\"../\">up</a><p id=\"x\"></p>"),
+        ]);
+        assert!(!tree.check(Options::default()));
+
+        let tree = Tree::new("slash-no-index", &[
+            ("index.html", "<a href=\"foo/\">foo</a>"),
+            ("foo/bar.html", ""),
+        ]);
+        assert!(tree.check(Options::default()));
+    }
+
+    #[test]
+    fn custom_index_names() {
+        let tree = Tree::new("readme-index", &[
+            ("README.html", "<a href=\"foo/\">foo</a><a href=\"bar/\">bar</a>"),
+            ("foo/README.html", "<a href=\"../\">up</a>"),
+            ("bar/index.html", ""),
+            ("bar/page.html", ""),
+        ]);
+        let options = Options {
+            index_name: "README.html".to_string(),
+            report_orphans: true,
+            ..Options::default()
+        };
+        let mut checker = tree.checker(options);
+        checker.report_orphans();
+        let errors = messages(&checker);
+        assert_eq!(errors, ["README.html:1:32: broken link - bar/README.html",
+                            "bar/index.html: warning: no other page links here",
+                            "bar/page.html: warning: no other page links here"]);
+    }
+
+    #[test]
+    fn cache_budget_rereads_pages() {
+        let files = [
+            ("a.html", "<a href=\"b.html#x\">1</a> <a href=\"c.html#gone\">2</a>\n\
+                        <span id=\"d\"></span><span id=\"d\"></span>"),
+            ("b.html", "<a href=\"a.html#d\">3</a> <a href=\"c.html#y\">4</a>\n\
+                        <span id=\"x\"></span>"),
+            ("c.html", "<a href=\"a.html#e\">5</a> <a href=\"b.html#x\">6</a>\n\
+                        <a href=\"#y\">7</a><span id=\"y\"></span>"),
+        ];
+        // Pages are read again in a different order, so their duplicate ids
+        // are found at other times.
+        let lines = |checker: &Checker| {
+            let mut lines = messages(checker);
+            lines.sort();
+            lines
+        };
+        let unbounded = check_in_memory(&files, Options::default());
+        assert_eq!(lines(&unbounded).len(), 3);
+        assert_eq!(unbounded.stats().pages_evicted, 0);
+        for &jobs in &[1, 2] {
+            // Too little to keep more than the page last read.
+            let options = Options { cache_budget: Some(1), jobs, ..Options::default() };
+            let bounded = check_in_memory(&files, options);
+            assert_eq!(lines(&bounded), lines(&unbounded));
+            assert!(bounded.stats().pages_evicted > 0);
+        }
+    }
+
+    #[test]
+    fn navigation_chains() {
+        let files = [
+            ("intro.html", "<link rel=\"next\" href=\"ch1.html\">"),
+            ("ch1.html", "<link rel=\"prev\" href=\"intro.html\">\n\
+                          <a rel=\"next prefetch\" href=\"ch2.html#top\">Next</a>"),
+            ("ch2.html", "<a rel=\"prev\" href=\"./intro.html\">Previous</a>\n\
+                          <a rel=\"next\" href=\"gone.html\">Next</a>"),
+            ("loop/a.html", "<link rel=next href=b.html><link rel=prev href=b.html>"),
+            ("loop/b.html", "<link rel=next href=a.html><link rel=prev href=a.html>"),
+        ];
+        let mut checker = check_in_memory(&files, Options {
+            check_navigation: true,
+            ..Options::default()
+        });
+        checker.report_navigation();
+        let errors = checker.errors().iter()
+            .filter(|e| e.kind == ErrorKind::BrokenNavigation)
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [
+            "ch1.html:2:1: warning: broken navigation link - ch2.html#top \
+             (`next` leads to ch2.html, whose `prev` link leads to intro.html)",
+            "ch2.html:1:1: warning: broken navigation link - ./intro.html \
+             (`prev` leads to intro.html, whose `next` link leads to ch1.html)",
+            "ch2.html:2:1: warning: broken navigation link - gone.html \
+             (`next` leads to gone.html, which wasn't checked)",
+            "loop/a.html:1:1: warning: broken navigation link - \
+             loop/a.html -> loop/b.html -> loop/a.html \
+             (the `next` links go around in a cycle)",
+        ]);
+
+        let checker = check_in_memory(&files, Options::default());
+        assert!(!checker.errors().iter().any(|e| e.kind == ErrorKind::BrokenNavigation));
+    }
+
+    #[test]
+    fn self_links() {
+        let files = [
+            ("std/struct.Foo.html", "<a href=\"struct.Foo.html\">Foo</a>\n\
+                                     <a href=\"#method.new\">new</a>\n\
+                                     <a href=\"../std/struct.Foo.html#method.new\">new</a>\n\
+                                     <a href=\"struct.Bar.html\">Bar</a>\n\
+                                     <p id=\"method.new\"></p>"),
+            ("std/struct.Bar.html", ""),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        assert!(checker.errors().is_empty());
+
+        let options = Options { warn_self_links: true, ..Options::default() };
+        let checker = check_in_memory(&files, options);
+        let errors = messages(&checker);
+        assert_eq!(errors, [
+            "std/struct.Foo.html:1:10: warning: link to the page it's on - struct.Foo.html",
+            "std/struct.Foo.html:3:10: warning: link to the page it's on - \
+             ../std/struct.Foo.html#method.new",
+        ]);
+    }
+
+    #[test]
+    fn rewritten_links() {
+        let files = [
+            ("index.html", "<a href=\"latest/std/index.html#vec\">a</a>\n\
+                            <a href=\"latest/std/gone.html\">b</a>\n\
+                            <a href=\"1.75.0/std/index.html\">c</a>"),
+            ("1.75.0/std/index.html", "<p id=\"vec\"></p>"),
+            ("guide/index.html", "<a href=\"../latest/std/index.html#vec\">a</a>\
+                                  <a href=\"./../latest/\">b</a>"),
+            ("1.75.0/index.html", ""),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        assert_eq!(checker.errors().len(), 4);
+
+        let rewrite = Rewrite::parse("s#^latest/#1.75.0/#").unwrap();
+        let options = Options { rewrites: vec![rewrite], ..Options::default() };
+        let checker = check_in_memory(&files, options);
+        let errors = messages(&checker);
+        assert_eq!(errors, ["index.html:2:10: broken link - 1.75.0/std/gone.html"]);
+    }
+
+    #[test]
+    fn resolved_links() {
+        let files = [
+            ("index.html", "<a href=\"a.html#x\">a</a><a href=\"a.html#x\">again</a>\n\
+                            <a href=\"a.html#nope\">b</a><a href=\"gone.html\">c</a>\n\
+                            <link rel=\"stylesheet\" href=\"main.css\">\n\
+                            <a href=\"http://example.com\">d</a>"),
+            ("a.html", "<p id=\"x\"></p><a href=\"index.html\">up</a>"),
+            ("main.css", ""),
+        ];
+        let link = |source: &str, target: &str, fragment: Option<&str>| {
+            ResolvedLink {
+                source: PathBuf::from(source),
+                target: PathBuf::from(target),
+                fragment: fragment.map(|f| f.to_string()),
+            }
+        };
+        let expected = [
+            link("a.html", "index.html", None),
+            link("index.html", "a.html", Some("x")),
+            link("index.html", "main.css", None),
+        ];
+        for &jobs in &[1, 2] {
+            let options = Options { record_links: true, jobs, ..Options::default() };
+            let checker = check_in_memory(&files, options);
+            assert_eq!(checker.resolved_links().iter().cloned().collect::<Vec<_>>(), expected);
+        }
+        let checker = check_in_memory(&files, Options::default());
+        assert!(checker.resolved_links().is_empty());
+    }
+
+    #[test]
+    fn bare_directory_links_are_errors() {
+        let tree = Tree::new("bare-dir", &[
+            ("index.html", "<a href=\"foo\">foo</a>"),
+            ("foo/index.html", ""),
+        ]);
+        assert!(tree.check(Options::default()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_files_are_checked_once() {
+        use std::os::unix::fs::symlink;
+
+        let tree = Tree::new("symlink-file", &[("index.html", "<a href=\"alias.html\">a</a>")]);
+        symlink(tree.0.join("index.html"), tree.0.join("alias.html")).unwrap();
+        let checker = tree.checker(Options::default());
+        assert!(!checker.has_errors());
+        assert_eq!(checker.stats().files_checked, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycles_are_reported() {
+        use std::os::unix::fs::symlink;
+
+        let tree = Tree::new("symlink-cycle", &[("a/index.html", "")]);
+        symlink(&tree.0, tree.0.join("a/loop")).unwrap();
+        let checker = tree.checker(Options::default());
+        assert!(!checker.has_errors());
+        assert_eq!(checker.stats().warnings, 1);
+        assert_eq!(checker.errors()[0].kind, ErrorKind::SymlinkCycle);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_can_be_skipped() {
+        use std::os::unix::fs::symlink;
+
+        let tree = Tree::new("symlink-skip", &[("index.html", "")]);
+        let shared = Tree::new("symlink-skip-shared", &[
+            ("broken.html", "<a href=\"x.html\">x</a>"),
+        ]);
+        symlink(&shared.0, tree.0.join("shared")).unwrap();
+        assert!(tree.check(Options::default()));
+        assert!(!tree.check(Options { no_follow_symlinks: true, ..Options::default() }));
+    }
+
+    #[test]
+    fn check_single_file() {
+        let tree = Tree::new("single-file", &[
+            ("a/index.html", "<a href=\"../b.html#x\">b</a>"),
+            ("a/broken.html", "<a href=\"missing.html\">b</a>"),
+            ("b.html", "<p id=\"x\"></p>"),
+        ]);
+        let mut checker = Checker::new(&tree.0, Options::default());
+        checker.check_file(&tree.0.join("a/index.html")).unwrap();
+        assert!(!checker.has_errors());
+        assert_eq!(checker.stats().files_checked, 1);
+
+        checker.check_file(&tree.0.join("a/broken.html")).unwrap();
+        assert!(checker.has_errors());
+    }
+
+    #[test]
+    fn lint_empty_attributes() {
+        let tree = Tree::new("lint-structure", &[
+            ("index.html", "<a href=\"\">here</a>\n<p id=\"\"></p>\n<a href=\"#x\" id=\"x\">x</a>"),
+        ]);
+        let checker = tree.checker(Options::default());
+        assert_eq!(checker.stats().warnings, 1);
+
+        let checker = tree.checker(Options { lint_structure: true, ..Options::default() });
+        assert!(!checker.has_errors());
+        let lints = checker.errors()
+                           .iter()
+                           .map(|e| (e.kind, e.line, &e.target[..]))
+                           .collect::<Vec<_>>();
+        assert_eq!(lints, [(ErrorKind::EmptyAttribute, 2, "id"),
+                           (ErrorKind::EmptyAttribute, 1, "href")]);
+    }
+
+    #[test]
+    fn empty_links() {
+        let checker = check_in_memory(&[
+            ("index.html", "<a href=\"\">here</a> <img src=\"\">\n\
+                            <a href=\"#\">top</a> <a href=\"#x\" id=\"x\">x</a> \
+                            <img src=\"logo.png\">"),
+        ], Options::default());
+        assert!(!checker.has_errors());
+        let errors = messages(&checker);
+        assert_eq!(errors, ["index.html:1:10: warning: empty `href` attribute",
+                            "index.html:1:31: warning: empty `src` attribute"]);
+    }
+
+    #[test]
+    fn query_only_links_warn() {
+        let tree = Tree::new("query-only", &[
+            ("index.html", "<a href=\"?search=foo\">search</a><a href=\"index.html?x\">x</a>"),
+        ]);
+        let checker = tree.checker(Options::default());
+        assert!(!checker.has_errors());
+        assert_eq!(checker.stats().warnings, 1);
+        assert_eq!(checker.errors()[0].kind, ErrorKind::QueryOnlyLink);
+        assert_eq!(checker.errors()[0].target, "?search=foo");
+
+        let options = Options { allow: vec![ErrorKind::QueryOnlyLink], ..Options::default() };
+        assert_eq!(tree.checker(options).stats().warnings, 0);
+    }
+
+    #[test]
+    fn skipped_schemes() {
+        let files = [
+            ("index.html", "<a href=\"rust:std::vec::Vec\">a</a><a href=\"Primitive:str\">b</a>\n\
+                            <a href=\"rusty.html\">c</a>"),
+        ];
+        assert_eq!(check_in_memory(&files, Options::default()).errors().len(), 3);
+
+        let options = Options {
+            skip_schemes: vec!["rust".to_string(), "primitive".to_string()],
+            ..Options::default()
+        };
+        let checker = check_in_memory(&files, options);
+        assert_eq!(checker.stats().external_skipped, 2);
+        let errors = checker.errors().iter().map(|e| &e.target[..]).collect::<Vec<_>>();
+        assert_eq!(errors, ["rusty.html"]);
+    }
+
+    #[test]
+    fn absolute_paths_are_reported() {
+        let files = [
+            ("index.html", "<a href=\"/std/index.html\">a</a><a href=\"//example.com/\">b</a>"),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        let errors = messages(&checker);
+        assert_eq!(errors, ["index.html:1:10: can't resolve link offline - /std/index.html"]);
+        assert_eq!(checker.stats().external_skipped, 1);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn drive_paths_are_reported() {
+        let files = [("index.html", "<a href=\"C:/docs/index.html\">a</a>")];
+        let checker = check_in_memory(&files, Options::default());
+        let errors = checker.errors().iter().map(|e| e.kind).collect::<Vec<_>>();
+        assert_eq!(errors, [ErrorKind::UnresolvableLink]);
+    }
+
+    #[test]
+    fn external_links_are_listed() {
+        let files = [
+            ("index.html", "<a href=\"https://example.com/a#x\">a</a>\n\
+                            <a href=\"https://example.com/a\">a</a><a href=\"a.html\">a</a>\n\
+                            <a href=\"ftp://example.org/\">f</a><a href=\"javascript:x()\">j</a>"),
+            ("a.html", ""),
+        ];
+        assert!(check_in_memory(&files, Options::default()).stats().external_urls.is_empty());
+
+        let options = Options { list_external: true, ..Options::default() };
+        let checker = check_in_memory(&files, options);
+        let urls = checker.stats().external_urls.iter().map(|u| &u[..]).collect::<Vec<_>>();
+        assert_eq!(urls, ["ftp://example.org/", "https://example.com/a"]);
+        assert_eq!(checker.stats().external_skipped, 4);
+    }
+
+    #[test]
+    fn query_links_warn_when_asked() {
+        let files = [
+            ("index.html", "<a href=\"a.html?tab=x#f\">a</a><a href=\"gone.html?x\">g</a>\n\
+                            <a href=\"a.html#f\">f</a><a href=\"https://example.com/?q\">e</a>"),
+            ("a.html", "<h2 id=\"f\">f</h2>"),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        assert_eq!(checker.errors().len(), 1);
+
+        let options = Options { warn_query_links: true, ..Options::default() };
+        let checker = check_in_memory(&files, options);
+        let errors = checker.errors().iter()
+            .map(|e| (e.kind, &e.target[..]))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [(ErrorKind::QueryLink, "a.html?tab=x#f"),
+                            (ErrorKind::QueryLink, "gone.html?x"),
+                            (ErrorKind::BrokenLink, "gone.html")]);
+    }
+
+    #[test]
+    fn cached_external_links_are_not_fetched() {
+        let tree = Tree::new("external-cache", &[
+            ("index.html", "<a href=\"https://example.com/#top\">a</a>\
+                            <a href=\"https://example.com/\">b</a>"),
+            ("cache.json", ""),
+        ]);
+        let cache_file = tree.0.join("cache.json");
+        let mut cache = external::Cache::load(&cache_file, 100);
+        cache.insert("https://example.com/", external::now());
+        cache.save(external::now()).unwrap();
+
+        let options = Options {
+            check_external: true,
+            external_cache: Some(cache_file),
+            external_cache_ttl: 100,
+            ..Options::default()
+        };
+        let mut checker = tree.checker(options);
+        checker.check_external();
+        assert!(!checker.has_errors());
+        assert_eq!(checker.stats().external_checked, 2);
+        assert_eq!(checker.stats().external_skipped, 0);
+    }
+
+    #[test]
+    fn id_index() {
+        let files = [
+            ("index.html", "<h1 id=\"b\">b</h1><h2 id=\"a\">a</h2><a href=\"std/vec.html\">v</a>"),
+            ("std/vec.html", "<h1 id=\"new\">new</h1>"),
+            ("src/lib.rs.html", "<span id=\"1\">1</span>"),
+        ];
+        let tree = Tree::new("id-index", &files);
+        let index = |jobs| {
+            let options = Options {
+                exclude_fragments_for: vec![Pattern::new("src/**").unwrap()],
+                jobs,
+                ..Options::default()
+            };
+            tree.checker(options).id_index()
+        };
+        let expected = [
+            (PathBuf::from("index.html"), vec!["a".to_string(), "b".to_string()]),
+            (PathBuf::from("std/vec.html"), vec!["new".to_string()]),
+        ];
+        assert_eq!(index(1).into_iter().collect::<Vec<_>>(), expected);
+        assert_eq!(index(2).into_iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn parallel_walks_are_deterministic() {
+        let pages = (0..40).map(|i| {
+            (format!("{}/page{}.html", i % 4, i),
+             format!("<h1 id=\"a\">{}</h1><h2 id=\"a\">{}</h2>\n\
+                      <a href=\"../{}/page{}.html#b\">x</a><a href=\"gone{}.html\">y</a>",
+                     i, i, (i + 1) % 4, (i + 1) % 40, i % 3))
+        }).collect::<Vec<_>>();
+        let files = pages.iter().map(|(path, page)| (&path[..], &page[..]))
+                                .collect::<Vec<_>>();
+        let tree = Tree::new("parallel", &files);
+        let errors = |jobs| {
+            let checker = tree.checker(Options { jobs, ..Options::default() });
+            assert_eq!(checker.stats().files_checked, 40);
+            assert_eq!(checker.stats().links_checked, 80);
+            assert_eq!(checker.stats().errors, checker.errors().len());
+            messages(&checker)
+        };
+        let first = errors(4);
+        assert_eq!(first.len(), 120);
+        let mut sorted = first.clone();
+        sorted.sort_by_key(|e| {
+            let mut parts = e.splitn(3, ':');
+            (parts.next().unwrap().to_string(), parts.next().unwrap().parse::<usize>().unwrap())
+        });
+        assert_eq!(first, sorted);
+        assert_eq!(errors(4), first);
+        let mut sequential = errors(1);
+        sequential.sort();
+        let mut parallel = first;
+        parallel.sort();
+        assert_eq!(sequential, parallel);
+    }
+
+    #[test]
+    fn duplicate_ids_are_sorted_with_links() {
+        // `b.html` is read for its ids while `a.html` is checked.
+        let tree = Tree::new("sorted-ids", &[
+            ("a.html", "<a href=\"b.html#x\">x</a>\n<a href=\"gone.html\">g</a>"),
+            ("b.html", "<a href=\"gone.html\">g</a>\n<p id=\"x\"><p id=\"x\">"),
+        ]);
+        let errors = |jobs| {
+            let checker = tree.checker(Options { jobs, ..Options::default() });
+            messages(&checker)
+        };
+        let expected = [
+            "a.html:2:10: broken link - gone.html",
+            "b.html:1:10: broken link - gone.html",
+            "b.html:2:18: id is not unique: `x`",
+        ];
+        assert_eq!(errors(2), expected);
+        assert_eq!(errors(3), expected);
+        // A single thread reports them as they're found, in the order the
+        // directory lists the pages.
+        let mut sequential = errors(1);
+        sequential.sort();
+        assert_eq!(sequential, expected);
+        let checker = tree.checker(Options { jobs: 2, cross_file_ids: true, ..Options::default() });
+        assert_eq!(messages(&checker), expected);
+    }
+
+    #[test]
+    fn parallel_walks_load_link_targets_once() {
+        /// Counts how often `/docs/target.html` is read, taking long enough
+        /// for every thread to get to a page in the meantime.
+        struct Counting(MemoryFs, Arc<AtomicUsize>);
+
+        impl FileSystem for Counting {
+            fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+                if path == Path::new("/docs/target.html") {
+                    self.1.fetch_add(1, Ordering::SeqCst);
+                }
+                thread::sleep(Duration::from_millis(10));
+                self.0.read(path)
+            }
+
+            fn is_dir(&self, path: &Path) -> io::Result<bool> {
+                self.0.is_dir(path)
+            }
+
+            fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<::vfs::DirEntry>>> {
+                self.0.read_dir(path)
+            }
+
+            fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+                self.0.canonicalize(path)
+            }
+        }
+
+        let mut fs = MemoryFs::new();
+        fs.add_file(Path::new("/docs/target.html"),
+                    b"<p id=\"x\"><p id=\"x\"><a href=\"gone.html\">g</a>");
+        fs.add_file(Path::new("/docs/stub.html"),
+                    b"<!DOCTYPE html>\n<html>\n<head>\n\
+                      <meta http-equiv=\"refresh\" content=\"0;URL=target.html\">\n\
+                      </head>\n<body>\n\
+                      <p>Redirecting to <a href=\"target.html\">target.html</a>...</p>\n\
+                      </body>\n</html>\n");
+        for i in 0..40 {
+            let page = format!("<a href=\"target.html#x\">x</a><a href=\"stub.html#{}\">y</a>",
+                               if i == 0 { "gone" } else { "x" });
+            fs.add_file(&Path::new("/docs").join(format!("page{}.html", i)), page.as_bytes());
+        }
+        let reads = Arc::new(AtomicUsize::new(0));
+        let options = Options { jobs: 4, ..Options::default() };
+        let fs = Box::new(Counting(fs, reads.clone()));
+        let mut checker = Checker::with_file_system(Path::new("/docs"), options, fs);
+        checker.walk(Path::new("/docs")).unwrap();
+        // At most once when it's checked itself, and once for the links into
+        // it, also through the redirect, whichever threads they're on.
+        assert!(reads.load(Ordering::SeqCst) <= 2);
+        assert_eq!(messages(&checker), [
+            "page0.html:1:39: broken link fragment `#gone` pointing to `target.html`",
+            "target.html:1:18: id is not unique: `x`",
+            "target.html:1:30: broken link - gone.html",
+        ]);
+    }
+
+    #[test]
+    fn cached_ids_are_used_for_unchanged_pages() {
+        let tree = Tree::new("id-cache", &[
+            ("index.html", "<a href=\"a.html#x\">a</a><a href=\"b.html#cached\">b</a>"),
+            ("a.html", "<h1 id=\"x\">A</h1>\n<h2 id=\"x\">A</h2>"),
+            ("b.html", "<h1 id=\"b\">B</h1>"),
+            ("ids.json", ""),
+        ]);
+        let cache_file = tree.0.join("ids.json");
+        let b = Path::new("b.html");
+        let mut cache = IdCache::load(&cache_file);
+        cache.insert(b, id_cache::stamp("<h1 id=\"b\">B</h1>"), vec!["cached".to_string()],
+                     Vec::new());
+        cache.insert(Path::new("gone.html"), (0, 0), Vec::new(), Vec::new());
+        cache.save().unwrap();
+
+        let options = Options { id_cache: Some(cache_file.clone()), ..Options::default() };
+        let expected = ["a.html:2:9: id is not unique: `x`"];
+        assert_eq!(messages(&tree.checker(options.clone())), expected);
+        let mut saved = String::new();
+        File::open(&cache_file).unwrap().read_to_string(&mut saved).unwrap();
+        assert!(saved.contains("a.html") && !saved.contains("gone.html"));
+        // The duplicate is reported again from the cache.
+        assert_eq!(messages(&tree.checker(options.clone())), expected);
+
+        // Rewritten to the same size, which the modification time might not
+        // tell either.
+        File::create(tree.0.join(b)).unwrap().write_all(b"<h1 id=\"c\">B</h1>").unwrap();
+        assert_eq!(messages(&tree.checker(options)),
+                   [expected[0], "index.html:1:34: broken link fragment `#cached` pointing to \
+                                  `b.html`"]);
+    }
+
+    #[test]
+    fn external_links_are_tallied_by_host() {
+        let tree = Tree::new("external-hosts", &[
+            ("index.html", "<a href=\"https://a.com/x\">1</a><a href=\"https://a.com/x\">2</a>\
+                            <a href=\"https://A.com/gone\">3</a><a href=\"http://b.org/\">4</a>"),
+        ]);
+        let mut checker = tree.checker(Options { check_external: true, ..Options::default() });
+        checker.check_external_with(|url| {
+            if url.ends_with("gone") {
+                external::Outcome::Failed("HTTP 404".to_string())
+            } else {
+                external::Outcome::Ok
+            }
+        });
+        let hosts = checker.stats().external_hosts.iter()
+            .map(|(host, stats)| (&host[..], stats.links, stats.failed))
+            .collect::<Vec<_>>();
+        assert_eq!(hosts, [("a.com", 3, 1), ("b.org", 1, 0)]);
+    }
+
+    #[test]
+    fn links_on_one_line_have_distinct_columns() {
+        let tree = Tree::new("columns", &[
+            ("index.html", "<p><a href=\"a.html\">a</a> <a href='b.html'>b</a></p>"),
+        ]);
+        let checker = tree.checker(Options::default());
+        let errors = checker.errors();
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].line, errors[0].column), (1, 13));
+        assert_eq!((errors[1].line, errors[1].column), (1, 36));
+        assert_eq!(errors[0].to_string(), "index.html:1:13: broken link - a.html");
+        assert_eq!(errors[1].to_string(), "index.html:1:36: broken link - b.html");
+    }
+
+    #[test]
+    fn fragments_resolve_through_redirects() {
+        let stub = "<!DOCTYPE html>\n<html>\n<head>\n\
+                    <meta http-equiv=\"refresh\" content=\"0;URL=target.html\">\n\
+                    </head>\n<body>\n\
+                    <p>Redirecting to <a href=\"target.html\">target.html</a>...</p>\n\
+                    </body>\n</html>\n";
+        let tree = Tree::new("redirect-fragment", &[
+            ("a.html", "<a href=\"stub.html#foo\">a</a>\n\
+                        <a href=\"stub.html#foo\">again</a>\n\
+                        <a href=\"stub.html#nope\">missing</a>"),
+            ("b.html", "<a href=\"target.html#foo\">b</a><a href=\"stub.html#foo\">b</a>"),
+            ("stub.html", stub),
+            ("target.html", "<h1 id=\"foo\">Foo</h1>"),
+        ]);
+        let checker = tree.checker(Options::default());
+        let errors = checker.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::BrokenFragment);
+        assert_eq!(errors[0].file, PathBuf::from("a.html"));
+        assert_eq!(errors[0].line, 3);
+        assert_eq!(errors[0].target, "target.html");
+        assert_eq!(errors[0].fragment, Some("nope".to_string()));
+    }
+
+    #[test]
+    fn malformed_redirect_stubs() {
+        let stub = |line: &str| format!("\n\n\n\n\n\n{}\n", line);
+        assert_eq!(maybe_redirect(&stub("<p>Redirecting to <a href=\"a.html#b\">")),
+                   Some(("a.html".to_string(), Some("b".to_string()))));
+        assert_eq!(maybe_redirect(&stub("<p>Redirecting to <a href=\"a.html")), None);
+        assert_eq!(maybe_redirect(&stub("<p>Redirecting to <a href=")), None);
+        assert_eq!(maybe_redirect("<p>Redirecting to <a href=\"a.html\">"), None);
+    }
+
+    #[test]
+    fn redirects_to_fragments() {
+        let stub = |url: &str| {
+            format!("<!DOCTYPE html>\n<html>\n<head>\n\
+                     <meta http-equiv=\"refresh\" content=\"0;URL={0}\">\n\
+                     </head>\n<body>\n\
+                     <p>Redirecting to <a href=\"{0}\">{0}</a>...</p>\n\
+                     </body>\n</html>\n", url)
+        };
+        let good = stub("target.html#foo");
+        let bad = stub("target.html#nope");
+        let tree = Tree::new("redirect-to-fragment", &[
+            ("index.html", "<a href=\"good.html\">a</a>\n\
+                            <a href=\"good.html#nope\">b</a>\n\
+                            <a href=\"bad.html\">c</a>\n\
+                            <a href=\"bad.html#foo\">d</a>"),
+            ("good.html", &good),
+            ("bad.html", &bad),
+            ("target.html", "<h1 id=\"foo\">Foo</h1>"),
+        ]);
+        let checker = tree.checker(Options::default());
+        let errors = checker.errors();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].to_string(),
+                   "index.html:3:10: broken link fragment `#nope` pointing to `target.html` \
+                    (redirected to by bad.html)");
+        assert_eq!(errors[1].line, 4);
+        assert_eq!(errors[1].fragment, Some("nope".to_string()));
+    }
+
+    #[test]
+    fn redirects_as_errors() {
+        let stub = "<!DOCTYPE html>\n<html>\n<head>\n\
+                    <meta http-equiv=\"refresh\" content=\"0;URL=target.html\">\n\
+                    </head>\n<body>\n\
+                    <p>Redirecting to <a href=\"target.html\">target.html</a>...</p>\n\
+                    </body>\n</html>\n";
+        let tree = Tree::new("redirect-policy", &[
+            ("index.html", "<a href=\"target.html\">a</a>\n<a href=\"stub.html#foo\">b</a>"),
+            ("stub.html", stub),
+            ("target.html", "<h1 id=\"foo\">Foo</h1>"),
+        ]);
+        assert!(!tree.check(Options::default()));
+
+        let checker = tree.checker(Options {
+            treat_redirect_as_error: true,
+            ..Options::default()
+        });
+        let errors = checker.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::RedirectedLink);
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(errors[0].message(), "link resolves via redirect: stub.html -> target.html");
+    }
+
+    #[test]
+    fn redirect_chains_and_loops() {
+        let stub = |target: &str| {
+            format!("<!DOCTYPE html>\n<html>\n<head>\n\
+                     <meta http-equiv=\"refresh\" content=\"0;URL={0}\">\n\
+                     </head>\n<body>\n\
+                     <p>Redirecting to <a href=\"{0}\">{0}</a>...</p>\n\
+                     </body>\n</html>\n", target)
+        };
+        let (a, b, c, d) = (stub("b.html"), stub("target.html"), stub("d.html"), stub("c.html"));
+        let tree = Tree::new("redirect-chains", &[
+            ("index.html", "<a href=\"b.html\">one</a>\n<a href=\"a.html\">two</a>\n\
+                            <a href=\"c.html\">loop</a>"),
+            ("a.html", &a),
+            ("b.html", &b),
+            ("c.html", &c),
+            ("d.html", &d),
+            ("target.html", ""),
+        ]);
+        let checker = tree.checker(Options::default());
+        let errors = checker.errors().iter()
+            .map(|e| (e.kind, e.line, e.message()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [
+            (ErrorKind::RedirectChain, 2,
+             "link resolves via a chain of redirects: a.html -> b.html -> target.html"
+                 .to_string()),
+            (ErrorKind::BrokenRedirect, 3,
+             "broken redirect to c.html (redirect loop)".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn canonical_links() {
+        let tree = Tree::new("canonical", &[
+            ("good.html", "<link rel=\"canonical\" href=\"https://docs.rs/foo/good.html\">"),
+            ("local.html", "<link rel=\"stylesheet\" href=\"style.css\">\n\
+                            <link href=\"good.html\" rel=\"canonical\" />"),
+            ("style.css", ""),
+            ("bad.html", "<link rel=\"canonical\" href=\"https:///bad.html\">\n\
+                          <link rel=\"canonical\" href=\"ftp://host/bad.html\">"),
+        ]);
+        assert!(!tree.check(Options::default()));
+
+        let checker = tree.checker(Options { check_canonical: true, ..Options::default() });
+        let mut errors = messages(&checker);
+        errors.sort();
+        assert_eq!(errors, [
+            "bad.html:1:1: broken canonical link - https:///bad.html (no host)",
+            "bad.html:2:1: broken canonical link - ftp://host/bad.html (unsupported scheme)",
+        ]);
+    }
+
+    #[test]
+    fn orphaned_pages() {
+        let stub = "<!DOCTYPE html>\n<html>\n<head>\n\
+                    <meta http-equiv=\"refresh\" content=\"0;URL=target.html\">\n\
+                    </head>\n<body>\n\
+                    <p>Redirecting to <a href=\"target.html\">target.html</a>...</p>\n\
+                    </body>\n</html>\n";
+        let tree = Tree::new("orphans", &[
+            ("index.html", "<a href=\"a.html\">a</a><a href=\"stub.html\">stub</a>"),
+            ("a.html", "<a id=\"top\" href=\"#top\">top</a><a href=\"a.html\">self</a>"),
+            ("orphan.html", "<a href=\"index.html\">home</a>"),
+            ("sub/index.html", ""),
+            ("stub.html", stub),
+            ("target.html", ""),
+        ]);
+        let mut checker = tree.checker(Options { report_orphans: true, ..Options::default() });
+        checker.report_orphans();
+        let errors = messages(&checker);
+        assert_eq!(errors, ["orphan.html: warning: no other page links here"]);
+        assert!(!checker.has_errors());
+    }
+
+    #[test]
+    fn static_root_assets() {
+        let tree = Tree::new("static-root", &[
+            ("docs/foo/index.html", "<link rel=\"stylesheet\" href=\"../rustdoc.css\">"),
+            ("docs/bar/index.html", "<link rel=\"stylesheet\" href=\"/static/main.css\">"),
+            ("static/rustdoc.css", ""),
+            ("static/main.css", ""),
+        ]);
+        let docs = tree.0.join("docs");
+        let mut checker = Checker::new(&docs, Options::default());
+        checker.check_file(&docs.join("foo/index.html")).unwrap();
+        assert_eq!(checker.errors()[0].target, "rustdoc.css");
+
+        let options = Options { static_root: Some(tree.0.join("static")), ..Options::default() };
+        let mut checker = Checker::new(&docs, options);
+        checker.walk(&docs).unwrap();
+        assert!(!checker.has_errors());
+    }
+
+    #[test]
+    fn redirects_outside_of_the_root() {
+        let stub = "<!DOCTYPE html>\n<html>\n<head>\n\
+                    <meta http-equiv=\"refresh\" content=\"0;URL=../../../../etc/passwd\">\n\
+                    </head>\n<body>\n\
+                    <p>Redirecting to <a href=\"../../../../etc/passwd\">passwd</a>...</p>\n\
+                    </body>\n</html>\n";
+        let mut fs = MemoryFs::new();
+        fs.add_file(Path::new("/docs/index.html"), b"<a href=\"std/stub.html\">x</a>");
+        fs.add_file(Path::new("/docs/std/stub.html"), stub.as_bytes());
+        fs.add_file(Path::new("/etc/passwd"), b"root:x:0:0");
+        let mut checker = Checker::with_file_system(Path::new("/docs/"), Options::default(),
+                                                    Box::new(fs));
+        checker.walk(Path::new("/docs")).unwrap();
+        let errors = messages(&checker);
+        assert_eq!(errors, ["index.html:1:10: broken redirect to /etc/passwd \
+                             (outside of the docs root)"]);
+        match checker.ids_for(Path::new("std/stub.html")) {
+            Err(LoadError::RedirectOutsideRoot(target)) => {
+                assert_eq!(target, Path::new("/etc/passwd"));
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn unreadable_dirs() {
+        /// Fails to list `/docs/private`, and one of the entries of `/docs/half`.
+        struct Unreadable(MemoryFs);
+
+        impl FileSystem for Unreadable {
+            fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+                self.0.read(path)
+            }
+
+            fn is_dir(&self, path: &Path) -> io::Result<bool> {
+                self.0.is_dir(path)
+            }
+
+            fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<::vfs::DirEntry>>> {
+                if path == Path::new("/docs/private") {
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+                }
+                let mut entries = self.0.read_dir(path)?;
+                if path == Path::new("/docs/half") {
+                    entries.insert(0, Err(io::Error::other("stale")));
+                }
+                Ok(entries)
+            }
+
+            fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+                self.0.canonicalize(path)
+            }
+        }
+
+        let checker = |strict_io| {
+            let mut fs = MemoryFs::new();
+            fs.add_file(Path::new("/docs/a.html"), b"<a href=\"gone.html\">x</a>");
+            fs.add_file(Path::new("/docs/private/b.html"), b"");
+            fs.add_file(Path::new("/docs/half/c.html"), b"<a href=\"gone.html\">x</a>");
+            fs.add_file(Path::new("/docs/z.html"), b"<a href=\"gone.html\">x</a>");
+            let options = Options { strict_io, ..Options::default() };
+            let mut checker = Checker::with_file_system(Path::new("/docs"), options,
+                                                        Box::new(Unreadable(fs)));
+            checker.walk(Path::new("/docs")).unwrap();
+            messages(&checker)
+        };
+        // The rest of a directory with an unreadable entry is still checked.
+        assert_eq!(checker(false), [
+            "a.html:1:10: broken link - gone.html",
+            "private: warning: cannot read directory - private (denied)",
+            "half: warning: cannot read directory - half (stale)",
+            "half/c.html:1:10: broken link - half/gone.html",
+            "z.html:1:10: broken link - gone.html",
+        ]);
+        assert_eq!(checker(true), [
+            "a.html:1:10: broken link - gone.html",
+            "private: cannot read directory - private (denied)",
+        ]);
+    }
+
+    #[test]
+    fn streamed_pages() {
+        // More than one chunk, with links up and down the page.
+        let mut big = String::new();
+        for n in 0..40_000 {
+            big.push_str(&format!("<p id=\"p{}\"><a href=\"#p{}\">next</a></p>\n", n, n + 1));
+        }
+        big.push_str("<p id=\"p7\"><a href=\"gone.html\">g</a> <a href=\"sub/a.html#x\">a</a>");
+        let tree = Tree::new("streamed", &[("big.html", &big), ("sub/a.html", "<p id=\"y\">")]);
+        let errors = |stream_threshold| {
+            let options = Options { stream_threshold, ..Options::default() };
+            messages(&tree.checker(options))
+        };
+        let expected = [
+            "big.html:40001:8: id is not unique: `p7`",
+            "big.html:40000:25: broken link fragment `#p40000` pointing to `big.html`",
+            "big.html:40001:21: broken link - gone.html",
+            "big.html:40001:47: broken link fragment `#x` pointing to `sub/a.html`",
+        ];
+        assert_eq!(errors(None), expected);
+        assert_eq!(errors(Some(1024)), expected);
+
+        // A page on a single line is read in chunks too, which don't cut its
+        // tags, and its `<base>` only applies to the links after it. Links
+        // into it only need its ids.
+        let mut minified = String::new();
+        for n in 0..40_000 {
+            minified.push_str(&format!("<p id=\"p{}\"><a href=\"#p{}\">next</a></p>", n, n + 1));
+        }
+        minified.push_str("<a href=\"a.html\">a</a><base href=\"sub/\"><a href=\"a.html#x\">a</a>");
+        let files = [
+            ("big.html", &minified[..]),
+            ("index.html", "<a href=\"big.html#p9\">9</a> <a href=\"big.html#q\">q</a>"),
+            ("sub/a.html", "<p id=\"y\">"),
+        ];
+        let errors = |stream_threshold| {
+            let options = Options { stream_threshold, ..Options::default() };
+            let checker = check_in_memory(&files, options);
+            messages(&checker)
+        };
+        let expected = [
+            "big.html:1:1777764: broken link fragment `#p40000` pointing to `big.html`",
+            "big.html:1:1777794: broken link - a.html",
+            "big.html:1:1777834: broken link fragment `#x` pointing to `sub/a.html`",
+            "index.html:1:38: broken link fragment `#q` pointing to `big.html`",
+        ];
+        assert_eq!(errors(None), expected);
+        assert_eq!(errors(Some(1024)), expected);
+    }
+
+    #[test]
+    fn forgotten_files() {
+        let tree = Tree::new("forgotten", &[("index.html", "<a href=\"a.html#x\">a</a>")]);
+        let index = tree.0.join("index.html");
+        let options = Options { record_links: true, ..Options::default() };
+        let mut checker = Checker::new(&tree.0, options);
+        checker.check_file(&index).unwrap();
+        assert_eq!(checker.errors()[0].kind, ErrorKind::BrokenLink);
+        let recheck = |checker: &mut Checker| {
+            let linking = checker.forget(Path::new("a.html"));
+            assert_eq!(linking, [PathBuf::from("index.html")]);
+            for page in linking {
+                checker.forget(&page);
+                checker.check_file(&tree.0.join(page)).unwrap();
+            }
+            checker.errors().iter().map(|e| e.kind).collect::<Vec<_>>()
+        };
+
+        // Once `a.html` is written, the link works, and the problem is gone.
+        File::create(tree.0.join("a.html")).unwrap().write_all(b"<p id=\"x\">").unwrap();
+        assert_eq!(recheck(&mut checker), []);
+        assert!(!checker.has_errors());
+
+        // The link resolved, so it's known to lead to `a.html`.
+        File::create(tree.0.join("a.html")).unwrap().write_all(b"<p id=\"y\">").unwrap();
+        assert_eq!(recheck(&mut checker), [ErrorKind::BrokenFragment]);
+
+        fs::remove_file(tree.0.join("a.html")).unwrap();
+        assert_eq!(recheck(&mut checker), [ErrorKind::BrokenLink]);
+        assert_eq!(checker.stats().errors, 1);
+    }
+
+    #[test]
+    fn load_errors() {
+        let stub = "<!DOCTYPE html>\n<html>\n<head>\n\
+                    <meta http-equiv=\"refresh\" content=\"0;URL=gone.html\">\n\
+                    </head>\n<body>\n\
+                    <p>Redirecting to <a href=\"gone.html\">gone.html</a>...</p>\n\
+                    </body>\n</html>\n";
+        let tree = Tree::new("load-errors", &[
+            ("index.html", "<a href=\"stub.html\">b</a>"),
+            ("stub.html", stub),
+        ]);
+        // Opening a directory may work, but reading it doesn't.
+        fs::create_dir(tree.0.join("dir.html")).unwrap();
+
+        let mut checker = Checker::new(&tree.0, Options::default());
+        match checker.check_file(&tree.0.join("stub.html")) {
+            Err(LoadError::IsRedirect) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+        assert!(!checker.has_errors());
+        match checker.check_file(&tree.0.join("dir.html")) {
+            Err(LoadError::IOError(_)) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+        match checker.ids_for(Path::new("stub.html")) {
+            Err(LoadError::BrokenRedirect(..)) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+        checker.check_file(&tree.0.join("index.html")).unwrap();
+
+        let errors = checker.errors().iter().map(|e| (e.kind, e.line)).collect::<Vec<_>>();
+        assert_eq!(errors, [(ErrorKind::UnreadableFile, 0), (ErrorKind::BrokenRedirect, 1)]);
+    }
+
+    #[test]
+    fn error_messages() {
+        let gone = || io::Error::new(io::ErrorKind::NotFound, "gone");
+        let broken = LoadError::BrokenRedirect(PathBuf::from("a.html"), gone());
+        assert_eq!(broken.to_string(), "broken redirect to a.html: gone");
+        assert_eq!(broken.source().unwrap().to_string(), "gone");
+        assert_eq!(LoadError::RedirectLoop(PathBuf::from("a.html")).to_string(),
+                   "redirect loop through a.html");
+        assert_eq!(LoadError::RedirectOutsideRoot(PathBuf::from("../a.html")).to_string(),
+                   "redirect to ../a.html outside of the docs root");
+        assert_eq!(LoadError::IsRedirect.to_string(), "the page is a redirect");
+        assert!(LoadError::IsRedirect.source().is_none());
+
+        let load = LinkCheckError::Load(PathBuf::from("b.html"), broken);
+        assert_eq!(load.to_string(), "failed to check 'b.html': broken redirect to a.html: gone");
+        assert_eq!(load.source().unwrap().to_string(), "broken redirect to a.html: gone");
+        assert_eq!(LinkCheckError::UnreadableDocs(PathBuf::from("doc"), gone()).to_string(),
+                   "failed to read 'doc': gone");
+        assert_eq!(LinkCheckError::WorkerPanicked.to_string(), "a thread checking pages panicked");
+    }
+
+    #[test]
+    fn run_reports() {
+        let stub = "<!DOCTYPE html>\n<html>\n<head>\n\
+                    <meta http-equiv=\"refresh\" content=\"0;URL=index.html\">\n\
+                    </head>\n<body>\n\
+                    <p>Redirecting to <a href=\"index.html\">index.html</a>...</p>\n\
+                    </body>\n</html>\n";
+        let tree = Tree::new("run-reports", &[
+            ("index.html", "<a href=\"gone.html\">a</a>"),
+            ("stub.html", stub),
+        ]);
+        let report = Checker::new(&tree.0, Options::default()).run(&tree.0).unwrap();
+        assert!(report.has_errors());
+        assert_eq!(report.errors.iter().map(|e| e.kind).collect::<Vec<_>>(),
+                   [ErrorKind::BrokenLink]);
+        assert_eq!(report.stats.files_checked, 1);
+
+        let mut checker = Checker::new(&tree.0, Options::default());
+        assert!(!checker.run(&tree.0.join("stub.html")).unwrap().has_errors());
+
+        match Checker::new(&tree.0, Options::default()).run(&tree.0.join("missing")) {
+            Err(LinkCheckError::UnreadableDocs(ref docs, _)) => {
+                assert_eq!(*docs, tree.0.join("missing"));
+            }
+            res => panic!("unexpected {:?}", res.map(|report| report.errors)),
+        }
+    }
+
+    #[test]
+    fn ignore_markers() {
+        let tree = Tree::new("ignore-markers", &[
+            ("index.html", "<!-- linkcheck:ignore-next -->\n\
+                            <a href=\"a.html\">a</a> <a href=\"b.html\">b</a>\n\
+                            <a href=\"c.html\">c</a><!-- linkcheck:ignore-next -->\
+                            <a href=\"d.html\">d</a>\n\
+                            <a href=\"examples.html#x\">x</a>\n\
+                            <!-- linkcheck:ignore-next -->"),
+            ("examples.html", "<!-- linkcheck:ignore-file -->\n\
+                               <h1 id=\"x\">X</h1><a href=\"nowhere.html\">nowhere</a>"),
+        ]);
+        let checker = tree.checker(Options::default());
+        let errors = checker.errors().iter().map(|e| e.target.clone()).collect::<Vec<_>>();
+        assert_eq!(errors, ["b.html", "c.html"]);
+    }
+
+    /// Checks a generated tree of 2000 pages linking to each other and prints
+    /// where the time went. Run with `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn bench_synthetic_tree() {
+        let pages = (0..2000).map(|i| {
+            let mut page = format!("<h1 id=\"top\">Page {}</h1>\n", i);
+            for j in 0..50 {
+                let target = (i * 31 + j * 7) % 2000;
+                page.push_str(&format!("<a href=\"../{}/page{}.html#top\">{}</a>\n",
+                                       target % 20, target, j));
+                page.push_str(&format!("<p id=\"item-{}\">item</p>\n", j));
+            }
+            (format!("{}/page{}.html", i % 20, i), page)
+        }).collect::<Vec<_>>();
+        let files = pages.iter().map(|(path, page)| (&path[..], &page[..]))
+                                .collect::<Vec<_>>();
+        let tree = Tree::new("bench", &files);
+        let checker = tree.checker(Options::default());
+        println!("{:?}", checker.stats());
+        println!("{}", checker.timings());
+        // The second run takes the ids from the cache written by the first.
+        let options = Options { id_cache: Some(tree.0.join("ids.json")), ..Options::default() };
+        for run in &["cold", "warm"] {
+            println!("{} id cache: {}", run, tree.checker(options.clone()).timings());
+        }
+    }
+
+    #[test]
+    fn bom_and_invalid_utf8() {
+        let tree = Tree::new("encodings", &[
+            ("index.html", "\u{feff}<a href=\"latin1.html#caf\u{e9}\">a</a>"),
+        ]);
+        File::create(tree.0.join("latin1.html")).unwrap()
+            .write_all(b"<h1 id=\"caf\xe9\">Caf\xe9</h1><a href=\"gone.html\">x</a>").unwrap();
+        let checker = tree.checker(Options::default());
+        let mut errors = messages(&checker);
+        errors.sort();
+        assert_eq!(errors, [
+            "index.html:1:10: broken link fragment `#caf\u{e9}` pointing to `latin1.html`",
+            "latin1.html: warning: not valid UTF-8, invalid bytes were replaced",
+            "latin1.html:1:37: broken link - gone.html",
+        ]);
+    }
+
+    #[test]
+    fn base_tags() {
+        let tree = Tree::new("base-tags", &[
+            ("a.html", "<h1 id=\"x\">A</h1>"),
+            ("other/index.html", "<h1 id=\"top\">Other</h1>"),
+            ("other/b.html", ""),
+            ("sub/deep/parent.html", "<base href=\"../../\"><a href=\"a.html#x\">a</a>"),
+            ("sub/page.html", "<base href=\"../other/index.html\">\n\
+                               <a href=\"b.html\">b</a><a href=\"#top\">top</a>"),
+            ("sub/deep/absolute.html", "<base href=\"/other/\"><a href=\"b.html\">b</a>"),
+            ("external.html", "<base href=\"https://example.com/\">\
+                               <a href=\"missing.html\">m</a>"),
+            ("sub/deep/broken.html", "<base href=\"../\"><a href=\"a.html\">a</a>"),
+        ]);
+        let checker = tree.checker(Options::default());
+        let errors = checker.errors().iter()
+            .map(|e| (e.kind, e.file.clone(), e.target.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [(ErrorKind::BrokenLink,
+                             PathBuf::from("sub/deep/broken.html"),
+                             "sub/a.html".to_string())]);
+        assert_eq!(checker.stats().external_skipped, 1);
+    }
+
+    #[test]
+    fn name_anchors() {
+        let tree = Tree::new("name-anchors", &[
+            ("index.html", "<a href=\"#old\">old</a> <a href=\"#both\">both</a>\n\
+                            <a name=\"old\"></a> <a id=\"both\" name=\"both\"></a>\n\
+                            <a name=\"twice\"></a><a name=\"twice\"></a>\n\
+                            <meta name=\"viewport\"><a href=\"#viewport\">v</a>\n"),
+        ]);
+        let checker = tree.checker(Options::default());
+        let errors = checker.errors().iter()
+            .map(|e| (e.kind, e.line, e.column))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [(ErrorKind::DuplicateId, 3, 21),
+                            (ErrorKind::BrokenFragment, 4, 32)]);
+    }
+
+    #[test]
+    fn id_attributes() {
+        let tree = Tree::new("id-attributes", &[
+            ("index.html", "<div data-id=\"data\" aria-hidden=\"true\" id=\"real\"></div>\n\
+                            <span aria-describedby=\"x\"\tid = 'tabbed'/><br idx=\"no\">\n\
+                            <p>Set id=\"prose\" in the config.</p><hr\n\
+                            id=\"continued\" class=\"x\"/><p id=\"a\"class=\"b\"/>\n"),
+        ]);
+        let mut checker = Checker::new(&tree.0, Options::default());
+        let mut ids = checker.ids_for(Path::new("index.html")).unwrap()
+            .iter().cloned().collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, ["a", "continued", "real", "tabbed"]);
+    }
+
+    #[test]
+    fn in_memory_trees() {
+        let files = [
+            ("index.html", "<a href=\"std/\">std</a>\n\
+                            <a href=\"std/fn.f.html#x\">f</a><a href=\"nope/\">x</a>"),
+            ("std/index.html", "<a href=\"../index.html\">up</a><a href=\"./fn.f.html\">f</a>"),
+            ("std/fn.f.html", "<h1 id=\"y\">f</h1><a href=\"../logo.png\">logo</a>"),
+            ("std/io/struct.Error.html", "<a href=\"../../index.html\">up</a>\n\
+                                          <a href=\"../fn.f.html#z\">f</a>\
+                                          <a href=\"../../logo.svg\">logo</a>"),
+            ("logo.png", "PNG"),
+        ];
+        let expected = [
+            "index.html:2:10: broken link fragment `#x` pointing to `std/fn.f.html`",
+            "index.html:2:41: broken link - nope",
+            "std/io/struct.Error.html:2:10: broken link fragment `#z` pointing to \
+             `std/fn.f.html`",
+            "std/io/struct.Error.html:2:40: broken link - logo.svg",
+        ];
+        for &jobs in &[1, 2] {
+            let checker = check_in_memory(&files, Options { jobs, ..Options::default() });
+            let mut errors = messages(&checker);
+            errors.sort();
+            assert_eq!(errors, expected);
+            assert_eq!(checker.stats().files_checked, 4);
+        }
+
+        // Walking a directory below the root still resolves links above it.
+        let root = Path::new("/docs");
+        let mut fs = MemoryFs::new();
+        for &(path, contents) in &files {
+            fs.add_file(&root.join(path), contents.as_bytes());
+        }
+        let mut checker = Checker::with_file_system(root, Options::default(), Box::new(fs));
+        checker.walk(&root.join("std")).unwrap();
+        assert_eq!(messages(&checker), &expected[2..]);
+        assert_eq!(checker.stats().files_checked, 3);
+    }
+
+    #[test]
+    fn placeholder_fragments() {
+        let files = [
+            ("index.html", "<a href=\"#\">menu</a><a href=\"#top\">up</a>\n\
+                            <a href=\"other.html#\">o</a><a href=\"other.html#Top\">o</a>"),
+            ("other.html", ""),
+        ];
+        assert!(!check_in_memory(&files, Options::default()).has_errors());
+
+        let checker = check_in_memory(&files, Options {
+            strict_fragments: true,
+            ..Options::default()
+        });
+        let errors = checker.errors().iter()
+            .map(|e| (e.kind, e.line, e.fragment.clone().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [(ErrorKind::BrokenFragment, 1, "top".to_string()),
+                            (ErrorKind::BrokenFragment, 2, "Top".to_string())]);
+    }
+
+    #[test]
+    fn fix_suggestions() {
+        assert_eq!(edit_distance("struct.Fo.html", "struct.Foo.html"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+
+        let files = [
+            ("index.html", "<a href=\"std/struct.Fo.html\">a</a>\n\
+                            <a href=\"std/fn.unrelated.html\">b</a>\n\
+                            <a href=\"nope/struct.Foo.html\">c</a>"),
+            ("std/struct.Foo.html", ""),
+            ("std/struct.Bar.html", ""),
+        ];
+        let checker = check_in_memory(&files, Options {
+            fix_suggestions: true,
+            ..Options::default()
+        });
+        let notes = checker.errors().iter().map(|e| e.note.clone()).collect::<Vec<_>>();
+        assert_eq!(notes, [Some("did you mean struct.Foo.html?".to_string()), None, None]);
+        assert_eq!(checker.errors()[0].message(),
+                   "broken link - std/struct.Fo.html (did you mean struct.Foo.html?)");
+    }
+
+    #[test]
+    fn fragments_on_neighbouring_pages() {
+        let files = [
+            ("book/ch1.html", "<a href=\"ch2.html#moved\">a</a>\n\
+                               <a href=\"ch2.html#nowhere\">b</a>\n\
+                               <a href=\"#moved\">c</a>"),
+            ("book/ch2.html", "<h1 id=\"ch2\">2</h1>"),
+            ("book/ch2a.html", "<h2 id=\"moved\">2a</h2>"),
+            ("book/ch3.html", "<h2 id=\"moved\">3</h2><p id=\"dup\"></p><p id=\"dup\"></p>"),
+            ("other/ch2.html", "<h2 id=\"nowhere\">elsewhere</h2>"),
+        ];
+        let checker = check_in_memory(&files, Options {
+            suggest_fragment_pages: true,
+            ..Options::default()
+        });
+        let errors = checker.errors().iter()
+            .map(|e| (e.line, e.kind, e.note.clone()))
+            .collect::<Vec<_>>();
+        let found = Some("found on book/ch2a.html, book/ch3.html".to_string());
+        assert_eq!(errors, [
+            (1, ErrorKind::BrokenFragment, found.clone()),
+            (2, ErrorKind::BrokenFragment, None),
+            (3, ErrorKind::BrokenFragment, found),
+            (1, ErrorKind::DuplicateId, None),
+        ]);
+
+        let checker = check_in_memory(&files, Options::default());
+        assert!(checker.errors().iter().all(|e| e.note.is_none()));
+    }
+
+    #[test]
+    fn unsaved_pages() {
+        let mut checker = check_in_memory(&[("std/fn.f.html", "<h1 id=\"x\">f</h1>")],
+                                          Options::default());
+        let page = Path::new("/docs/std/new.html");
+        checker.check_html(page, "<p>\n  <a href=\"fn.f.html#x\">f</a> <a href=\"#y\">y</a>\n\
+                                  <a href=\"fn.g.html\">g</a>").unwrap();
+        let errors = checker.errors().iter()
+            .map(|e| (e.kind, e.file.clone(), e.line, e.column))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [(ErrorKind::BrokenFragment, PathBuf::from("std/new.html"), 2, 40),
+                            (ErrorKind::BrokenLink, PathBuf::from("std/new.html"), 3, 10)]);
+    }
+
+    #[test]
+    fn cross_file_ids() {
+        let files = [
+            ("a.html", "<h1 id=\"intro\">A</h1><h2 id=\"a\">A</h2>"),
+            ("b.html", "<h1 id=\"b\">B</h1>\n<h2 id=\"intro\">B</h2>"),
+        ];
+        assert!(check_in_memory(&files, Options::default()).errors().is_empty());
+
+        let checker = check_in_memory(&files, Options {
+            cross_file_ids: true,
+            ..Options::default()
+        });
+        let errors = checker.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::CrossFileDuplicateId);
+        assert_eq!((errors[0].line, errors[0].column), (2, 9));
+        assert_eq!(errors[0].message(), "id `intro` is also defined in a.html");
+    }
+
+    #[test]
+    fn stray_whitespace() {
+        let files = [
+            ("index.html", "<a href=\"a.html \">a</a> <a href=\" a.html#x\">x</a>\n\
+                            <a href=\"gone.html \">g</a>"),
+            ("a.html", "<p id=\"x\">"),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        let errors = messages(&checker);
+        assert_eq!(errors, [
+            "index.html:1:10: warning: stray whitespace around link - `a.html `",
+            "index.html:1:34: warning: stray whitespace around link - ` a.html#x`",
+            "index.html:2:10: warning: stray whitespace around link - `gone.html `",
+            "index.html:2:10: broken link - gone.html",
+        ]);
+    }
+
+    #[test]
+    fn options_as_json() {
+        let options = Options {
+            exclude_fragments_for: vec![Pattern::new("src/**/*.html").unwrap()],
+            skip_schemes: vec!["rust".to_string()],
+            ..Options::default()
+        };
+        let json = ::serde_json::to_value(&options).unwrap();
+        assert_eq!(json["exclude-fragments-for"].to_string(), r#"["src/**/*.html"]"#);
+        assert_eq!(json["skip-scheme"].to_string(), r#"["rust"]"#);
+        assert!(json["max-depth"].is_null());
+    }
+
+    #[test]
+    fn extra_attrs() {
+        let files = [
+            ("index.html", "<a href=\"a.html\" data-target=\"gone.html\">a</a>\n\
+                            <!-- linkcheck:ignore-next --><div data-target=\"x.html\">\n\
+                            <a href=\"nope.html\">n</a><div data-target=\"a.html#f\">"),
+            ("a.html", ""),
+        ];
+        // The marker skips the next `href` unless `data-target` is checked.
+        assert!(!check_in_memory(&files, Options::default()).has_errors());
+
+        let checker = check_in_memory(&files, Options {
+            extra_attrs: vec!["data-target".to_string()],
+            ..Options::default()
+        });
+        let errors = checker.errors().iter()
+            .map(|e| (e.line, e.column, e.target.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [(1, 31, "gone.html".to_string()),
+                            (3, 10, "nope.html".to_string()),
+                            (3, 44, "a.html".to_string())]);
+    }
+
+    #[test]
+    fn media_links() {
+        let files = [
+            ("index.html", "<video poster=\"still.png\" src=\"gone.mp4\">\n\
+                            <source src=\"clip.webm\"><source src=\"clip.mp4\">\n\
+                            </video><img src=\"gone.png\"><audio>\n\
+                            <source src=\"gone.ogg\" type=\"audio/ogg\"></audio>\n\
+                            <video poster=\"gone.png\"><source src=\"https://x.org/a.mp4\">"),
+            ("still.png", ""),
+            ("clip.webm", ""),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        let errors = checker.errors().iter()
+            .map(|e| (e.line, e.column, e.target.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [(2, 38, "clip.mp4".to_string()),
+                            (4, 14, "gone.ogg".to_string()),
+                            (5, 16, "gone.png".to_string())]);
+    }
+
+    #[test]
+    fn ignored_extensions() {
+        let files = [
+            ("index.html", "<script src=\"app.js\"></script>\n\
+                            <a href=\"app.js.map\">map</a> <a href=\"gone.html\">g</a>\n\
+                            <video poster=\"still.png\"><source src=\"clip.WEBM\"></video>"),
+            ("app.js", ""),
+        ];
+        let options = Options {
+            ignore_extensions: vec!["map".to_string(), "webm".to_string()],
+            ..Options::default()
+        };
+        let checker = check_in_memory(&files, options);
+        let errors = checker.errors().iter()
+            .map(|e| (e.line, e.target.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [(2, "gone.html".to_string()), (3, "still.png".to_string())]);
+    }
+
+    #[test]
+    fn hidden_fragments() {
+        let files = [
+            ("index.html", "<a href=\"#shown\">a</a> <a href=\"#closed\">b</a>\n\
+                            <div hidden><p><span id=\"closed\">x</span></div>\n\
+                            <section id=\"shown\" class=\"hidden\">\n\
+                            <div style=\"color: red; display : none\"><br><p id=\"styled\"></div>\n\
+                            <script>if (a<b) {}</script><p id=\"after\"></section>\n\
+                            <a href=\"#styled\">c</a> <a href=\"#after\">d</a>\n\
+                            <a href=\"other.html#closed\">e</a>"),
+            ("other.html", "<div hidden id=\"closed\"></div>"),
+        ];
+        assert!(check_in_memory(&files, Options::default()).errors().is_empty());
+
+        let options = Options { check_hidden_fragments: true, ..Options::default() };
+        let checker = check_in_memory(&files, options);
+        let errors = messages(&checker);
+        assert_eq!(errors, [
+            "index.html:1:33: warning: link fragment `#closed` points into a hidden element",
+            "index.html:6:10: warning: link fragment `#styled` points into a hidden element",
+        ]);
+    }
+
+    #[test]
+    fn fragments_are_normalized() {
+        // "café" composed and decomposed, with the accent as U+0301.
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        let page = format!("<h1 id=\"{}\">x</h1><a name=\"{}-2\"></a>\n\
+                            <a href=\"#{}\">1</a><a href=\"#{}-2\">2</a><a href=\"#{}\">3</a>",
+                           decomposed, composed, composed, decomposed, decomposed);
+        let checker = check_in_memory(&[("index.html", &page)], Options::default());
+        assert!(!checker.has_errors());
+    }
+
+    #[test]
+    fn lazy_ids() {
+        let mut big = String::from("<h1 id=\"first\">x</h1><h2 id=\"first\">y</h2>\n");
+        for i in 0..10000 {
+            big.push_str(&format!("<p id=\"line-{}\">{}</p>\n", i, i));
+        }
+        big.push_str("<a name=\"last\"></a>\n");
+        let mut fs = MemoryFs::new();
+        fs.add_file(Path::new("/docs/big.html"), big.as_bytes());
+        let options = Options { lazy_ids: true, ..Options::default() };
+        let mut checker = Checker::with_file_system(Path::new("/docs"), options, Box::new(fs));
+
+        checker.check_html(Path::new("/docs/a.html"), "<a href=\"big.html#first\">a</a>").unwrap();
+        assert!(checker.errors().is_empty());
+        let scanned = checker.cache[Path::new("big.html")].scanned;
+        assert!(scanned > 0 && scanned < big.len());
+
+        checker.check_html(Path::new("/docs/b.html"),
+                           "<a href=\"big.html#last\">a</a><a href=\"big.html#gone\">b</a>")
+               .unwrap();
+        let errors = messages(&checker);
+        assert_eq!(errors, ["b.html:1:39: broken link fragment `#gone` pointing to `big.html`"]);
+        assert_eq!(checker.cache[Path::new("big.html")].scanned, big.len());
+    }
+
+    #[test]
+    fn max_depth() {
+        let files = [
+            ("index.html", "<a href=\"std/vec/struct.Vec.html#new\">a</a>\
+                            <a href=\"std/vec/gone.html\">b</a>"),
+            ("std/index.html", "<a href=\"vec/struct.Vec.html#old\">c</a>"),
+            ("std/vec/struct.Vec.html", "<h1 id=\"new\">x</h1><a href=\"gone.html\">d</a>"),
+        ];
+        let options = |max_depth| Options { max_depth, ..Options::default() };
+        assert_eq!(check_in_memory(&files, options(None)).stats().files_checked, 3);
+        assert_eq!(check_in_memory(&files, options(Some(0))).stats().files_checked, 1);
+
+        let checker = check_in_memory(&files, options(Some(1)));
+        assert_eq!(checker.stats().files_checked, 2);
+        let mut errors = messages(&checker);
+        errors.sort();
+        assert_eq!(errors, [
+            "index.html:1:53: broken link - std/vec/gone.html",
+            "std/index.html:1:10: broken link fragment `#old` pointing to \
+             `std/vec/struct.Vec.html`",
+        ]);
+    }
+
+    #[test]
+    fn page_collisions() {
+        let mut checker = check_in_memory(&[
+            ("index.html", "<a href=\"std/index.html\">std</a>"),
+            ("std/index.html", "<p id=\"x\">std</p>"),
+        ], Options::default());
+        assert_eq!(checker.errors().len(), 0);
+        // Outside of the root, a relative path is its own page path, so this
+        // would be mistaken for the page above.
+        let outside = Path::new("std/index.html");
+        checker.check_file(outside).unwrap();
+        checker.check_file(outside).unwrap();
+        let errors = messages(&checker);
+        assert_eq!(errors, ["std/index.html: another file maps to the same page - \
+                            std/index.html (read /docs/std/index.html instead)"]);
+    }
+
+    #[test]
+    fn link_text() {
+        let files = [
+            ("index.html", "<a href=\"a.html\">text</a><a href=\"a.html\"></a><a name=\"x\"></a>\n\
+                            <a href=\"a.html\"><img src=\"i.png\"></a>\
+                            <a href=\"a.html\"><img src=\"i.png\" alt=\"Home\"></a>\n\
+                            <a href=\"a.html\" aria-label=\"Home\"><svg></svg></a>\
+                            <a href=\"b.html\">\n  <span class=\"icon\"></span>&nbsp;\n\
+                            </a><a href=\"a.html\"><code>a</code></a>\n\
+                            <a name=\"y\"><a href=\"a.html\">&#160;</a><a href=\"a.html\">&lt;</a>\
+                            <a href=\"a.html\">&#x2014;</a><a href=\"b.html\">"),
+            ("a.html", ""),
+            ("b.html", ""),
+        ];
+        assert!(check_in_memory(&files, Options::default()).errors().is_empty());
+
+        let options = Options { check_link_text: true, ..Options::default() };
+        let checker = check_in_memory(&files, options);
+        let errors = messages(&checker);
+        assert_eq!(errors, [
+            "index.html:1:26: warning: link has no text for screen readers - a.html",
+            "index.html:2:1: warning: link has no text for screen readers - a.html",
+            "index.html:3:51: warning: link has no text for screen readers - b.html",
+            // A non-breaking space is no text, and unclosed elements end where
+            // the next one starts.
+            "index.html:6:13: warning: link has no text for screen readers - a.html",
+            "index.html:6:94: warning: link has no text for screen readers - b.html",
+        ]);
+    }
+
+    #[test]
+    fn mixed_content() {
+        let files = [
+            ("index.html", "<script src=\"HTTP://cdn.example/a.js\"></script>\n\
+                            <link rel=\"stylesheet\" href=\"http://cdn.example/a.css\">\n\
+                            <link rel=\"canonical\" href=\"http://example.com/\">\n\
+                            <img src=\"https://cdn.example/a.png\">\n\
+                            <a href=\"http://example.com\">a</a>"),
+        ];
+        assert!(check_in_memory(&files, Options::default()).errors().is_empty());
+
+        let options = Options { report_mixed_content: true, ..Options::default() };
+        let checker = check_in_memory(&files, options);
+        let errors = messages(&checker);
+        assert_eq!(errors, [
+            "index.html:1:14: warning: insecure asset is blocked on https pages - \
+             HTTP://cdn.example/a.js",
+            "index.html:2:1: warning: insecure asset is blocked on https pages - \
+             http://cdn.example/a.css",
+        ]);
+        assert!(!checker.has_errors());
+    }
+
+    #[test]
+    fn uppercase_extensions() {
+        let files = [
+            ("index.html", "<a href=\"Page.HTML#ok\">a</a><a href=\"Page.HTML#gone\">b</a>"),
+            ("Page.HTML", "<h1 id=\"ok\">x</h1><a href=\"missing.html\">c</a>"),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        assert_eq!(checker.stats().files_checked, 2);
+        let errors = messages(&checker);
+        assert_eq!(errors, [
+            "index.html:1:38: broken link fragment `#gone` pointing to `Page.HTML`",
+            "Page.HTML:1:28: broken link - missing.html",
+        ]);
+    }
+
+    #[test]
+    fn encoded_ids_are_not_duplicates() {
+        let files = [
+            ("index.html", "<h1 id=\"a b\">x</h1><h2 id=\"a%20b\">y</h2><h3 id=\"c\">z</h3>\n\
+                            <a href=\"#a%20b\">1</a><h4 id=\"c\">w</h4>"),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        let errors = messages(&checker);
+        assert_eq!(errors, ["index.html:2:31: id is not unique: `c`"]);
+    }
+
+    #[test]
+    fn fragments_with_hashes() {
+        let files = [
+            ("index.html", "<h1 id=\"r#type\">x</h1><h2 id=\"#old\">y</h2>\n\
+                            <a href=\"#r%23type\">1</a><a href=\"#r#type\">2</a>\n\
+                            <a href=\"##old\">3</a><a href=\"#%23old\">4</a>\n\
+                            <a href=\"#r%23gone\">5</a><a href=\"#old\">6</a>"),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        let errors = messages(&checker);
+        assert_eq!(errors, ["index.html:4:10: broken link fragment `#r%23gone` pointing to \
+                             `index.html`"]);
+    }
+
+    #[test]
+    fn javascript_dependencies() {
+        let files = [
+            ("index.html", "<a href=\"#\">menu</a><a href=\"javascript:void(0)\">x</a>\n\
+                            <a href=\"src/lib.rs.html#3-7\">src</a><a href=\"a.html#f\">a</a>\n\
+                            <div data-href=\"a.html\"></div>"),
+            ("a.html", "<h2 id=\"f\">f</h2>"),
+            ("src/lib.rs.html", ""),
+        ];
+        let options = Options {
+            extra_attrs: vec!["data-href".to_string()],
+            ..Options::default()
+        };
+        assert!(check_in_memory(&files, options.clone()).errors().is_empty());
+
+        let options = Options { verify_no_javascript_dependency: true, ..options };
+        let checker = check_in_memory(&files, options);
+        let errors = messages(&checker);
+        assert_eq!(errors, [
+            "index.html:1:10: warning: link depends on javascript - # \
+             (placeholder for a javascript button)",
+            "index.html:1:30: warning: link depends on javascript - javascript:void(0) \
+             (javascript: URL)",
+            "index.html:2:10: warning: link depends on javascript - src/lib.rs.html#3-7 \
+             (line numbers highlighted by javascript)",
+            "index.html:3:17: warning: link depends on javascript - a.html \
+             (only followed by javascript)",
+        ]);
+    }
+
+    #[test]
+    fn builder() {
+        let mut fs = MemoryFs::new();
+        fs.add_file(Path::new("/docs/index.html"), b"<a href=\"a.html#x\">a</a>");
+        fs.add_file(Path::new("/docs/a.html"), b"<h1 id=\"x-1\">A</h1><h1 id=\"y\">A</h1>");
+        let mut checker = Checker::builder("/docs")
+            .file_system(Box::new(fs))
+            .fuzzy_fragments(true)
+            .report_orphans(true)
+            .build();
+        assert_eq!(checker.options().jobs, 1);
+        checker.walk(Path::new("/docs")).unwrap();
+        assert!(!checker.has_errors());
+    }
+
+    #[test]
+    fn validators() {
+        struct Rfcs;
+
+        impl LinkValidator for Rfcs {
+            fn validate(&self, _page: &Path, attr: &str, url: &str) -> Validation {
+                let number = match (attr, url.strip_prefix("rfc:")) {
+                    ("data-rfc", _) => url,
+                    (_, Some(number)) => number,
+                    _ => return Validation::Continue,
+                };
+                match number.parse::<u32>() {
+                    Ok(n) if n <= 3000 => Validation::Valid,
+                    _ => Validation::Invalid("no such RFC".to_string()),
+                }
+            }
+        }
+
+        let mut fs = MemoryFs::new();
+        fs.add_file(Path::new("/docs/index.html"),
+                    b"<a href=\"rfc:1234\">x</a> <a href=\"rfc:9999\">y</a>\n\
+                      <a href=\"gone.html\">z</a> <a href=\"https://example.com\">w</a>\n\
+                      <span data-rfc=\"2000\"></span><span data-rfc=\"4000\"></span>");
+        let mut checker = Checker::builder("/docs")
+            .file_system(Box::new(fs))
+            .extra_attrs(vec!["data-rfc".to_string()])
+            .validator(Rfcs)
+            .jobs(2)
+            .build();
+        checker.walk(Path::new("/docs")).unwrap();
+        let errors = messages(&checker);
+        assert_eq!(errors, ["index.html:1:35: broken link - rfc:9999 (no such RFC)",
+                            "index.html:2:10: broken link - gone.html",
+                            "index.html:3:46: broken link - 4000 (no such RFC)"]);
+    }
+
+    #[test]
+    fn paths_relative_to() {
+        let relative = |base: &str, path: &str| relative_path(Path::new(base), Path::new(path));
+        assert_eq!(relative("/repo", "/repo/build/doc"), Path::new("build/doc"));
+        assert_eq!(relative("/repo/src", "/repo/build/doc"), Path::new("../build/doc"));
+        assert_eq!(relative("/repo/./build", "/repo/build"), Path::new(""));
+        assert_eq!(relative("repo", "build/doc"), Path::new("../build/doc"));
+        assert_eq!(relative("repo", "/build/doc"), Path::new("/build/doc"));
+
+        let files = [("index.html", "<a href=\"gone.html\">x</a>")];
+        let printed = |base: &str| {
+            let options = Options { relative_to: Some(PathBuf::from(base)), ..Options::default() };
+            let checker = check_in_memory(&files, options);
+            assert_eq!(checker.errors()[0].file, Path::new("index.html"));
+            let printed = checker.display_error(&checker.errors()[0]).to_string();
+            printed
+        };
+        assert_eq!(printed("/"), "docs/index.html:1:10: broken link - gone.html");
+        assert_eq!(printed("/docs"), "index.html:1:10: broken link - gone.html");
+        assert_eq!(printed("/build/out"), "../../docs/index.html:1:10: broken link - gone.html");
+    }
+
+    #[test]
+    fn pages_without_ids_are_parsed_once() {
+        let files = [
+            ("index.html", "<a href=\"a.html#x\">x</a><a href=\"a.html#y\">y</a>\n\
+                            <a href=\"a.html#z\">z</a>"),
+            ("a.html", "<p>No ids here</p>"),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        assert_eq!(checker.errors().len(), 3);
+        assert!(checker.errors().iter().all(|e| e.kind == ErrorKind::BrokenFragment));
+        assert!(checker.cache[Path::new("a.html")].parsed);
+        assert!(checker.cache[Path::new("a.html")].ids.is_empty());
+    }
+
+    #[test]
+    fn svg_references() {
+        let files = [
+            ("index.html", "<svg><symbol id=\"sym\"><g id=\"part\"></g></symbol></svg>\n\
+                            <svg><use href=\"#sym\"/><use xlink:href=\"#part\"/></svg>\n\
+                            <svg><use xlink:href=\"#gone\"/></svg>"),
+        ];
+        let checker = check_in_memory(&files, Options::default());
+        let errors = messages(&checker);
+        assert_eq!(errors, [
+            "index.html:3:23: broken link fragment `#gone` pointing to `index.html`",
+        ]);
+    }
+
+    #[test]
+    fn fuzzy_fragments() {
+        let files = [
+            ("index.html", "<a href=\"a.html#method.foo\">1</a>\n\
+                            <a href=\"a.html#method.bar\">2</a>\n\
+                            <a href=\"a.html#method.baz\">3</a>"),
+            ("a.html", "<h4 id=\"method.foo-1\">foo</h4><h4 id=\"method.bar-x\">bar</h4>\
+                        <h4 id=\"method.baz-\">baz</h4>"),
+        ];
+        let lines = |checker: Checker| {
+            checker.errors().iter().map(|e| e.line).collect::<Vec<_>>()
+        };
+        assert_eq!(lines(check_in_memory(&files, Options::default())), [1, 2, 3]);
+        let options = Options { fuzzy_fragments: true, ..Options::default() };
+        assert_eq!(lines(check_in_memory(&files, options)), [2, 3]);
+        let options = Options { fuzzy_fragments: true, lazy_ids: true, ..Options::default() };
+        assert_eq!(lines(check_in_memory(&files, options)), [2, 3]);
+    }
+
+    #[test]
+    fn case_insensitive_fragments() {
+        let files = [
+            ("index.html", "<a href=\"a.html#Method.Foo\">1</a>\n\
+                            <a href=\"a.html#method.BAR\">2</a>\n\
+                            <a href=\"a.html#Method.Baz\">3</a>"),
+            ("a.html", "<h4 id=\"method.foo\">foo</h4><h4 id=\"method.bar-1\">bar</h4>"),
+        ];
+        let lines = |checker: Checker| {
+            checker.errors().iter().map(|e| e.line).collect::<Vec<_>>()
+        };
+        assert_eq!(lines(check_in_memory(&files, Options::default())), [1, 2, 3]);
+        let options = Options { case_insensitive_fragments: true, ..Options::default() };
+        assert_eq!(lines(check_in_memory(&files, options)), [2, 3]);
+        let options = Options {
+            case_insensitive_fragments: true,
+            fuzzy_fragments: true,
+            ..Options::default()
+        };
+        assert_eq!(lines(check_in_memory(&files, options)), [3]);
+    }
+
+    #[test]
+    fn markdown_sources() {
+        let files = [
+            ("README.md", "# Intro\n\
+                           [guide](guide/index.md#getting-started) [bad](guide/index.md#nope) \
+                           [gone](gone.md)\n\
+                           [api][std] [x][undefined] `[code](code.md)`\n\
+                           \n\
+                           [std]: std/index.html#method.len\n"),
+            ("guide/index.md", "Getting started\n---\n[back](../README.md#intro)"),
+            ("std/index.html", "<h1 id=\"method.len\">len</h1><a href=\"../README.md#gone\">x</a>"),
+        ];
+        assert_eq!(check_in_memory(&files, Options::default()).errors().len(), 0);
+
+        let checker = check_in_memory(&files, Options { markdown: true, ..Options::default() });
+        let mut errors = messages(&checker);
+        errors.sort();
+        assert_eq!(errors, [
+            "README.md:2:47: broken link fragment `#nope` pointing to `guide/index.md`",
+            "README.md:2:75: broken link - gone.md",
+            "README.md:3:16: broken link - [undefined] (no such reference definition)",
+            "std/index.html:1:38: broken link fragment `#gone` pointing to `README.md`",
+        ]);
+    }
+
+    #[test]
+    fn assumed_extensions() {
+        let files = [
+            ("index.html", "<a href=\"std\">1</a> <a href=\"guide#intro\">2</a>\n\
+                            <a href=\"guide#gone\">3</a> <a href=\"gone\">4</a>\n\
+                            <a href=\"struct.Foo\">5</a>"),
+            ("std.html", ""),
+            ("struct.Foo.html", ""),
+            ("guide.html", "<h1 id=\"intro\">Guide</h1>"),
+        ];
+        assert_eq!(messages(&check_in_memory(&files, Options::default())).len(), 5);
+        let options = Options { assume_extension: Some("html".to_string()), ..Options::default() };
+        assert_eq!(messages(&check_in_memory(&files, options)), [
+            "index.html:2:10: broken link fragment `#gone` pointing to `guide.html`",
+            "index.html:2:37: broken link - gone",
+            "index.html:3:10: broken link - struct.Foo",
+        ]);
+    }
+
+    #[test]
+    fn fragments_excluded_for_globs() {
+        let files = [
+            ("index.html", "<a href=\"src/a/lib.rs.html#x\">1</a>\n\
+                            <a href=\"src/main.rs.html#y\">2</a>\n\
+                            <a href=\"src/gone.rs.html#z\">3</a>\n\
+                            <a href=\"lib.html#w\">4</a>"),
+            ("src/a/lib.rs.html", "<span id=\"d\"></span><span id=\"d\"></span>"),
+            ("src/main.rs.html", ""),
+            ("lib.html", ""),
+        ];
+        let checker = check_in_memory(&files, Options {
+            exclude_fragments_for: vec![Pattern::new("src/**/*.html").unwrap()],
+            ..Options::default()
+        });
+        let errors = checker.errors().iter()
+            .map(|e| (e.line, e.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [(3, ErrorKind::BrokenLink), (4, ErrorKind::BrokenFragment)]);
+    }
+
+    #[test]
+    fn severities_by_page() {
+        let files = [
+            ("index.html", "<a href=\"gone.html\">1</a>"),
+            ("std/vec.html", "<a href=\"gone.html\">2</a>"),
+            ("rustc_ast/ast.html", "<a href=\"gone.html\">3</a>\n<a href=\"?q\">4</a>"),
+        ];
+        let checker = check_in_memory(&files, Options {
+            warn_query_links: true,
+            severities: vec![(Pattern::new("std/**").unwrap(), Severity::Error),
+                             (Pattern::new("rustc_*/**").unwrap(), Severity::Warning),
+                             (Pattern::new("**").unwrap(), Severity::Warning)],
+            ..Options::default()
+        });
+        let mut errors = checker.errors().iter()
+            .map(|e| (e.file.to_str().unwrap(), e.kind, e.severity))
+            .collect::<Vec<_>>();
+        errors.sort();
+        assert_eq!(errors, [
+            ("index.html", ErrorKind::BrokenLink, Severity::Warning),
+            ("rustc_ast/ast.html", ErrorKind::BrokenLink, Severity::Warning),
+            ("rustc_ast/ast.html", ErrorKind::QueryOnlyLink, Severity::Warning),
+            ("std/vec.html", ErrorKind::BrokenLink, Severity::Error),
+        ]);
+        assert!(checker.has_errors());
+        assert_eq!("warning".parse(), Ok(Severity::Warning));
+        assert!("fatal".parse::<Severity>().is_err());
+    }
+
+    #[test]
+    fn streaming_walk_stops_early() {
+        let tree = Tree::new("streaming", &[
+            ("a.html", "<a href=\"x.html\">x</a>"),
+            ("b.html", "<a href=\"y.html\">y</a>"),
+            ("c.html", "<a href=\"z.html\">z</a>"),
+        ]);
+        let mut seen = Vec::new();
+        let mut checker = Checker::new(&tree.0, Options::default());
+        let completed = checker.walk_streaming(&tree.0, |err| {
+            seen.push(err.target.clone());
+            true
+        });
+        assert!(completed.unwrap());
+        seen.sort();
+        assert_eq!(seen, ["x.html", "y.html", "z.html"]);
+
+        let mut seen = 0;
+        let mut checker = Checker::new(&tree.0, Options::default());
+        assert!(!checker.walk_streaming(&tree.0, |_| { seen += 1; false }).unwrap());
+        assert_eq!(seen, 1);
+        assert_eq!(checker.errors().len(), 1);
+    }
+
+    #[test]
+    fn error_kind_names_round_trip() {
+        for kind in ErrorKind::ALL {
+            assert_eq!(kind.name().parse::<ErrorKind>(), Ok(*kind));
+        }
+        assert!("no-such-kind".parse::<ErrorKind>().is_err());
+    }
+
+    #[test]
+    fn all_error_kinds_in_declaration_order() {
+        for (i, &kind) in ErrorKind::ALL.iter().enumerate() {
+            assert_eq!(kind as usize, i, "{:?} is out of order", kind);
+        }
+        assert_eq!(ErrorKind::ALL.len(), ErrorKind::BrokenNavigation as usize + 1);
+    }
+}
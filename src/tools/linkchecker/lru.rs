@@ -0,0 +1,234 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A map which can be given a budget of bytes, beyond which the entries used
+//! least recently are dropped, for the pages of trees too big to keep in
+//! memory. Whoever finds an entry gone reads it again.
+//!
+//! Entries may grow or shrink after they're inserted, so their weight is
+//! only an estimate until the budget looks exceeded. Everything is weighed
+//! again then, and entries are dropped until a quarter of the budget is free,
+//! so that this doesn't happen on every insert.
+
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::hash::Hash;
+use std::mem;
+use std::ops::Index;
+
+/// How much memory a value takes up, roughly.
+pub trait Weigh {
+    fn weight(&self) -> usize;
+}
+
+struct Slot<V> {
+    value: V,
+    /// When the value was last looked up, on the clock of the map.
+    used: Cell<u64>,
+    /// The weight of the value when it was inserted or last weighed.
+    weight: usize,
+}
+
+pub struct Lru<K, V> {
+    entries: HashMap<K, Slot<V>>,
+    /// No entries are dropped without a budget.
+    budget: Option<usize>,
+    /// The estimated weight of all the entries.
+    size: usize,
+    clock: Cell<u64>,
+    evicted: usize,
+}
+
+impl<K: Eq + Hash + Clone, V: Weigh> Lru<K, V> {
+    pub fn new(budget: Option<usize>) -> Lru<K, V> {
+        Lru {
+            entries: HashMap::new(),
+            budget,
+            size: 0,
+            clock: Cell::new(0),
+            evicted: 0,
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        let now = self.clock.get() + 1;
+        self.clock.set(now);
+        now
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: ?Sized + Eq + Hash
+    {
+        self.entries.get(key).map(|slot| {
+            slot.used.set(self.tick());
+            &slot.value
+        })
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>, Q: ?Sized + Eq + Hash
+    {
+        let now = self.tick();
+        self.entries.get_mut(key).map(|slot| {
+            slot.used.set(now);
+            &mut slot.value
+        })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: ?Sized + Eq + Hash
+    {
+        self.entries.contains_key(key)
+    }
+
+    /// Inserts `value`, dropping the entries used least recently if that goes
+    /// over the budget. The new entry itself is always kept.
+    pub fn insert(&mut self, key: K, value: V) {
+        let weight = value.weight();
+        let slot = Slot { value, used: Cell::new(self.tick()), weight };
+        if let Some(old) = self.entries.insert(key.clone(), slot) {
+            self.size -= old.weight;
+        }
+        self.size += weight;
+        match self.budget {
+            Some(budget) if self.size > budget => self.shrink(&key, budget),
+            _ => {}
+        }
+    }
+
+    fn shrink(&mut self, keep: &K, budget: usize) {
+        let mut size = 0;
+        for slot in self.entries.values_mut() {
+            slot.weight = slot.value.weight();
+            size += slot.weight;
+        }
+        self.size = size;
+        let target = budget / 4 * 3;
+        if self.size <= target {
+            return;
+        }
+        let mut by_use = self.entries.iter()
+                                     .filter(|&(key, _)| key != keep)
+                                     .map(|(key, slot)| (slot.used.get(), key.clone()))
+                                     .collect::<Vec<_>>();
+        by_use.sort_by_key(|&(used, _)| used);
+        for (_, key) in by_use {
+            if self.size <= target {
+                break;
+            }
+            let slot = self.entries.remove(&key).unwrap();
+            self.size -= slot.weight;
+            self.evicted += 1;
+        }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>, Q: ?Sized + Eq + Hash
+    {
+        self.entries.remove(key).map(|slot| {
+            self.size -= slot.weight;
+            slot.value
+        })
+    }
+
+    /// Visits the entries in no particular order, without counting that as a
+    /// use.
+    pub fn iter<'a>(&'a self) -> Iter<'a, K, V> {
+        Iter(self.entries.iter())
+    }
+
+    /// How many entries have been dropped to stay within the budget since
+    /// this was last asked.
+    pub fn take_evicted(&mut self) -> usize {
+        mem::replace(&mut self.evicted, 0)
+    }
+}
+
+impl<K, V, Q> Index<&Q> for Lru<K, V>
+    where K: Eq + Hash + Clone + Borrow<Q>, Q: ?Sized + Eq + Hash, V: Weigh
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+pub struct Iter<'a, K: 'a, V: 'a>(hash_map::Iter<'a, K, Slot<V>>);
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.0.next().map(|(key, slot)| (key, &slot.value))
+    }
+}
+
+impl<K, V> IntoIterator for Lru<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter(self.entries.into_iter())
+    }
+}
+
+pub struct IntoIter<K, V>(hash_map::IntoIter<K, Slot<V>>);
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        self.0.next().map(|(key, slot)| (key, slot.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lru, Weigh};
+
+    impl Weigh for String {
+        fn weight(&self) -> usize {
+            self.len()
+        }
+    }
+
+    #[test]
+    fn least_recently_used_are_dropped() {
+        let mut lru = Lru::new(Some(40));
+        for name in &["a", "b", "c", "d"] {
+            lru.insert(name.to_string(), "x".repeat(10));
+        }
+        assert_eq!(lru.take_evicted(), 0);
+        // `a` is used again, so `b` and `c` are the ones to go to get down to
+        // 30 bytes.
+        assert!(lru.get("a").is_some());
+        lru.insert("e".to_string(), "x".repeat(10));
+        assert_eq!(lru.take_evicted(), 2);
+        let mut left = lru.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(left, ["a", "d", "e"]);
+
+        // Entries are weighed again, and the newest kept however big.
+        lru.get_mut("a").unwrap().push_str(&"x".repeat(100));
+        lru.insert("f".to_string(), "x".repeat(50));
+        assert_eq!(lru.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>(), ["f"]);
+        assert_eq!(lru.remove("f").map(|value| value.len()), Some(50));
+
+        let mut unbounded = Lru::new(None);
+        for i in 0..100 {
+            unbounded.insert(i.to_string(), "x".repeat(1000));
+        }
+        assert_eq!(unbounded.take_evicted(), 0);
+        assert_eq!(unbounded["42"].len(), 1000);
+    }
+}
@@ -10,368 +10,1532 @@
 
 //! Script to check the validity of `href` links in our HTML documentation.
 //!
-//! In the past we've been quite error prone to writing in broken links as most
-//! of them are manually rather than automatically added. As files move over
-//! time or apis change old links become stale or broken. The purpose of this
-//! script is to check all relative links in our documentation to make sure they
-//! actually point to a valid place.
+//! See the `linkchecker` library for how links are found and resolved.
 //!
-//! Currently this doesn't actually do any HTML parsing or anything fancy like
-//! that, it just has a simple "regex" to search for `href` and `id` tags.
-//! These values are then translated to file URLs if possible and then the
-//! destination is asserted to exist.
+//! Settings can be given on the command line or in a `linkchecker.toml` file,
+//! which is read from the current directory unless `--config` says otherwise.
+//! Command line flags take precedence over the configuration file, which may
+//! only have the options listed by `--config-schema`.
+//! `--print-config` prints the settings this results in as JSON, the DOCS and
+//! root to check among them, and exits without checking anything.
+//! `--expand-env` replaces `$VAR` and `${VAR}` in DOCS, or in the `docs` given
+//! in the configuration file, by the value of the environment variable, and
+//! fails if it isn't set. Its `root` is `--root`.
 //!
-//! A few whitelisted exceptions are allowed as there's known bugs in rustdoc,
-//! but this should catch the majority of "broken link" cases.
+//! Errors and warnings are printed to stdout. `RUST_LOG` can be set to a log
+//! level such as `info` or `debug` to also print progress to stderr, or to
+//! `error` to silence warnings. The report ends with the number of broken
+//! links and of the distinct targets they point to. With `--json-summary` only
+//! a JSON object with the final counts, those included, and the run time in
+//! `elapsed_ms` is printed to stdout, and individual diagnostics are silenced
+//! unless `RUST_LOG` asks for them, in which case they go to stderr. The run
+//! time is also logged at the end, at the `info` level.
+//! `--output` writes the report to a file instead of stdout, again leaving
+//! only what `RUST_LOG` asks for on stderr. `--summary-only-on-failure` holds
+//! the report back until the end, and only prints it if the check failed.
+//! `--format sarif` replaces the usual report with a SARIF document for GitHub
+//! code scanning, `--format codeclimate` with a Code Climate report for
+//! GitLab's code quality widget, `--format github` with annotations for GitHub
+//! Actions, and `--format tsv` with a table to paste into a spreadsheet. These
+//! give paths relative to `--repo-root`, which defaults to the current
+//! directory.
+//! `--format ndjson` prints each problem as a JSON object on a line of its own,
+//! and `--format human-compact` just a line per page with problems, counting
+//! them by kind, from the worst page to the least bad. These give paths
+//! relative to the docs root. `--relative-to DIR` gives the paths of every
+//! format relative to DIR instead, going up with `..` for docs outside of it.
+//!
+//! `--dump-index FILE` also writes the ids defined by each page to FILE, as a
+//! JSON object mapping the page's path relative to the docs root to a sorted
+//! list, for other tools to find anchors without parsing the pages again.
+//! `--dump-links FILE` writes every link which resolved to a file, and whose
+//! fragment was found, as a JSON array of objects with the `source` page, the
+//! `target` file and the `fragment`, or as TSV if FILE ends in `.tsv`. Links
+//! to the same target and fragment on a page are only written once.
+//!
+//! With `--experimental-http`, DOCS can be the URL of a page of docs deployed
+//! to a web server, which is checked against the directory it's in, or against
+//! `--root` if that's a URL as well. The page and the targets of its links are
+//! fetched with curl, a 404 or 410 meaning that a target is missing.
+//!
+//! With `--markdown`, which is experimental, the links of Markdown sources
+//! below DOCS are checked as well, so that they can be fixed before the docs
+//! are rendered.
+//!
+//! With `--stdin-html` a single page is read from stdin and checked as if it
+//! were at the path given as DOCS, by default `stdin.html` in `--root`. This
+//! is meant for editors, so the report defaults to `ndjson`.
+//!
+//! `--watch` keeps checking a directory after the first run, for authors
+//! working on the docs. Pages which changed are checked again as they're
+//! saved, along with the pages linking to them, and their problems printed.
+//!
+//! With `--git-base REF`, only the pages which git says changed since the
+//! commit REF are checked, for docs kept in a git repository. New pages git
+//! doesn't know about yet count as changed unless they're ignored. Their links
+//! are still resolved against all of DOCS.
+//!
+//! `--compare OTHER` checks another version of the docs as well, say stable
+//! when DOCS is nightly, and only reports the errors found in just one of
+//! them, as newly broken if they're in DOCS and newly fixed if they're in
+//! OTHER. Both need to be whole trees, as directories or archives.
+//!
+//! `--low-memory` keeps only about 256 megabytes of pages in memory, or as
+//! many as `--low-memory=MB` says, for CI runners short of it. Pages beyond
+//! that are read and parsed again when they're linked to, which is slower.
+//! It can't be combined with `--dump-index`, which needs every page's ids,
+//! and doesn't help with archives, whose pages are all unpacked into memory.
+//!
+//! The exit status tells scripts what went wrong:
+//!
+//! * 0: no errors were found, although there may have been warnings.
+//! * 1: there are broken links or other errors in the docs. With `--compare`,
+//!   only links which aren't also broken in the other version count.
+//! * 2: the arguments or the configuration file are invalid.
+//! * 3: the docs, stdin or the output file couldn't be read or written.
+//!
+//! Anything else, like the 101 of a panic, is a bug in the checker.
+
+extern crate getopts;
+extern crate glob;
+extern crate linkchecker;
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+
+mod codeclimate;
+mod compact;
+mod config;
+mod drift;
+mod expand;
+mod git;
+mod github;
+mod sarif;
+mod tsv;
+mod watch;
 
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use std::path::{Path, PathBuf, Component};
-use std::collections::{HashMap, HashSet};
-use std::collections::hash_map::Entry;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use Redirect::*;
+use glob::Pattern;
+use linkchecker::{Checker, ErrorKind, HostStats, LinkError, LoadError, Options, Severity};
+use linkchecker::relative_path;
+use linkchecker::external;
+use linkchecker::rewrite::Rewrite;
+use linkchecker::vfs::{self, HttpFs, MemoryFs};
 
-macro_rules! t {
-    ($e:expr) => (match $e {
-        Ok(e) => e,
-        Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
-    })
+use drift::Drift;
+
+const DEFAULT_CONFIG: &str = "linkchecker.toml";
+
+/// Exit statuses, see the crate documentation.
+const EXIT_LINK_ERRORS: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_IO: i32 = 3;
+
+#[derive(PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Format {
+    Human,
+    HumanCompact,
+    Sarif,
+    #[serde(rename = "codeclimate")]
+    CodeClimate,
+    Github,
+    Tsv,
+    Ndjson,
 }
 
-fn main() {
-    let docs = env::args_os().nth(1).unwrap();
-    let docs = env::current_dir().unwrap().join(docs);
-    let mut errors = false;
-    walk(&mut HashMap::new(), &docs, &docs, &mut errors);
-    if errors {
-        panic!("found some broken links");
+/// The settings of a run besides its `Options`, for `--print-config`.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct RunConfig<'a> {
+    docs: &'a Path,
+    root: &'a Path,
+    format: &'a Format,
+    output: &'a Option<PathBuf>,
+    json_summary: bool,
+    summary_only_on_failure: bool,
+    group_by_target_dir: bool,
+    stats_timing: bool,
+    dump_index: &'a Option<PathBuf>,
+    dump_links: &'a Option<PathBuf>,
+    repo_root: &'a Path,
+    git_base: &'a Option<String>,
+    compare: &'a Option<PathBuf>,
+    low_memory: Option<u64>,
+}
+
+/// The settings in effect as a JSON object, for `--print-config`. Fails on
+/// paths which aren't valid UTF-8.
+fn config_json(options: &Options, run: &RunConfig) -> serde_json::Result<String> {
+    let mut config = serde_json::to_value(options)?;
+    if let (&mut serde_json::Value::Object(ref mut config), serde_json::Value::Object(run)) =
+        (&mut config, serde_json::to_value(run)?) {
+        config.extend(run);
+    }
+    serde_json::to_string_pretty(&config)
+}
+
+/// The statistics of a run taking `elapsed` as JSON, for `--json-summary`.
+fn summary_json(checker: &Checker, elapsed: Duration) -> serde_json::Result<serde_json::Value> {
+    let mut stats = serde_json::to_value(checker.stats())?;
+    if let serde_json::Value::Object(ref mut stats) = stats {
+        let (links, targets) = broken_link_counts(checker.errors());
+        stats.insert("broken_links".to_string(), links.into());
+        stats.insert("broken_targets".to_string(), targets.into());
+        stats.insert("elapsed_ms".to_string(), millis(elapsed).into());
     }
+    Ok(stats)
 }
 
-#[derive(Debug)]
-pub enum LoadError {
-    IOError(std::io::Error),
-    BrokenRedirect(PathBuf, std::io::Error),
-    IsRedirect,
+/// TOML representation of `linkchecker.toml`. Options added here need to be
+/// added to `config::KEYS` as well.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct TomlConfig {
+    docs: Option<PathBuf>,
+    root: Option<PathBuf>,
+    expand_env: Option<bool>,
+    ignore: Option<Vec<String>>,
+    report_absolute_links: Option<bool>,
+    report_mixed_content: Option<bool>,
+    check_link_text: Option<bool>,
+    no_follow_symlinks: Option<bool>,
+    lint_structure: Option<bool>,
+    check_canonical: Option<bool>,
+    check_navigation: Option<bool>,
+    static_root: Option<PathBuf>,
+    report_orphans: Option<bool>,
+    treat_redirect_as_error: Option<bool>,
+    strict_fragments: Option<bool>,
+    check_hidden_fragments: Option<bool>,
+    fix_suggestions: Option<bool>,
+    suggest_fragment_pages: Option<bool>,
+    cross_file_ids: Option<bool>,
+    extra_attrs: Option<Vec<String>>,
+    exclude_fragments_for: Option<Vec<String>>,
+    warn_query_links: Option<bool>,
+    id_cache: Option<PathBuf>,
+    fuzzy_fragments: Option<bool>,
+    case_insensitive_fragments: Option<bool>,
+    markdown: Option<bool>,
+    verify_no_javascript_dependency: Option<bool>,
+    jobs: Option<usize>,
+    max_depth: Option<usize>,
+    skip_scheme: Option<Vec<String>>,
+    ignore_extension: Option<Vec<String>>,
+    list_external: Option<bool>,
+    allow: Option<Vec<String>>,
+    severity: Option<Vec<String>>,
+    json_summary: Option<bool>,
+    summary_only_on_failure: Option<bool>,
+    group_by_target_dir: Option<bool>,
+    stats_timing: Option<bool>,
+    output: Option<PathBuf>,
+    dump_index: Option<PathBuf>,
+    dump_links: Option<PathBuf>,
+    format: Option<String>,
+    repo_root: Option<PathBuf>,
+    relative_to: Option<PathBuf>,
+    assume_extension: Option<String>,
+    strict_io: Option<bool>,
+    stream_threshold: Option<u64>,
+    low_memory: Option<u64>,
+    index_name: Option<String>,
+    warn_self_links: Option<bool>,
+    rewrite: Option<Vec<String>>,
+    check_external: Option<bool>,
+    external_retries: Option<u32>,
+    external_cache: Option<PathBuf>,
+    external_cache_ttl: Option<u64>,
+    external_max_per_host: Option<usize>,
+    external_rate: Option<u32>,
 }
 
-enum Redirect {
-    SkipRedirect,
-    FromRedirect(bool),
+/// Logger reproducing the checker's traditional output: errors and warnings
+/// are printed verbatim to stdout, anything more verbose goes to stderr.
+struct Logger {
+    stdout: bool,
 }
 
-struct FileEntry {
-    source: String,
-    ids: HashSet<String>,
+static STDOUT_LOGGER: Logger = Logger { stdout: true };
+static STDERR_LOGGER: Logger = Logger { stdout: false };
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            log::Level::Error | log::Level::Warn if self.stdout => {
+                println!("{}", record.args())
+            }
+            log::Level::Error | log::Level::Warn => eprintln!("{}", record.args()),
+            level => eprintln!("{}: {}", level.to_string().to_lowercase(), record.args()),
+        }
+    }
+
+    fn flush(&self) {}
 }
 
-type Cache = HashMap<PathBuf, FileEntry>;
-
-fn small_url_encode(s: &str) -> String {
-    s.replace("<", "%3C")
-     .replace(">", "%3E")
-     .replace(" ", "%20")
-     .replace("?", "%3F")
-     .replace("'", "%27")
-     .replace("&", "%26")
-     .replace(",", "%2C")
-     .replace(":", "%3A")
-     .replace(";", "%3B")
-     .replace("[", "%5B")
-     .replace("]", "%5D")
-     .replace("\"", "%22")
+/// Installs the logger, keeping stdout free of diagnostics if `quiet` is set.
+fn init_logger(quiet: bool) {
+    let default = if quiet { log::LevelFilter::Off } else { log::LevelFilter::Warn };
+    let level = env::var("RUST_LOG").ok()
+                                    .and_then(|level| level.parse().ok())
+                                    .unwrap_or(default);
+    log::set_logger(if quiet { &STDERR_LOGGER } else { &STDOUT_LOGGER }).unwrap();
+    log::set_max_level(level);
 }
 
-impl FileEntry {
-    fn parse_ids(&mut self, file: &Path, contents: &str, errors: &mut bool) {
-        if self.ids.is_empty() {
-            with_attrs_in_source(contents, " id", |fragment, i, _| {
-                let frag = fragment.trim_left_matches("#").to_owned();
-                let encoded = small_url_encode(&frag);
-                if !self.ids.insert(frag) {
-                    *errors = true;
-                    println!("{}:{}: id is not unique: `{}`", file.display(), i, fragment);
+fn main() {
+    let start = Instant::now();
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    let opts = command_line();
+
+    let usage = |exit_code: i32, opts: &getopts::Options| -> ! {
+        println!("{}", opts.usage("Usage: linkchecker [options] [DOCS]\n\n\
+                                   DOCS is a directory to check, a single HTML file \
+                                   to check against the directory it's in, or a `.tar` \
+                                   or `.tar.gz` archive of docs to check without \
+                                   unpacking it to disk, which holds all of its pages \
+                                   in memory. With `--experimental-http` it may also \
+                                   be the URL of a deployed page.\n\n\
+                                   Warnings can be silenced with `--allow KIND`, where \
+                                   KIND is one of the kinds listed in the JSON output, \
+                                   like `query-only-link`.\n\n\
+                                   `--severity GLOB=LEVEL` turns the errors on pages \
+                                   matching GLOB into warnings, or keeps them errors, \
+                                   the first matching GLOB winning.\n\n\
+                                   The exit status is 0 if no errors were found, 1 if \
+                                   some were, 2 for invalid arguments and 3 if the \
+                                   docs couldn't be read."));
+        process::exit(exit_code);
+    };
+
+    let matches = opts.parse(&args[..]).unwrap_or_else(|e| {
+        println!("\n{}\n", e);
+        usage(EXIT_USAGE, &opts);
+    });
+    if matches.opt_present("h") {
+        usage(0, &opts);
+    }
+    if matches.opt_present("config-schema") {
+        for &(key, kind) in config::KEYS {
+            println!("{}: {}", key, kind);
+        }
+        process::exit(0);
+    }
+
+    let cwd = env::current_dir().unwrap();
+    let config_file = match matches.opt_str("config") {
+        Some(file) => Some(cwd.join(file)),
+        None => {
+            let default = cwd.join(DEFAULT_CONFIG);
+            if default.exists() { Some(default) } else { None }
+        }
+    };
+    let toml = config_file.as_ref().map(|file| {
+        let mut contents = String::new();
+        if let Err(err) = File::open(file).and_then(|mut f| f.read_to_string(&mut contents)) {
+            println!("failed to read configuration '{}': {}", file.display(), err);
+            process::exit(EXIT_IO);
+        }
+        let table = toml::from_str(&contents).unwrap_or_else(|err| {
+            println!("failed to parse TOML configuration '{}': {}", file.display(), err);
+            process::exit(EXIT_USAGE);
+        });
+        let unknown = config::unknown_keys(&table);
+        if !unknown.is_empty() {
+            for key in unknown {
+                match config::suggestion(key) {
+                    Some(known) => {
+                        println!("unknown option '{}' in {}, did you mean '{}'?",
+                                 key, file.display(), known)
+                    }
+                    None => println!("unknown option '{}' in {}", key, file.display()),
                 }
-                // Just in case, we also add the encoded id.
-                self.ids.insert(encoded);
-            });
+            }
+            println!("`--config-schema` lists the options");
+            process::exit(EXIT_USAGE);
+        }
+        toml::Value::Table(table).try_into().unwrap_or_else(|err| {
+            println!("invalid TOML configuration '{}': {}", file.display(), err);
+            process::exit(EXIT_USAGE);
+        })
+    }).unwrap_or_else(TomlConfig::default);
+
+    let mut options = match options_from(&matches, &toml, config_file.as_deref(), &cwd) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("\n{}\n", e);
+            usage(EXIT_USAGE, &opts);
+        }
+    };
+    let low_memory = options.cache_budget.map(|bytes| bytes / (1024 * 1024));
+
+    let mut json_summary = false;
+    set(&mut json_summary, toml.json_summary);
+    if matches.opt_present("json-summary") {
+        json_summary = true;
+    }
+    let mut summary_only_on_failure = false;
+    set(&mut summary_only_on_failure, toml.summary_only_on_failure);
+    if matches.opt_present("summary-only-on-failure") {
+        summary_only_on_failure = true;
+    }
+
+    let mut group_by_target_dir = false;
+    set(&mut group_by_target_dir, toml.group_by_target_dir);
+    if matches.opt_present("group-by-target-dir") {
+        group_by_target_dir = true;
+    }
+    let mut stats_timing = false;
+    set(&mut stats_timing, toml.stats_timing);
+    if matches.opt_present("stats-timing") {
+        stats_timing = true;
+    }
+
+    let stdin_html = matches.opt_present("stdin-html");
+    let format = match matches.opt_str("format").or(toml.format) {
+        None if stdin_html => Format::Ndjson,
+        None => Format::Human,
+        Some(ref format) if format == "human" => Format::Human,
+        Some(ref format) if format == "human-compact" => Format::HumanCompact,
+        Some(ref format) if format == "sarif" => Format::Sarif,
+        Some(ref format) if format == "codeclimate" => Format::CodeClimate,
+        Some(ref format) if format == "github" => Format::Github,
+        Some(ref format) if format == "tsv" => Format::Tsv,
+        Some(ref format) if format == "ndjson" => Format::Ndjson,
+        Some(format) => {
+            println!("\nunknown format `{}`\n", format);
+            usage(EXIT_USAGE, &opts);
         }
+    };
+    if format != Format::Human && json_summary {
+        println!("\n--json-summary can only be used with --format human\n");
+        usage(EXIT_USAGE, &opts);
     }
-}
 
-fn walk(cache: &mut Cache, root: &Path, dir: &Path, errors: &mut bool) {
-    for entry in t!(dir.read_dir()).map(|e| t!(e)) {
-        let path = entry.path();
-        let kind = t!(entry.file_type());
-        if kind.is_dir() {
-            walk(cache, root, &path, errors);
-        } else {
-            let pretty_path = check(cache, root, &path, errors);
-            if let Some(pretty_path) = pretty_path {
-                let entry = cache.get_mut(&pretty_path).unwrap();
-                // we don't need the source anymore,
-                // so drop to reduce memory-usage
-                entry.source = String::new();
+    let compare = matches.opt_str("compare").map(|other| cwd.join(other));
+    if compare.is_some() && (format != Format::Human || json_summary || stdin_html) {
+        println!("\n--compare can only be used with --format human, and without \
+                  --json-summary or --stdin-html\n");
+        usage(EXIT_USAGE, &opts);
+    }
+
+    let mut repo_root = cwd.clone();
+    if let (Some(dir), Some(file)) = (toml.repo_root, config_file.as_ref()) {
+        repo_root = file.parent().unwrap().join(dir);
+    }
+    if let Some(dir) = matches.opt_str("repo-root") {
+        repo_root = cwd.join(dir);
+    }
+
+    let mut output = None;
+    if let (Some(path), Some(file)) = (toml.output, config_file.as_ref()) {
+        output = Some(file.parent().unwrap().join(path));
+    }
+    if let Some(path) = matches.opt_str("output") {
+        output = Some(cwd.join(path));
+    }
+    let mut dump_index = None;
+    if let (Some(path), Some(file)) = (toml.dump_index, config_file.as_ref()) {
+        dump_index = Some(file.parent().unwrap().join(path));
+    }
+    if let Some(path) = matches.opt_str("dump-index") {
+        dump_index = Some(cwd.join(path));
+    }
+    if dump_index.is_some() && options.cache_budget.is_some() {
+        println!("\n--dump-index needs the ids of every page, which --low-memory drops\n");
+        usage(EXIT_USAGE, &opts);
+    }
+    let mut dump_links = None;
+    if let (Some(path), Some(file)) = (toml.dump_links, config_file.as_ref()) {
+        dump_links = Some(file.parent().unwrap().join(path));
+    }
+    if let Some(path) = matches.opt_str("dump-links") {
+        dump_links = Some(cwd.join(path));
+    }
+    // `--watch` needs to know which pages link to the ones which change.
+    options.record_links = dump_links.is_some() || matches.opt_present("watch");
+
+    // For scripts passing on a path they haven't expanded themselves, like one
+    // read from their own configuration.
+    let mut expand_env = false;
+    set(&mut expand_env, toml.expand_env);
+    if matches.opt_present("expand-env") {
+        expand_env = true;
+    }
+    let expand = |path: &str| -> String {
+        if !expand_env {
+            return path.to_string();
+        }
+        expand::vars(path, |name| env::var(name).ok()).unwrap_or_else(|name| {
+            println!("\nenvironment variable `{}` in the docs path is not set\n", name);
+            usage(EXIT_USAGE, &opts);
+        })
+    };
+    let docs_arg = matches.free.first().map(|docs| expand(docs));
+    let toml_docs = toml.docs.map(|docs| match docs.to_str() {
+        Some(docs) => PathBuf::from(expand(docs)),
+        None => docs,
+    });
+    // Paths given in the configuration file are relative to that file.
+    let toml_root = match (toml.root, config_file.as_ref()) {
+        (Some(root), Some(file)) => Some(file.parent().unwrap().join(root)),
+        _ => None,
+    };
+
+    // Deployed docs are fetched through an `HttpFs`, with URLs standing in
+    // for paths.
+    let http = docs_arg.as_ref().is_some_and(|docs| external::is_http(docs));
+    if http && !matches.opt_present("experimental-http") {
+        println!("\nchecking a URL is experimental and needs --experimental-http\n");
+        usage(EXIT_USAGE, &opts);
+    }
+    let local_root = match matches.opt_str("root") {
+        Some(root) => !external::is_http(&root),
+        None => toml_root.is_some(),
+    };
+    if http && (stdin_html || local_root) {
+        println!("\na URL can't be checked with --stdin-html or against a local --root\n");
+        usage(EXIT_USAGE, &opts);
+    }
+
+    let docs = match docs_arg.as_ref() {
+        Some(docs) if http && docs.ends_with('/') => vfs::url_path(docs).join("index.html"),
+        Some(docs) if http => vfs::url_path(docs),
+        Some(docs) => cwd.join(docs),
+        None if stdin_html => {
+            let root = matches.opt_str("root").map(|root| cwd.join(root)).or(toml_root.clone());
+            root.unwrap_or_else(|| cwd.clone()).join("stdin.html")
+        }
+        None => match (toml_docs, config_file.as_ref()) {
+            (Some(docs), Some(file)) => file.parent().unwrap().join(docs),
+            _ => usage(EXIT_USAGE, &opts),
+        },
+    };
+
+    init_logger(json_summary || format != Format::Human || output.is_some() ||
+                summary_only_on_failure || compare.is_some());
+
+    // The files in an archive appear below its path, as if it were a directory.
+    let archive = vfs::is_tar(&docs) && docs.is_file();
+    if compare.is_some() && (http || (docs.is_file() && !archive)) {
+        println!("\n--compare needs DOCS to be a directory or an archive, not a single page\n");
+        usage(EXIT_USAGE, &opts);
+    }
+    let git_base = matches.opt_str("git-base");
+    if git_base.is_some() && (http || stdin_html || archive || docs.is_file() ||
+                              compare.is_some()) {
+        println!("\n--git-base can only be used on a directory, and without --compare\n");
+        usage(EXIT_USAGE, &opts);
+    }
+    let watch = matches.opt_present("watch");
+    if watch && (http || stdin_html || archive || docs.is_file() || format != Format::Human ||
+                 json_summary || output.is_some() || summary_only_on_failure ||
+                 compare.is_some()) {
+        println!("\n--watch can only be used on a directory with --format human, and without \
+                  --json-summary, --output, --summary-only-on-failure or --compare\n");
+        usage(EXIT_USAGE, &opts);
+    }
+    let root = match (matches.opt_str("root"), toml_root) {
+        (Some(root), _) if http => vfs::url_path(&root),
+        (Some(root), _) => cwd.join(root),
+        (None, Some(root)) => root,
+        (None, None) if http => docs.parent().unwrap().to_path_buf(),
+        (None, None) if stdin_html => docs.parent().unwrap().to_path_buf(),
+        (None, None) if docs.is_file() && !archive => docs.parent().unwrap().to_path_buf(),
+        (None, None) => docs.clone(),
+    };
+
+    let run = RunConfig {
+        docs: &docs,
+        root: &root,
+        format: &format,
+        output: &output,
+        json_summary,
+        summary_only_on_failure,
+        group_by_target_dir,
+        stats_timing,
+        dump_index: &dump_index,
+        dump_links: &dump_links,
+        repo_root: &repo_root,
+        git_base: &git_base,
+        compare: &compare,
+        low_memory,
+    };
+    if matches.opt_present("print-config") {
+        match config_json(&options, &run) {
+            Ok(config) => println!("{}", config),
+            Err(err) => {
+                println!("failed to print the settings: {}", err);
+                process::exit(EXIT_USAGE);
             }
         }
+        process::exit(0);
     }
-}
 
-fn check(cache: &mut Cache,
-         root: &Path,
-         file: &Path,
-         errors: &mut bool)
-         -> Option<PathBuf> {
-    // Ignore none HTML files.
-    if file.extension().and_then(|s| s.to_str()) != Some("html") {
-        return None;
-    }
-
-    // Unfortunately we're not 100% full of valid links today to we need a few
-    // whitelists to get this past `make check` today.
-    // FIXME(#32129)
-    if file.ends_with("std/string/struct.String.html") ||
-       file.ends_with("interpret/struct.ValTy.html") ||
-       file.ends_with("symbol/struct.InternedString.html") ||
-       file.ends_with("ast/struct.ThinVec.html") ||
-       file.ends_with("util/struct.ThinVec.html") ||
-       file.ends_with("util/struct.RcSlice.html") ||
-       file.ends_with("layout/struct.TyLayout.html") ||
-       file.ends_with("ty/struct.Slice.html") ||
-       file.ends_with("ty/enum.Attributes.html") ||
-       file.ends_with("ty/struct.SymbolName.html") {
-        return None;
-    }
-    // FIXME(#32553)
-    if file.ends_with("string/struct.String.html") {
-        return None;
-    }
-    // FIXME(#32130)
-    if file.ends_with("btree_set/struct.BTreeSet.html") ||
-       file.ends_with("struct.BTreeSet.html") ||
-       file.ends_with("btree_map/struct.BTreeMap.html") ||
-       file.ends_with("hash_map/struct.HashMap.html") ||
-       file.ends_with("hash_set/struct.HashSet.html") ||
-       file.ends_with("sync/struct.Lrc.html") ||
-       file.ends_with("sync/struct.RwLock.html") {
-        return None;
-    }
-    // FIXME(#47038)
-    if file.ends_with("deriving/generic/index.html") ||
-       file.ends_with("deriving/generic/macro.vec.html") ||
-       file.ends_with("deriving/custom/macro.panic.html") ||
-       file.ends_with("proc_macro_impl/macro.panic.html") {
-        return None;
-    }
-
-    let res = load_file(cache, root, file, SkipRedirect);
-    let (pretty_file, contents) = match res {
-        Ok(res) => res,
-        Err(_) => return None,
+    // The page read from stdin doesn't need to exist, and a deployed one is
+    // only fetched once it's checked.
+    if !stdin_html && !http {
+        if let Err(err) = docs.metadata() {
+            println!("failed to read '{}': {}", docs.display(), err);
+            process::exit(EXIT_IO);
+        }
+    }
+
+    // The older version is checked the same way, as a whole tree. The ids
+    // cached for DOCS would be taken for its own, as pages are only known by
+    // their path relative to the root.
+    let other = compare.as_ref().map(|other| {
+        if let Err(err) = other.metadata() {
+            println!("failed to read '{}': {}", other.display(), err);
+            process::exit(EXIT_IO);
+        }
+        let archive = vfs::is_tar(other) && other.is_file();
+        let options = Options { id_cache: None, ..options.clone() };
+        let mut checker = open_checker(other, other, archive, options);
+        if let Err(err) = checker.run(other) {
+            eprintln!("{}", err);
+            process::exit(EXIT_IO);
+        }
+        checker
+    });
+
+    // A single page is checked against the others, which then only need to be
+    // scanned as far as its links need.
+    options.lazy_ids = stdin_html || http || (docs.is_file() && !archive) || git_base.is_some();
+    let mut checker = if http {
+        Checker::with_file_system(&root, options, Box::new(HttpFs::new()))
+    } else {
+        open_checker(&docs, &root, archive, options)
     };
-    {
-        cache.get_mut(&pretty_file)
-             .unwrap()
-             .parse_ids(&pretty_file, &contents, errors);
-    }
-
-    // Search for anything that's the regex 'href[ ]*=[ ]*".*?"'
-    with_attrs_in_source(&contents, " href", |url, i, base| {
-        // Ignore external URLs
-        if url.starts_with("http:") || url.starts_with("https:") ||
-           url.starts_with("javascript:") || url.starts_with("ftp:") ||
-           url.starts_with("irc:") || url.starts_with("data:") {
-            return;
+    if stdin_html {
+        let mut contents = Vec::new();
+        if let Err(err) = io::stdin().read_to_end(&mut contents) {
+            println!("failed to read stdin: {}", err);
+            process::exit(EXIT_IO);
         }
-        let mut parts = url.splitn(2, "#");
-        let url = parts.next().unwrap();
-        let fragment = parts.next();
-        let mut parts = url.splitn(2, "?");
-        let url = parts.next().unwrap();
-
-        // Once we've plucked out the URL, parse it using our base url and
-        // then try to extract a file path.
-        let mut path = file.to_path_buf();
-        if !base.is_empty() || !url.is_empty() {
-            path.pop();
-            for part in Path::new(base).join(url).components() {
-                match part {
-                    Component::Prefix(_) |
-                    Component::RootDir => panic!(),
-                    Component::CurDir => {}
-                    Component::ParentDir => { path.pop(); }
-                    Component::Normal(s) => { path.push(s); }
-                }
+        let contents = String::from_utf8_lossy(&contents);
+        let contents = contents.trim_start_matches('\u{feff}');
+        if let Err(LoadError::IsRedirect) = checker.check_html(&docs, contents) {
+            eprintln!("stdin is a redirect, there's nothing to check");
+        }
+    } else if let Some(ref base) = git_base {
+        let files = git::changed_files(&docs, base).unwrap_or_else(|err| {
+            println!("failed to find the pages changed since `{}`: {}", base, err);
+            process::exit(EXIT_IO);
+        });
+        eprintln!("checking {} files changed since {}", files.len(), base);
+        for file in files {
+            // Problems reading files are recorded as errors already.
+            let _ = checker.check_file(&file);
+        }
+    } else {
+        if let Err(err) = checker.run(&docs) {
+            eprintln!("{}", err);
+            process::exit(EXIT_IO);
+        }
+        let single_page = http || (docs.is_file() && !archive);
+        if single_page && checker.stats().files_checked == 0 {
+            eprintln!("{} is a redirect or ignored, there's nothing to check", docs.display());
+        }
+    }
+    if stdin_html || git_base.is_some() {
+        checker.check_external();
+    }
+    if let Some(ref path) = dump_index {
+        let json = serde_json::to_string(&checker.id_index()).unwrap();
+        if let Err(err) = File::create(path).and_then(|mut f| f.write_all(json.as_bytes())) {
+            println!("failed to write '{}': {}", path.display(), err);
+            process::exit(EXIT_IO);
+        }
+    }
+    if let Some(ref path) = dump_links {
+        let links = checker.resolved_links();
+        let contents = if path.extension().is_some_and(|ext| ext == "tsv") {
+            let mut rows = vec![tsv::LINKS_HEADER.to_string()];
+            rows.extend(links.iter().map(tsv::link_row));
+            rows.push(String::new());
+            rows.join("\n")
+        } else {
+            serde_json::to_string(&links).unwrap()
+        };
+        if let Err(err) = File::create(path).and_then(|mut f| f.write_all(contents.as_bytes())) {
+            println!("failed to write '{}': {}", path.display(), err);
+            process::exit(EXIT_IO);
+        }
+    }
+    let mut out: Box<dyn Write> = match output {
+        Some(ref path) => match File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(err) => {
+                println!("failed to create '{}': {}", path.display(), err);
+                process::exit(EXIT_IO);
             }
+        },
+        None => Box::new(io::stdout()),
+    };
+    let drift = other.as_ref().map(|other| drift::diff(other.errors(), checker.errors()));
+    let compared = other.as_ref().zip(drift.as_ref());
+    // A reader going away, like `head`, isn't worth a panic.
+    if let Err(err) = write_report(&mut out, &checker, compared, &run, start.elapsed()) {
+        if err.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("failed to write the report: {}", err);
+        }
+        process::exit(EXIT_IO);
+    }
+    drop(out);
+    if stats_timing {
+        eprintln!("timing: {}", checker.timings());
+    }
+    log_run_time(start.elapsed());
+    if watch {
+        watch_docs(&mut checker, &docs);
+    }
+    // Either version of the docs being incomplete makes the comparison moot.
+    let unreadable = |checker: &Checker| {
+        checker.options().strict_io &&
+            checker.errors().iter().any(|err| err.kind == ErrorKind::UnreadableDir)
+    };
+    if unreadable(&checker) || other.as_ref().is_some_and(unreadable) {
+        eprintln!("some of the docs couldn't be read");
+        process::exit(EXIT_IO);
+    }
+    if let Some(drift) = drift {
+        if !drift.broken.is_empty() {
+            eprintln!("found some newly broken links");
+            process::exit(EXIT_LINK_ERRORS);
         }
+    } else if checker.has_errors() {
+        eprintln!("found some broken links");
+        process::exit(EXIT_LINK_ERRORS);
+    }
+}
 
-        // Alright, if we've found a file name then this file had better
-        // exist! If it doesn't then we register and print an error.
-        if path.exists() {
-            if path.is_dir() {
-                // Links to directories show as directory listings when viewing
-                // the docs offline so it's best to avoid them.
-                *errors = true;
-                let pretty_path = path.strip_prefix(root).unwrap_or(&path);
-                println!("{}:{}: directory link - {}",
-                         pretty_file.display(),
-                         i + 1,
-                         pretty_path.display());
-                return;
+/// Writes the report of `checker` on the run `run` to `out`: its diagnostics
+/// or, with the other docs of `--compare` and how they differ, that
+/// difference, followed by the summary.
+fn write_report(out: &mut dyn Write,
+                checker: &Checker,
+                compared: Option<(&Checker, &Drift)>,
+                run: &RunConfig,
+                elapsed: Duration) -> io::Result<()> {
+    let repo_root = run.repo_root.canonicalize().unwrap_or_else(|_| run.repo_root.to_path_buf());
+    let root = run.root.canonicalize().unwrap_or_else(|_| run.root.to_path_buf());
+    let repo_relative_root = match checker.options().relative_to {
+        Some(ref base) => relative_path(base, &root),
+        None => root.strip_prefix(&repo_root).unwrap_or(&root).to_path_buf(),
+    };
+    // With `--summary-only-on-failure` the diagnostics were held back too, and
+    // a passing check prints nothing at all.
+    if let Some((other, drift)) = compared {
+        if !run.summary_only_on_failure || !drift.broken.is_empty() {
+            for line in drift.lines(other, checker) {
+                writeln!(out, "{}", line)?;
+            }
+        }
+    } else if !run.summary_only_on_failure || checker.has_errors() {
+        match *run.format {
+            Format::Sarif => {
+                let log = sarif::report(checker.errors(), &repo_relative_root);
+                serde_json::to_writer_pretty(&mut *out, &log)?;
+                writeln!(out)?;
             }
-            if let Some(extension) = path.extension() {
-                // Ignore none HTML files.
-                if extension != "html" {
-                    return;
+            Format::CodeClimate => {
+                let issues = codeclimate::report(checker.errors(), &repo_relative_root);
+                serde_json::to_writer_pretty(&mut *out, &issues)?;
+                writeln!(out)?;
+            }
+            Format::Github => {
+                for err in checker.errors() {
+                    writeln!(out, "{}", github::annotation(err, &repo_relative_root))?;
+                }
+            }
+            Format::Ndjson => {
+                for err in checker.errors() {
+                    let err = checker.display_error(err);
+                    serde_json::to_writer(&mut *out, &err)?;
+                    writeln!(out)?;
                 }
             }
-            let res = load_file(cache, root, &path, FromRedirect(false));
-            let (pretty_path, contents) = match res {
-                Ok(res) => res,
-                Err(LoadError::IOError(err)) => {
-                    panic!("error loading {}: {}", path.display(), err);
+            Format::HumanCompact => {
+                let errors = checker.errors()
+                                    .iter()
+                                    .map(|err| checker.display_error(err).into_owned())
+                                    .collect::<Vec<_>>();
+                for line in compact::lines(&errors) {
+                    writeln!(out, "{}", line)?;
                 }
-                Err(LoadError::BrokenRedirect(target, _)) => {
-                    *errors = true;
-                    println!("{}:{}: broken redirect to {}",
-                             pretty_file.display(),
-                             i + 1,
-                             target.display());
-                    return;
+            }
+            Format::Tsv => {
+                writeln!(out, "{}", tsv::HEADER)?;
+                for err in checker.errors() {
+                    writeln!(out, "{}", tsv::row(err, &repo_relative_root))?;
                 }
-                Err(LoadError::IsRedirect) => unreachable!(),
-            };
-
-            if let Some(ref fragment) = fragment {
-                // Fragments like `#1-6` are most likely line numbers to be
-                // interpreted by javascript, so we're ignoring these
-                if fragment.splitn(2, '-')
-                           .all(|f| f.chars().all(|c| c.is_numeric())) {
-                    return;
+            }
+            // Without `--output` the diagnostics were already printed by the
+            // logger as they were found.
+            Format::Human if (run.output.is_some() || run.summary_only_on_failure) &&
+                             !run.json_summary => {
+                for err in checker.errors() {
+                    writeln!(out, "{}", checker.display_error(err))?;
                 }
+            }
+            Format::Human => {}
+        }
+        let mut summary = Vec::new();
+        if run.group_by_target_dir {
+            summary.extend(group_by_target_dir_report(checker.errors()));
+        }
+        // The JSON summary has these already.
+        if !run.json_summary {
+            let (links, targets) = broken_link_counts(checker.errors());
+            if links > 0 {
+                summary.push(format!("{} broken links to {} distinct targets", links, targets));
+            }
+            summary.extend(external_hosts_report(&checker.stats().external_hosts));
+            let evicted = checker.stats().pages_evicted;
+            if evicted > 0 {
+                summary.push(format!("dropped pages from memory {} times to stay within \
+                                      --low-memory", evicted));
+            }
+            let urls = &checker.stats().external_urls;
+            if !urls.is_empty() {
+                summary.push(format!("{} external URLs:", urls.len()));
+                summary.extend(urls.iter().map(|url| format!("    {}", url)));
+            }
+        }
+        for line in summary {
+            if run.json_summary || *run.format == Format::Sarif ||
+               *run.format == Format::CodeClimate || *run.format == Format::Tsv ||
+               *run.format == Format::Ndjson {
+                eprintln!("{}", line);
+            } else {
+                writeln!(out, "{}", line)?;
+            }
+        }
+        if run.json_summary {
+            writeln!(out, "{}", summary_json(checker, elapsed)?)?;
+        }
+    }
+    Ok(())
+}
 
-                let entry = &mut cache.get_mut(&pretty_path).unwrap();
-                entry.parse_ids(&pretty_path, &contents, errors);
+/// The command line options, which `main` parses.
+fn command_line() -> getopts::Options {
+    let mut opts = getopts::Options::new();
+    opts.optopt("", "config", "TOML configuration file", "FILE");
+    opts.optopt("", "root", "resolve links against DIR instead of DOCS", "DIR");
+    opts.optopt("", "static-root", "look for assets missing from the root in DIR", "DIR");
+    opts.optmulti("", "ignore", "skip files whose path ends with PATH", "PATH");
+    opts.optflag("", "report-absolute-links", "warn about `/`-leading hrefs and srcs");
+    opts.optflag("", "report-mixed-content", "warn about assets loaded over `http:`");
+    opts.optflag("", "check-link-text", "warn about links without text for screen readers");
+    opts.optflag("", "no-follow-symlinks", "skip symbolic links instead of following them");
+    opts.optflag("", "lint-structure", "warn about empty `id` and `href` attributes");
+    opts.optflag("", "check-canonical", "check `<link rel=\"canonical\">` targets");
+    opts.optflag("", "check-navigation", "warn about `rel=\"next\"` and `rel=\"prev\"` links \
+                                          which don't form a consistent reading order");
+    opts.optflag("", "report-orphans", "warn about pages no other page links to");
+    opts.optflag("", "treat-redirect-as-error", "report links leading to redirect pages");
+    opts.optflag("", "strict-fragments", "require an element with id `top` for `#top`");
+    opts.optflag("", "check-hidden-fragments", "warn about links within a page to elements \
+                                                which are hidden");
+    opts.optflag("", "warn-self-links", "warn about links whose path leads back to the page \
+                                         they're on");
+    opts.optflag("", "fix-suggestions", "suggest similarly named files for broken links");
+    opts.optflag("", "suggest-fragment-pages", "name the pages next to the one a broken \
+                                                fragment points to which have its id");
+    opts.optflag("", "fuzzy-fragments", "accept `#foo` if the page has `foo-1`, `foo-2`, ...");
+    opts.optflag("", "markdown", "also check the links of Markdown sources (`.md` files); \
+                                  experimental");
+    opts.optflag("", "case-insensitive-fragments", "compare fragments and ids in lowercase, for \
+                                                    servers which lowercase anchors");
+    opts.optflag("", "verify-no-javascript-dependency", "report links which only work with \
+                                                         javascript");
+    opts.optflag("", "cross-file-ids", "warn about ids defined on more than one page");
+    opts.optopt("", "extra-attrs", "also check links in the comma-separated ATTRS, like \
+                                     `data-href`", "ATTRS");
+    opts.optmulti("", "exclude-fragments-for", "don't check fragments of links to pages \
+                                                matching GLOB, like `src/**/*.html`", "GLOB");
+    opts.optflag("", "warn-query-links", "warn about relative links with a `?query`");
+    opts.optmulti("", "rewrite", "rewrite the path below DOCS links lead to with RULE, \
+                                  like `s#^latest/#1.75.0/#`, before looking for the file",
+                  "RULE");
+    opts.optmulti("", "skip-scheme", "don't check links with the URL scheme NAME, like `rust` \
+                                      for `rust:std::vec`", "NAME");
+    opts.optopt("", "ignore-extension", "don't report missing files with one of the \
+                                         comma-separated EXTS, like `map,woff2`", "EXTS");
+    opts.optflag("", "list-external", "list every external URL linked to, checked or not");
+    opts.optmulti("", "allow", "don't report warnings of the given KIND", "KIND");
+    opts.optmulti("", "severity", "report errors on pages matching GLOB as LEVEL, `error` or \
+                                   `warning`, like `src/**=warning`", "GLOB=LEVEL");
+    opts.optflag("", "json-summary", "only print a JSON object with the final counts");
+    opts.optflag("", "summary-only-on-failure", "print nothing unless the check fails");
+    opts.optflag("", "group-by-target-dir", "summarize broken links by target directory");
+    opts.optflag("", "stats-timing", "print where the time went to stderr");
+    opts.optopt("", "output", "write the report to FILE instead of stdout", "FILE");
+    opts.optopt("", "dump-index", "write the ids of each page to FILE as JSON", "FILE");
+    opts.optopt("", "dump-links", "write every link which resolved to FILE, as TSV if it ends \
+                                   in `.tsv` and as JSON otherwise", "FILE");
+    opts.optopt("", "format", "report format: `human` (default), `human-compact`, `sarif`, \
+                               `codeclimate`, `github`, `tsv` or `ndjson`", "FORMAT");
+    opts.optflag("", "stdin-html", "check a page read from stdin, as if it were DOCS");
+    opts.optflag("", "experimental-http", "allow DOCS to be the URL of a deployed page, whose \
+                                           links are fetched with curl");
+    opts.optopt("", "compare", "also check the docs in OTHER, an older version of DOCS, and \
+                                only report the links broken in one of them", "OTHER");
+    opts.optopt("", "repo-root", "report paths relative to DIR for `sarif`, `codeclimate`, \
+                                   `github` and `tsv` (default: the current directory)", "DIR");
+    opts.optopt("", "relative-to", "report paths relative to DIR in every format", "DIR");
+    opts.optflag("", "strict-io", "stop at the first directory which can't be read, instead of \
+                                   warning about it");
+    opts.optopt("", "assume-extension", "try links without an extension which don't lead \
+                                         anywhere again with `.EXT` appended, for servers \
+                                         which add it", "EXT");
+    opts.optflag("", "expand-env", "replace `$VAR` and `${VAR}` in DOCS by the value of the \
+                                    environment variable");
+    opts.optflagopt("", "low-memory", "keep at most about MB megabytes of pages in memory, 256 \
+                                       by default, reading them again as needed", "MB");
+    opts.optopt("", "stream-threshold", "read pages bigger than BYTES a bit at a time, only \
+                                         checking their links", "BYTES");
+    opts.optopt("", "index-name", "file name of the page shown for a directory (default \
+                                   `index.html`)", "NAME");
+    opts.optopt("", "max-depth", "only check pages up to N directories below DOCS", "N");
+    opts.optopt("", "jobs", "check pages on N threads, reporting problems sorted at the end \
+                             (default 1)", "N");
+    opts.optopt("", "id-cache", "remember the ids of unchanged pages in FILE", "FILE");
+    opts.optflag("", "check-external", "also check `http:` and `https:` links using curl");
+    opts.optopt("", "external-retries", "retry transient failures N times (default 3)", "N");
+    opts.optopt("", "external-cache", "remember working external links in FILE", "FILE");
+    opts.optopt("", "external-cache-ttl", "how long cached links stay valid (default 1 day)",
+                "SECS");
+    opts.optopt("", "external-max-per-host", "make at most N requests to a host at once \
+                                               (default 2)", "N");
+    opts.optopt("", "external-rate", "start at most N requests per second, or 0 for no \
+                                       limit (default 10)", "N");
+    opts.optopt("", "git-base", "only check the pages of DOCS which changed since the git \
+                                 commit REF", "REF");
+    opts.optflag("", "watch", "keep checking the pages of DOCS which change, until killed");
+    opts.optflag("", "print-config", "print the settings in effect as JSON and exit");
+    opts.optflag("", "config-schema", "print the options of the configuration file and the \
+                                       type of their values, and exit");
+    opts.optflag("h", "help", "print this help message");
+    opts
+}
 
-                if !entry.ids.contains(*fragment) {
-                    *errors = true;
-                    print!("{}:{}: broken link fragment ",
-                           pretty_file.display(),
-                           i + 1);
-                    println!("`#{}` pointing to `{}`", fragment, pretty_path.display());
-                };
+/// The `Options` set by the command line `matches` and the configuration
+/// file `config_file` holding `toml`, where the command line wins. Relative
+/// paths are resolved against `cwd`, or the directory of the configuration
+/// file when given in it. Fails on invalid values.
+fn options_from(matches: &getopts::Matches,
+                toml: &TomlConfig,
+                config_file: Option<&Path>,
+                cwd: &Path) -> Result<Options, String> {
+    let mut options = Options::default();
+    set(&mut options.ignore, toml.ignore.clone());
+    if matches.opt_present("ignore") {
+        options.ignore = matches.opt_strs("ignore");
+    }
+    set(&mut options.report_absolute_links, toml.report_absolute_links);
+    if matches.opt_present("report-absolute-links") {
+        options.report_absolute_links = true;
+    }
+    set(&mut options.report_mixed_content, toml.report_mixed_content);
+    if matches.opt_present("report-mixed-content") {
+        options.report_mixed_content = true;
+    }
+    set(&mut options.check_link_text, toml.check_link_text);
+    if matches.opt_present("check-link-text") {
+        options.check_link_text = true;
+    }
+    set(&mut options.no_follow_symlinks, toml.no_follow_symlinks);
+    if matches.opt_present("no-follow-symlinks") {
+        options.no_follow_symlinks = true;
+    }
+    set(&mut options.lint_structure, toml.lint_structure);
+    if matches.opt_present("lint-structure") {
+        options.lint_structure = true;
+    }
+    set(&mut options.check_canonical, toml.check_canonical);
+    if matches.opt_present("check-canonical") {
+        options.check_canonical = true;
+    }
+    set(&mut options.check_navigation, toml.check_navigation);
+    if matches.opt_present("check-navigation") {
+        options.check_navigation = true;
+    }
+    set(&mut options.report_orphans, toml.report_orphans);
+    if matches.opt_present("report-orphans") {
+        options.report_orphans = true;
+    }
+    set(&mut options.treat_redirect_as_error, toml.treat_redirect_as_error);
+    if matches.opt_present("treat-redirect-as-error") {
+        options.treat_redirect_as_error = true;
+    }
+    set(&mut options.strict_fragments, toml.strict_fragments);
+    if matches.opt_present("strict-fragments") {
+        options.strict_fragments = true;
+    }
+    set(&mut options.check_hidden_fragments, toml.check_hidden_fragments);
+    if matches.opt_present("check-hidden-fragments") {
+        options.check_hidden_fragments = true;
+    }
+    set(&mut options.warn_self_links, toml.warn_self_links);
+    if matches.opt_present("warn-self-links") {
+        options.warn_self_links = true;
+    }
+    set(&mut options.fix_suggestions, toml.fix_suggestions);
+    if matches.opt_present("fix-suggestions") {
+        options.fix_suggestions = true;
+    }
+    set(&mut options.suggest_fragment_pages, toml.suggest_fragment_pages);
+    if matches.opt_present("suggest-fragment-pages") {
+        options.suggest_fragment_pages = true;
+    }
+    set(&mut options.fuzzy_fragments, toml.fuzzy_fragments);
+    if matches.opt_present("fuzzy-fragments") {
+        options.fuzzy_fragments = true;
+    }
+    set(&mut options.markdown, toml.markdown);
+    if matches.opt_present("markdown") {
+        options.markdown = true;
+    }
+    set(&mut options.case_insensitive_fragments, toml.case_insensitive_fragments);
+    if matches.opt_present("case-insensitive-fragments") {
+        options.case_insensitive_fragments = true;
+    }
+    set(&mut options.verify_no_javascript_dependency, toml.verify_no_javascript_dependency);
+    if matches.opt_present("verify-no-javascript-dependency") {
+        options.verify_no_javascript_dependency = true;
+    }
+    set(&mut options.cross_file_ids, toml.cross_file_ids);
+    if matches.opt_present("cross-file-ids") {
+        options.cross_file_ids = true;
+    }
+    set(&mut options.extra_attrs, toml.extra_attrs.clone());
+    if let Some(attrs) = matches.opt_str("extra-attrs") {
+        options.extra_attrs = attrs.split(',')
+                                   .filter(|attr| !attr.is_empty())
+                                   .map(|attr| attr.to_string())
+                                   .collect();
+    }
+    let mut exclude_fragments_for = toml.exclude_fragments_for.clone().unwrap_or_default();
+    if matches.opt_present("exclude-fragments-for") {
+        exclude_fragments_for = matches.opt_strs("exclude-fragments-for");
+    }
+    options.exclude_fragments_for = exclude_fragments_for.iter().map(|glob| {
+        Pattern::new(glob).map_err(|e| {
+            format!("invalid --exclude-fragments-for `{}`: {}", glob, e)
+        })
+    }).collect::<Result<_, _>>()?;
+    let mut rewrites = toml.rewrite.clone().unwrap_or_default();
+    if matches.opt_present("rewrite") {
+        rewrites = matches.opt_strs("rewrite");
+    }
+    options.rewrites = rewrites.iter().map(|rule| {
+        Rewrite::parse(rule).map_err(|e| format!("invalid --rewrite: {}", e))
+    }).collect::<Result<_, _>>()?;
+    set(&mut options.skip_schemes, toml.skip_scheme.clone());
+    if matches.opt_present("skip-scheme") {
+        options.skip_schemes = matches.opt_strs("skip-scheme");
+    }
+    let mut ignore_extensions = toml.ignore_extension.clone().unwrap_or_default();
+    if let Some(extensions) = matches.opt_str("ignore-extension") {
+        ignore_extensions = extensions.split(',').map(|ext| ext.to_string()).collect();
+    }
+    options.ignore_extensions = ignore_extensions.iter()
+                                                 .map(|ext| ext.trim_start_matches('.'))
+                                                 .filter(|ext| !ext.is_empty())
+                                                 .map(|ext| ext.to_string())
+                                                 .collect();
+    set(&mut options.list_external, toml.list_external);
+    if matches.opt_present("list-external") {
+        options.list_external = true;
+    }
+    set(&mut options.warn_query_links, toml.warn_query_links);
+    if matches.opt_present("warn-query-links") {
+        options.warn_query_links = true;
+    }
+    // Like `root`, relative to the configuration file it's given in.
+    if let (Some(dir), Some(file)) = (toml.static_root.as_ref(), config_file) {
+        options.static_root = Some(file.parent().unwrap().join(dir));
+    }
+    if let Some(dir) = matches.opt_str("static-root") {
+        options.static_root = Some(cwd.join(dir));
+    }
+    set(&mut options.jobs, toml.jobs);
+    if let Some(n) = matches.opt_str("jobs") {
+        options.jobs = n.parse().map_err(|e| format!("invalid --jobs `{}`: {}", n, e))?;
+    }
+    set(&mut options.stream_threshold, toml.stream_threshold.map(Some));
+    if let Some(n) = matches.opt_str("stream-threshold") {
+        options.stream_threshold = Some(n.parse().map_err(|e| {
+            format!("invalid --stream-threshold `{}`: {}", n, e)
+        })?);
+    }
+    let mut low_memory = toml.low_memory;
+    if matches.opt_present("low-memory") {
+        low_memory = Some(match matches.opt_str("low-memory") {
+            Some(mb) => mb.parse().map_err(|e| format!("invalid --low-memory `{}`: {}", mb, e))?,
+            None => 256,
+        });
+    }
+    if let Some(mb) = low_memory {
+        options.cache_budget = Some(mb.checked_mul(1024 * 1024).ok_or_else(|| {
+            format!("invalid --low-memory `{}`: too many megabytes", mb)
+        })?);
+    }
+    set(&mut options.max_depth, toml.max_depth.map(Some));
+    if let Some(n) = matches.opt_str("max-depth") {
+        options.max_depth = Some(n.parse().map_err(|e| {
+            format!("invalid --max-depth `{}`: {}", n, e)
+        })?);
+    }
+    // Like `root`, relative to the configuration file it's given in.
+    if let (Some(cache), Some(file)) = (toml.id_cache.as_ref(), config_file) {
+        options.id_cache = Some(file.parent().unwrap().join(cache));
+    }
+    if let Some(cache) = matches.opt_str("id-cache") {
+        options.id_cache = Some(cwd.join(cache));
+    }
+    let mut allow = toml.allow.clone().unwrap_or_default();
+    if matches.opt_present("allow") {
+        allow = matches.opt_strs("allow");
+    }
+    options.allow = allow.iter().map(|kind| kind.parse()).collect::<Result<_, _>>()?;
+    let mut severities = toml.severity.clone().unwrap_or_default();
+    if matches.opt_present("severity") {
+        severities = matches.opt_strs("severity");
+    }
+    options.severities = severities.iter().map(|rule| {
+        severity_rule(rule).map_err(|e| format!("invalid --severity `{}`: {}", rule, e))
+    }).collect::<Result<_, _>>()?;
+
+    set(&mut options.check_external, toml.check_external);
+    if matches.opt_present("check-external") {
+        options.check_external = true;
+    }
+    set(&mut options.external_retries, toml.external_retries);
+    if let Some(n) = matches.opt_str("external-retries") {
+        options.external_retries = n.parse().map_err(|e| {
+            format!("invalid --external-retries `{}`: {}", n, e)
+        })?;
+    }
+    // Like `root`, a cache file given in the configuration file is relative
+    // to that file.
+    if let (Some(cache), Some(file)) = (toml.external_cache.as_ref(), config_file) {
+        options.external_cache = Some(file.parent().unwrap().join(cache));
+    }
+    if let Some(cache) = matches.opt_str("external-cache") {
+        options.external_cache = Some(cwd.join(cache));
+    }
+    set(&mut options.external_cache_ttl, toml.external_cache_ttl);
+    if let Some(ttl) = matches.opt_str("external-cache-ttl") {
+        options.external_cache_ttl = ttl.parse().map_err(|e| {
+            format!("invalid --external-cache-ttl `{}`: {}", ttl, e)
+        })?;
+    }
+
+    set(&mut options.external_max_per_host, toml.external_max_per_host);
+    if let Some(n) = matches.opt_str("external-max-per-host") {
+        options.external_max_per_host = n.parse().map_err(|e| {
+            format!("invalid --external-max-per-host `{}`: {}", n, e)
+        })?;
+    }
+    set(&mut options.external_rate, toml.external_rate);
+    if let Some(n) = matches.opt_str("external-rate") {
+        options.external_rate = n.parse().map_err(|e| {
+            format!("invalid --external-rate `{}`: {}", n, e)
+        })?;
+    }
+    if let (Some(dir), Some(file)) = (toml.relative_to.as_ref(), config_file) {
+        options.relative_to = Some(file.parent().unwrap().join(dir));
+    }
+    if let Some(dir) = matches.opt_str("relative-to") {
+        options.relative_to = Some(cwd.join(dir));
+    }
+    options.relative_to = options.relative_to.map(|dir| dir.canonicalize().unwrap_or(dir));
+    set(&mut options.strict_io, toml.strict_io);
+    if matches.opt_present("strict-io") {
+        options.strict_io = true;
+    }
+    options.assume_extension = matches.opt_str("assume-extension")
+                                      .or_else(|| toml.assume_extension.clone())
+                                      .map(|ext| ext.trim_start_matches('.').to_string());
+    set(&mut options.index_name, toml.index_name.clone());
+    if let Some(name) = matches.opt_str("index-name") {
+        options.index_name = name;
+    }
+    Ok(options)
+}
+
+/// Checks the pages below `docs` again whenever they change, forever. The
+/// problems found are printed by the logger as usual.
+fn watch_docs(checker: &mut Checker, docs: &Path) -> ! {
+    eprintln!("watching {} for changes", docs.display());
+    let follow_symlinks = !checker.options().no_follow_symlinks;
+    let mut files = watch::snapshot(docs, follow_symlinks);
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        let new_files = watch::snapshot(docs, follow_symlinks);
+        let changed = watch::changes(&files, &new_files);
+        files = new_files;
+        if changed.is_empty() {
+            continue;
+        }
+        // The pages linking to the ones which changed are checked again too,
+        // as their links may work now, or not anymore.
+        let mut recheck = BTreeSet::new();
+        for file in &changed {
+            recheck.extend(checker.forget(file).into_iter().map(|page| docs.join(page)));
+            recheck.insert(file.clone());
+        }
+        for page in &recheck {
+            if !changed.contains(page) {
+                checker.forget(page);
             }
-        } else {
-            *errors = true;
-            print!("{}:{}: broken link - ", pretty_file.display(), i + 1);
-            let pretty_path = path.strip_prefix(root).unwrap_or(&path);
-            println!("{}", pretty_path.display());
         }
-    });
-    Some(pretty_file)
+        let start = checker.errors().len();
+        for file in recheck.iter().filter(|file| files.contains_key(*file)) {
+            let _ = checker.check_file(file);
+        }
+        let errors = checker.errors()[start..].iter()
+                                              .filter(|err| err.severity == Severity::Error)
+                                              .count();
+        eprintln!("checked {} changed files and {} pages linking to them, found {} errors",
+                  changed.len(), recheck.len() - changed.len(), errors);
+    }
 }
 
-fn load_file(cache: &mut Cache,
-             root: &Path,
-             file: &Path,
-             redirect: Redirect)
-             -> Result<(PathBuf, String), LoadError> {
-    let mut contents = String::new();
-    let pretty_file = PathBuf::from(file.strip_prefix(root).unwrap_or(&file));
-
-    let maybe_redirect = match cache.entry(pretty_file.clone()) {
-        Entry::Occupied(entry) => {
-            contents = entry.get().source.clone();
-            None
-        }
-        Entry::Vacant(entry) => {
-            let mut fp = File::open(file).map_err(|err| {
-                if let FromRedirect(true) = redirect {
-                    LoadError::BrokenRedirect(file.to_path_buf(), err)
-                } else {
-                    LoadError::IOError(err)
-                }
-            })?;
-            fp.read_to_string(&mut contents).map_err(|err| LoadError::IOError(err))?;
+/// Creates a checker for the docs at `docs`, unpacking them from the archive
+/// into memory if `archive` is set.
+fn open_checker(docs: &Path, root: &Path, archive: bool, options: Options) -> Checker {
+    if !archive {
+        return Checker::new(root, options);
+    }
+    match MemoryFs::unpack_tar(docs) {
+        Ok(fs) => Checker::with_file_system(root, options, Box::new(fs)),
+        Err(err) => {
+            println!("failed to read archive '{}': {}", docs.display(), err);
+            process::exit(EXIT_IO);
+        }
+    }
+}
 
-            let maybe = maybe_redirect(&contents);
-            if maybe.is_some() {
-                if let SkipRedirect = redirect {
-                    return Err(LoadError::IsRedirect);
-                }
-            } else {
-                entry.insert(FileEntry {
-                    source: contents.clone(),
-                    ids: HashSet::new(),
-                });
-            }
-            maybe
+/// Summarizes broken link targets by their parent directory, so a systemic
+/// failure such as a crate missing its docs stands out. Directories are
+/// listed from the most to the least broken links.
+fn group_by_target_dir_report(errors: &[LinkError]) -> Vec<String> {
+    let mut groups = BTreeMap::new();
+    for err in errors {
+        if err.severity != Severity::Error || err.kind == ErrorKind::DuplicateId {
+            continue;
         }
-    };
-    match maybe_redirect.map(|url| file.parent().unwrap().join(url)) {
-        Some(redirect_file) => {
-            load_file(cache, root, &redirect_file, FromRedirect(true))
+        let dir = Path::new(&err.target).parent().unwrap_or(Path::new(""));
+        *groups.entry(dir.to_path_buf()).or_insert(0) += 1;
+    }
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.sort_by_key(|&(_, count)| Reverse(count));
+    groups.into_iter().map(|(dir, count)| {
+        if dir == Path::new("") {
+            format!("{} broken links under the docs root", count)
+        } else {
+            format!("{} broken links under {}/", count, dir.display())
+        }
+    }).collect()
+}
+
+/// Counts the broken links, like `group_by_target_dir_report`, and the
+/// distinct targets (with their fragment) they point to, which tells one
+/// missing page linked to everywhere from many unrelated mistakes.
+fn broken_link_counts(errors: &[LinkError]) -> (usize, usize) {
+    let broken = errors.iter()
+                       .filter(|err| err.severity == Severity::Error &&
+                                     err.kind != ErrorKind::DuplicateId)
+                       .map(|err| (resolved_target(err), &err.fragment))
+                       .collect::<Vec<_>>();
+    let targets = broken.iter().collect::<BTreeSet<_>>().len();
+    (broken.len(), targets)
+}
+
+/// The file a problem points to, relative to the docs root, however the link
+/// spelled it. Most kinds of problems give the target resolved already, but
+/// some give the link as written, whose `.` and `..` are resolved here.
+fn resolved_target(err: &LinkError) -> PathBuf {
+    if err.kind == ErrorKind::BrokenExternalLink {
+        return PathBuf::from(&err.target);
+    }
+    let mut path = PathBuf::new();
+    for part in Path::new(&err.target).components() {
+        match part {
+            Component::CurDir => {}
+            Component::ParentDir => { path.pop(); }
+            part => path.push(part.as_os_str()),
         }
-        None => Ok((pretty_file, contents)),
     }
+    path
 }
 
-fn maybe_redirect(source: &str) -> Option<String> {
-    const REDIRECT: &'static str = "<p>Redirecting to <a href=";
+/// Summarizes external links by host, so that a host which is down, or
+/// which the docs rely on a lot, stands out. Hosts are listed from the most to
+/// the least links.
+fn external_hosts_report(hosts: &BTreeMap<String, HostStats>) -> Vec<String> {
+    let mut hosts = hosts.iter().collect::<Vec<_>>();
+    hosts.sort_by_key(|&(_, stats)| Reverse(stats.links));
+    hosts.into_iter().map(|(host, stats)| {
+        format!("{} external links to {}, {} broken", stats.links, host, stats.failed)
+    }).collect()
+}
 
-    let mut lines = source.lines();
-    let redirect_line = match lines.nth(6) {
-        Some(l) => l,
-        None => return None,
+/// Parses a `--severity` rule like `src/**=warning`.
+fn severity_rule(rule: &str) -> Result<(Pattern, Severity), String> {
+    let eq = match rule.rfind('=') {
+        Some(eq) => eq,
+        None => return Err("expected GLOB=LEVEL".to_string()),
     };
+    let pattern = Pattern::new(&rule[..eq]).map_err(|e| e.to_string())?;
+    Ok((pattern, rule[eq + 1..].parse()?))
+}
 
-    redirect_line.find(REDIRECT).map(|i| {
-        let rest = &redirect_line[(i + REDIRECT.len() + 1)..];
-        let pos_quote = rest.find('"').unwrap();
-        rest[..pos_quote].to_owned()
-    })
+/// Logs how long the run took, which `RUST_LOG=info` shows.
+fn log_run_time(elapsed: Duration) {
+    log::info!("finished in {:.2}s", millis(elapsed) as f64 / 1000.0);
 }
 
-fn with_attrs_in_source<F: FnMut(&str, usize, &str)>(contents: &str, attr: &str, mut f: F) {
-    let mut base = "";
-    for (i, mut line) in contents.lines().enumerate() {
-        while let Some(j) = line.find(attr) {
-            let rest = &line[j + attr.len()..];
-            // The base tag should always be the first link in the document so
-            // we can get away with using one pass.
-            let is_base = line[..j].ends_with("<base");
-            line = rest;
-            let pos_equals = match rest.find("=") {
-                Some(i) => i,
-                None => continue,
-            };
-            if rest[..pos_equals].trim_left_matches(" ") != "" {
-                continue;
-            }
+fn millis(d: Duration) -> u64 {
+    d.as_secs() * 1000 + u64::from(d.subsec_nanos()) / 1_000_000
+}
+
+fn set<T>(field: &mut T, val: Option<T>) {
+    if let Some(v) = val {
+        *field = v;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use log;
+    use toml;
+
+    use linkchecker::{Checker, ErrorKind, Options};
+    use linkchecker::vfs::MemoryFs;
+
+    use super::{Format, RunConfig, TomlConfig, broken_link_counts, command_line, config_json};
+    use super::{log_run_time, options_from, summary_json, write_report};
+
+    #[test]
+    fn broken_links_to_one_target() {
+        let root = Path::new("/docs");
+        let mut fs = MemoryFs::new();
+        fs.add_file(&root.join("index.html"),
+                    b"<a href=\"gone.html\">1</a> <a href=\"./gone.html\">2</a>");
+        fs.add_file(&root.join("sub/page.html"),
+                    b"<a href=\"../gone.html\">3</a> <a href=\"../sub/./gone.html\">4</a>");
+        let mut checker = Checker::with_file_system(root, Options::default(), Box::new(fs));
+        checker.walk(root).unwrap();
+        assert_eq!(broken_link_counts(checker.errors()), (4, 2));
+
+        // Unresolvable links are given as written.
+        let mut errors = checker.errors().to_vec();
+        for target in &["/std/foo.html", "/std/./foo.html"] {
+            let mut err = errors[0].clone();
+            err.kind = ErrorKind::UnresolvableLink;
+            err.target = target.to_string();
+            errors.push(err);
+        }
+        assert_eq!(broken_link_counts(&errors), (6, 3));
+    }
+
+    #[test]
+    fn command_line_overrides_the_config_file() {
+        let toml = toml::from_str::<TomlConfig>("ignore = [\"std/\"]\n\
+                                                 jobs = 4\n\
+                                                 check-external = true\n\
+                                                 external-cache = \"cache.json\"\n\
+                                                 low-memory = 64\n").unwrap();
+        let config = Some(Path::new("/conf/linkchecker.toml"));
+        let cwd = Path::new("/cwd");
+
+        let matches = command_line().parse(&["--jobs", "2", "--ignore", "core/", "--ignore",
+                                             "alloc/"]).unwrap();
+        let options = options_from(&matches, &toml, config, cwd).unwrap();
+        assert_eq!(options.jobs, 2);
+        assert_eq!(options.ignore, ["core/", "alloc/"]);
+        assert!(options.check_external);
+        assert_eq!(options.external_cache, Some(PathBuf::from("/conf/cache.json")));
+        assert_eq!(options.cache_budget, Some(64 << 20));
+
+        let matches = command_line().parse(&["--external-cache", "other.json",
+                                             "--low-memory=128"]).unwrap();
+        let options = options_from(&matches, &toml, config, cwd).unwrap();
+        assert_eq!(options.jobs, 4);
+        assert_eq!(options.ignore, ["std/"]);
+        assert_eq!(options.external_cache, Some(PathBuf::from("/cwd/other.json")));
+        assert_eq!(options.cache_budget, Some(128 << 20));
+
+        // Without a value `--low-memory` keeps 256 megabytes.
+        let matches = command_line().parse(&["--low-memory"]).unwrap();
+        let options = options_from(&matches, &toml, config, cwd).unwrap();
+        assert_eq!(options.cache_budget, Some(256 << 20));
+        let options = options_from(&matches, &TomlConfig::default(), None, cwd).unwrap();
+        assert_eq!(options.cache_budget, Some(256 << 20));
+        let options = options_from(&command_line().parse(&[] as &[&str]).unwrap(),
+                                   &TomlConfig::default(), None, cwd).unwrap();
+        assert_eq!(options.cache_budget, None);
+
+        let matches = command_line().parse(&["--low-memory=18446744073709551615"]).unwrap();
+        assert_eq!(options_from(&matches, &toml, config, cwd).err().unwrap(),
+                   "invalid --low-memory `18446744073709551615`: too many megabytes");
+
+        let matches = command_line().parse(&["--jobs", "many"]).unwrap();
+        assert_eq!(options_from(&matches, &toml, config, cwd).err().unwrap(),
+                   "invalid --jobs `many`: invalid digit found in string");
+    }
 
-            let rest = &rest[pos_equals + 1..];
+    #[test]
+    fn json_summary() {
+        let root = Path::new("/docs");
+        let mut fs = MemoryFs::new();
+        fs.add_file(&root.join("index.html"),
+                    b"<a href=\"gone.html\">1</a> <a href=\"gone.html\">2</a>\n\
+                      <a href=\"page.html\">3</a> <a href=\"https://example.com\">4</a>");
+        fs.add_file(&root.join("page.html"), b"");
+        let mut checker = Checker::with_file_system(root, Options::default(), Box::new(fs));
+        checker.walk(root).unwrap();
+        let summary = summary_json(&checker, Duration::from_millis(1500)).unwrap();
+        assert_eq!(summary["files_checked"], 2);
+        assert_eq!(summary["links_checked"], 3);
+        assert_eq!(summary["external_skipped"], 1);
+        assert_eq!(summary["errors"], 2);
+        assert_eq!(summary["warnings"], 0);
+        assert_eq!(summary["broken_links"], 2);
+        assert_eq!(summary["broken_targets"], 1);
+        assert_eq!(summary["elapsed_ms"], 1500);
+    }
+
+    /// The settings of a plain run on `/docs`, for tests to start from.
+    fn run_config() -> RunConfig<'static> {
+        RunConfig {
+            docs: Path::new("/docs"),
+            root: Path::new("/docs"),
+            format: &Format::Human,
+            output: &None,
+            json_summary: false,
+            summary_only_on_failure: false,
+            group_by_target_dir: false,
+            stats_timing: false,
+            dump_index: &None,
+            dump_links: &None,
+            repo_root: Path::new("/"),
+            git_base: &None,
+            compare: &None,
+            low_memory: None,
+        }
+    }
+
+    #[test]
+    fn report_to_output_file() {
+        let root = Path::new("/docs");
+        let mut fs = MemoryFs::new();
+        fs.add_file(&root.join("index.html"),
+                    b"<a href=\"gone.html\">1</a>\n<a href=\"gone.html#x\">2</a>");
+        let mut checker = Checker::with_file_system(root, Options::default(), Box::new(fs));
+        checker.walk(root).unwrap();
+        let output = Some(PathBuf::from("/out/report.txt"));
+        let run = RunConfig { output: &output, ..run_config() };
+        let mut out = Vec::new();
+        write_report(&mut out, &checker, None, &run, Duration::from_millis(0)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "index.html:1:10: broken link - gone.html\n\
+                    index.html:2:10: broken link - gone.html\n\
+                    2 broken links to 1 distinct targets\n");
+
+        // Without `--output` the logger already printed the diagnostics.
+        let run = RunConfig { output: &None, ..run };
+        let mut out = Vec::new();
+        write_report(&mut out, &checker, None, &run, Duration::from_millis(0)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "2 broken links to 1 distinct targets\n");
+    }
+
+    #[test]
+    fn print_config() {
+        let output = Some(PathBuf::from("/out/report.json"));
+        let git_base = Some("origin/master".to_string());
+        let run = RunConfig {
+            format: &Format::CodeClimate,
+            output: &output,
+            summary_only_on_failure: true,
+            stats_timing: true,
+            git_base: &git_base,
+            low_memory: Some(64),
+            ..run_config()
+        };
+        let options = Options { jobs: 4, ..Options::default() };
+        let config = config_json(&options, &run).unwrap();
+        for line in &["\"format\": \"codeclimate\"", "\"output\": \"/out/report.json\"",
+                      "\"summary-only-on-failure\": true", "\"git-base\": \"origin/master\"",
+                      "\"stats-timing\": true", "\"low-memory\": 64", "\"jobs\": 4",
+                      "\"docs\": \"/docs\""] {
+            assert!(config.contains(line), "{} missing from {}", line, config);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn print_config_of_non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let docs = Path::new(OsStr::from_bytes(b"/docs/\xff"));
+        let run = RunConfig { docs, root: docs, ..run_config() };
+        assert!(config_json(&Options::default(), &run).is_err());
+    }
 
-            let pos_quote = match rest.find(&['"', '\''][..]) {
-                Some(i) => i,
-                None => continue,
-            };
-            let quote_delim = rest.as_bytes()[pos_quote] as char;
+    #[test]
+    fn run_time_is_logged() {
+        struct Capture(Mutex<Vec<(log::Level, String)>>);
 
-            if rest[..pos_quote].trim_left_matches(" ") != "" {
-                continue;
+        impl log::Log for Capture {
+            fn enabled(&self, _metadata: &log::Metadata) -> bool {
+                true
             }
-            let rest = &rest[pos_quote + 1..];
-            let url = match rest.find(quote_delim) {
-                Some(i) => &rest[..i],
-                None => continue,
-            };
-            if is_base {
-                base = url;
-                continue;
+
+            fn log(&self, record: &log::Record) {
+                self.0.lock().unwrap().push((record.level(), record.args().to_string()));
             }
-            f(url, i, base)
+
+            fn flush(&self) {}
         }
+
+        static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+        log::set_logger(&CAPTURE).unwrap();
+        log::set_max_level(log::LevelFilter::Info);
+        log_run_time(Duration::from_millis(1500));
+        let logged = CAPTURE.0.lock().unwrap();
+        assert_eq!(*logged, [(log::Level::Info, "finished in 1.50s".to_string())]);
     }
 }
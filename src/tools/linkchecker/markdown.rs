@@ -0,0 +1,376 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Links and headings of Markdown sources, for `Options::markdown`.
+//!
+//! Like the rest of the checker this doesn't really parse anything: lines are
+//! scanned for the shapes of inline links, reference definitions and
+//! headings. Fenced code blocks and code spans are skipped, but indented
+//! code blocks aren't told apart from other indented text.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Whether `path` is a Markdown source, by its extension.
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
+}
+
+/// Calls `f` with each line of `contents` outside of fenced code blocks, and
+/// its 0-based number.
+fn with_text_lines<'a, F>(contents: &'a str, mut f: F)
+    where F: FnMut(&'a str, usize)
+{
+    let mut fence: Option<&str> = None;
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim_start_matches(' ');
+        if line.len() - trimmed.len() < 4 {
+            let marker = if trimmed.starts_with("```") {
+                Some(&trimmed[..trimmed.len() - trimmed.trim_start_matches('`').len()])
+            } else if trimmed.starts_with("~~~") {
+                Some(&trimmed[..trimmed.len() - trimmed.trim_start_matches('~').len()])
+            } else {
+                None
+            };
+            match (fence, marker) {
+                (None, Some(marker)) => {
+                    fence = Some(marker);
+                    continue;
+                }
+                (Some(open), Some(marker)) if marker.starts_with(open) &&
+                                              trimmed[marker.len()..].trim().is_empty() => {
+                    fence = None;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        if fence.is_none() {
+            f(line, i);
+        }
+    }
+}
+
+/// Blanks out code spans and backslash-escaped characters in `line`, keeping
+/// the byte offsets of everything else.
+fn mask_code(line: &str) -> String {
+    let mut masked = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if c == '\\' && rest.len() > 1 && rest.as_bytes()[1].is_ascii() {
+            masked.push_str("  ");
+            rest = &rest[2..];
+        } else if c == '`' {
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            let after = &rest[ticks..];
+            let close = &rest[..ticks];
+            match after.find(close) {
+                Some(end) => {
+                    let len = ticks + end + ticks;
+                    masked.extend((0..len).map(|_| ' '));
+                    rest = &rest[len..];
+                }
+                None => {
+                    masked.push_str(close);
+                    rest = after;
+                }
+            }
+        } else {
+            masked.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    masked
+}
+
+/// The label of a reference definition like `[label]: target` starting
+/// `line`, and the byte offset of its target. Footnotes like `[^1]: ...` aren't
+/// definitions of links.
+fn reference_definition(line: &str) -> Option<(&str, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() >= 4 || !trimmed.starts_with('[') ||
+       trimmed.starts_with("[^") {
+        return None;
+    }
+    let end = trimmed.find("]:")?;
+    let label = &trimmed[1..end];
+    let after = &trimmed[end + 2..];
+    let target = after.trim_start();
+    if label.trim().is_empty() || target.is_empty() {
+        return None;
+    }
+    Some((label, line.len() - target.len()))
+}
+
+/// Normalizes a reference label, which is matched ignoring case and runs of
+/// whitespace.
+fn normalize_label(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// The destination of a link starting at `s`, after a `(` or a reference
+/// definition's `:`, without the angle brackets it may be enclosed in, and
+/// its byte offset in `s`.
+fn destination(s: &str) -> (&str, usize) {
+    let start = s.len() - s.trim_start().len();
+    let s = &s[start..];
+    if s.starts_with('<') {
+        let end = s.find('>').unwrap_or(s.len());
+        return (&s[1..end], start + 1);
+    }
+    let mut depth = 0;
+    let end = s.char_indices().find(|&(_, c)| {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return true,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        c.is_whitespace()
+    }).map_or(s.len(), |(i, _)| i);
+    (&s[..end], start)
+}
+
+/// Calls `f` with the target of each link and image in `contents`, along
+/// with its 0-based line and 1-based byte column. Inline links are found, as
+/// well as the targets of reference definitions like `[label]: target`, which
+/// the links using the label lead to.
+pub fn with_links<'a, F>(contents: &'a str, mut f: F)
+    where F: FnMut(&'a str, usize, usize)
+{
+    with_text_lines(contents, |line, i| {
+        if let Some((_, offset)) = reference_definition(line) {
+            let (target, start) = destination(&line[offset..]);
+            f(target, i, offset + start + 1);
+            return;
+        }
+        let masked = mask_code(line);
+        let mut depth = 0;
+        for (j, c) in masked.char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' if depth > 0 => {
+                    depth -= 1;
+                    if masked[j + 1..].starts_with('(') {
+                        let (target, start) = destination(&line[j + 2..]);
+                        f(target, i, j + 2 + start + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+/// Finds the uses of reference links like `[text][label]` or `[label][]`
+/// whose label isn't defined anywhere in `contents`, which Markdown renders as
+/// plain text. Returns the labels with their 0-based line and 1-based byte
+/// column.
+pub fn undefined_references(contents: &str) -> Vec<(String, usize, usize)> {
+    let mut defined = HashSet::new();
+    with_text_lines(contents, |line, _| {
+        if let Some((label, _)) = reference_definition(line) {
+            defined.insert(normalize_label(label));
+        }
+    });
+    let mut undefined = Vec::new();
+    with_text_lines(contents, |line, i| {
+        if reference_definition(line).is_some() {
+            return;
+        }
+        let masked = mask_code(line);
+        let mut opened = Vec::new();
+        for (j, c) in masked.char_indices() {
+            match c {
+                '[' => opened.push(j),
+                ']' => {
+                    let open = match opened.pop() {
+                        Some(open) => open,
+                        None => continue,
+                    };
+                    let rest = &masked[j + 1..];
+                    if !rest.starts_with('[') || !opened.is_empty() {
+                        continue;
+                    }
+                    let end = match rest.find(']') {
+                        Some(end) => end,
+                        None => continue,
+                    };
+                    let (label, col) = if end == 1 {
+                        (&line[open + 1..j], open + 2)
+                    } else {
+                        (&line[j + 2..j + 1 + end], j + 3)
+                    };
+                    if !defined.contains(&normalize_label(label)) {
+                        undefined.push((label.to_string(), i, col));
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+    undefined
+}
+
+/// Turns the text of a heading into the id renderers like mdBook and GitHub
+/// give it: lowercase, with spaces turned into `-` and punctuation other than
+/// `-` and `_` left out. Link targets and emphasis don't count.
+fn slug(text: &str) -> String {
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("](") {
+        plain.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        rest = &after[after.find(')').map_or(after.len(), |end| end + 1)..];
+    }
+    plain.push_str(rest);
+    plain.trim().chars().filter_map(|c| {
+        if c.is_alphanumeric() || c == '-' || c == '_' {
+            Some(c.to_lowercase().collect::<String>())
+        } else if c.is_whitespace() {
+            Some("-".to_string())
+        } else {
+            None
+        }
+    }).collect()
+}
+
+/// Calls `f` with the id of each heading in `contents`, in both the `# ATX`
+/// and the underlined setext style. Headings of the same text get ids with
+/// the suffixes `-1`, `-2` and so on, in order.
+pub fn with_heading_ids<F: FnMut(String)>(contents: &str, mut f: F) {
+    let mut seen = HashMap::new();
+    let mut add = |text: &str| {
+        let id = slug(text);
+        let count = seen.entry(id.clone()).or_insert(0);
+        if *count == 0 {
+            f(id);
+        } else {
+            f(format!("{}-{}", id, count));
+        }
+        *count += 1;
+    };
+    let mut previous: Option<&str> = None;
+    let mut last = None;
+    with_text_lines(contents, |line, i| {
+        // Lines of a fenced block break up paragraphs too.
+        if last.is_some_and(|last| last + 1 != i) {
+            previous = None;
+        }
+        last = Some(i);
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+        let hashes = trimmed.len() - trimmed.trim_start_matches('#').len();
+        if indent < 4 && (1..=6).contains(&hashes) {
+            let text = &trimmed[hashes..];
+            if text.is_empty() || text.starts_with(' ') || text.starts_with('\t') {
+                let text = text.trim();
+                // An optional closing sequence of `#`s.
+                let unclosed = text.trim_end_matches('#');
+                let text = if unclosed.is_empty() || unclosed.ends_with(' ') {
+                    unclosed
+                } else {
+                    text
+                };
+                add(text);
+                previous = None;
+                return;
+            }
+        }
+        let underline = trimmed.trim_end();
+        let is_underline = indent < 4 && !underline.is_empty() &&
+            (underline.bytes().all(|b| b == b'=') || underline.bytes().all(|b| b == b'-'));
+        match previous {
+            Some(text) if is_underline => {
+                add(text);
+                previous = None;
+            }
+            _ if trimmed.trim().is_empty() => previous = None,
+            _ => previous = Some(line),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{undefined_references, with_heading_ids, with_links};
+
+    fn links(contents: &str) -> Vec<(String, usize, usize)> {
+        let mut links = Vec::new();
+        with_links(contents, |url, i, col| links.push((url.to_string(), i, col)));
+        links
+    }
+
+    fn ids(contents: &str) -> Vec<String> {
+        let mut ids = Vec::new();
+        with_heading_ids(contents, |id| ids.push(id));
+        ids
+    }
+
+    #[test]
+    fn inline_links() {
+        let contents = "See [the book](book/index.md) and ![logo](img/logo.png \"Logo\").\n\
+                        [nested [brackets]](a.md#x) [angle](<b c.md>) [p](f(x).md)\n\
+                        `[code](not.md)` \\[escaped](no.md) [text] (no.md)";
+        assert_eq!(links(contents), [
+            ("book/index.md".to_string(), 0, 16),
+            ("img/logo.png".to_string(), 0, 43),
+            ("a.md#x".to_string(), 1, 21),
+            ("b c.md".to_string(), 1, 38),
+            ("f(x).md".to_string(), 1, 51),
+        ]);
+    }
+
+    #[test]
+    fn fenced_code_is_skipped() {
+        let contents = "```rust\n[a](a.md)\n```\n[b](b.md)\n~~~~\n[c](c.md)\n```\n~~~~~\n[d](d.md)";
+        let found = links(contents).into_iter().map(|(url, _, _)| url).collect::<Vec<_>>();
+        assert_eq!(found, ["b.md", "d.md"]);
+    }
+
+    #[test]
+    fn references() {
+        let contents = "[Text][Std Docs] and [missing][] and [other][gone]\n\
+                        [not a link] [^1]\n\
+                        \n\
+                        [std docs]: ../std/index.html \"Title\"\n\
+                        [missing]:\n\
+                        [^1]: A footnote.";
+        assert_eq!(links(contents), [("../std/index.html".to_string(), 3, 13)]);
+        assert_eq!(undefined_references(contents), [
+            ("missing".to_string(), 0, 23),
+            ("gone".to_string(), 0, 46),
+        ]);
+    }
+
+    #[test]
+    fn heading_ids() {
+        let contents = "# Getting started\n\
+                        ## The `Vec<T>` type, again! ##\n\
+                        #NotAHeading\n\
+                        ```\n\
+                        # a comment\n\
+                        ```\n\
+                        Getting started\n\
+                        ===============\n\
+                        \n\
+                        ---\n\
+                        A [linked](x.md) *title*\n\
+                        ---";
+        assert_eq!(ids(contents), [
+            "getting-started",
+            "the-vect-type-again",
+            "getting-started-1",
+            "a-linked-title",
+        ]);
+    }
+}
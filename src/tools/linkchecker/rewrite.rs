@@ -0,0 +1,115 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rules rewriting the target of a link, for docs whose server maps the URLs
+//! it's asked for to other files, like `latest/` to the directory of a
+//! release. Rules see the target once it's resolved, as a path relative to
+//! the docs root with `/` separators, so `../latest/` from a page one level
+//! down is rewritten the same as `latest/` from the top.
+//!
+//! A rule is written like a `sed` substitution, `s#PATTERN#REPLACEMENT#`,
+//! where the character after the `s` can be any delimiter which doesn't
+//! appear in the pattern or replacement. The pattern is a regular expression,
+//! and the replacement can refer to its groups as `$1` or `${name}`. Only the
+//! first match is replaced, unless the rule ends with a `g` flag.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use regex::Regex;
+use serde::{Serialize, Serializer};
+
+#[derive(Clone, Debug)]
+pub struct Rewrite {
+    /// The rule as it was written.
+    rule: String,
+    pattern: Regex,
+    replacement: String,
+    global: bool,
+}
+
+impl Rewrite {
+    /// Parses a rule like `s#^latest/#1.75.0/#`.
+    pub fn parse(rule: &str) -> Result<Rewrite, String> {
+        let mut chars = rule.chars();
+        let delimiter = match (chars.next(), chars.next()) {
+            (Some('s'), Some(c)) if !c.is_alphanumeric() && c != '\\' => c,
+            _ => return Err(format!("`{}` doesn't look like `s#PATTERN#REPLACEMENT#`", rule)),
+        };
+        let parts = chars.as_str().split(delimiter).collect::<Vec<_>>();
+        let global = match parts.get(2) {
+            Some(&"") if parts.len() == 3 => false,
+            Some(&"g") if parts.len() == 3 => true,
+            Some(flags) if parts.len() == 3 => {
+                return Err(format!("unknown flags `{}` in `{}`", flags, rule));
+            }
+            _ => {
+                return Err(format!("`{}` needs a pattern and a replacement, each followed by `{}`",
+                                   rule,
+                                   delimiter));
+            }
+        };
+        let pattern = Regex::new(parts[0]).map_err(|e| {
+            format!("bad pattern in `{}`: {}", rule, e)
+        })?;
+        Ok(Rewrite {
+            rule: rule.to_string(),
+            pattern,
+            replacement: parts[1].to_string(),
+            global,
+        })
+    }
+
+    /// Applies the rule to `path`, which is left as is if it doesn't match.
+    pub fn apply<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        if self.global {
+            self.pattern.replace_all(path, &self.replacement[..])
+        } else {
+            self.pattern.replace(path, &self.replacement[..])
+        }
+    }
+}
+
+impl fmt::Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.rule)
+    }
+}
+
+impl Serialize for Rewrite {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rewrite;
+
+    #[test]
+    fn rules() {
+        let latest = Rewrite::parse("s#^latest/#1.75.0/#").unwrap();
+        assert_eq!(latest.apply("latest/std/index.html"), "1.75.0/std/index.html");
+        assert_eq!(latest.apply("std/latest/index.html"), "std/latest/index.html");
+
+        let groups = Rewrite::parse("s|^v(\\d+)/|$1.0/|").unwrap();
+        assert_eq!(groups.apply("v2/index.html"), "2.0/index.html");
+
+        let global = Rewrite::parse("s/-/_/g").unwrap();
+        assert_eq!(global.apply("a-b-c.html"), "a_b_c.html");
+        assert_eq!(Rewrite::parse("s/-/_/").unwrap().apply("a-b-c.html"), "a_b-c.html");
+
+        assert!(Rewrite::parse("latest/").is_err());
+        assert!(Rewrite::parse("s#latest/").is_err());
+        assert!(Rewrite::parse("s#a#b#c#").is_err());
+        assert!(Rewrite::parse("s#a#b#x").is_err());
+        assert!(Rewrite::parse("s#(#b#").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reports in the SARIF 2.1.0 format, as understood by GitHub code scanning.
+//!
+//! Every kind of error is a rule, and every error a result pointing at the
+//! file, line and column of the link. File paths are given relative to the
+//! root of the repository, so that they match up with the repository's files.
+
+use std::path::{Component, Path};
+
+use linkchecker::{ErrorKind, LinkError, Severity};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const INFORMATION_URI: &str =
+    "https://github.com/rust-lang/rust/tree/master/src/tools/linkchecker";
+
+#[derive(Serialize)]
+pub struct Log {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run>,
+}
+
+#[derive(Serialize)]
+struct Run {
+    tool: Tool,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    information_uri: &'static str,
+    rules: Vec<Rule>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    id: &'static str,
+    default_configuration: Configuration,
+}
+
+#[derive(Serialize)]
+struct Configuration {
+    level: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: &'static str,
+    rule_index: usize,
+    level: &'static str,
+    message: Message,
+    locations: Vec<Location>,
+}
+
+#[derive(Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<Region>,
+}
+
+#[derive(Serialize)]
+struct ArtifactLocation {
+    uri: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_column: Option<usize>,
+}
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    }
+}
+
+/// Converts `path` to a relative URI, with forward slashes on all platforms.
+pub fn uri(path: &Path) -> String {
+    let parts = path.components().filter_map(|c| match c {
+        Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+        Component::ParentDir => Some("..".to_string()),
+        _ => None,
+    }).collect::<Vec<_>>();
+    parts.join("/")
+}
+
+/// Builds the report for `errors`, whose paths are relative to `root`.
+/// `root` itself is given relative to the repository root, or is absolute if
+/// it's outside of it.
+pub fn report(errors: &[LinkError], root: &Path) -> Log {
+    let rules = ErrorKind::ALL.iter().map(|kind| {
+        Rule {
+            id: kind.name(),
+            default_configuration: Configuration {
+                level: level(kind.severity()),
+            },
+        }
+    }).collect();
+    let results = errors.iter().map(|err| {
+        let file = root.join(&err.file);
+        let uri = if file.is_absolute() {
+            format!("file://{}", file.display())
+        } else {
+            uri(&file)
+        };
+        let region = if err.line == 0 {
+            None
+        } else {
+            Some(Region {
+                start_line: err.line,
+                start_column: if err.column == 0 { None } else { Some(err.column) },
+            })
+        };
+        SarifResult {
+            rule_id: err.kind.name(),
+            rule_index: ErrorKind::ALL.iter().position(|k| *k == err.kind).unwrap(),
+            level: level(err.severity),
+            message: Message { text: err.message() },
+            locations: vec![Location {
+                physical_location: PhysicalLocation {
+                    artifact_location: ArtifactLocation { uri },
+                    region,
+                },
+            }],
+        }
+    }).collect();
+    Log {
+        schema: SCHEMA,
+        version: "2.1.0",
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver {
+                    name: "linkchecker",
+                    information_uri: INFORMATION_URI,
+                    rules,
+                },
+            },
+            results,
+        }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use linkchecker::{ErrorKind, LinkError};
+    use serde_json;
+
+    use super::report;
+
+    #[test]
+    fn results_point_at_repo_paths() {
+        let err = LinkError::new(ErrorKind::QueryOnlyLink, Path::new("std/index.html"), 3, 7, "?x");
+        let json = serde_json::to_value(report(&[err], Path::new("build/doc"))).unwrap();
+        assert_eq!(json["version"], "2.1.0");
+        let result = &json["runs"][0]["results"][0];
+        assert_eq!(result["ruleId"], "query-only-link");
+        assert_eq!(result["level"], "warning");
+        assert_eq!(result["message"]["text"], "query-only link - ?x");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "build/doc/std/index.html");
+        assert_eq!(location["region"]["startLine"], 3);
+        assert_eq!(location["region"]["startColumn"], 7);
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Values computed at most once for all the threads of a parallel walk, like
+//! the ids of a page which pages checked on several threads link into.
+//!
+//! The first thread to ask for a key computes its value, and the threads
+//! asking for it in the meantime wait for that instead of doing the same
+//! work again.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::hash::Hash;
+use std::sync::{Arc, Condvar, Mutex};
+
+enum State<V> {
+    Pending,
+    Ready(V),
+    /// The thread computing the value panicked.
+    Abandoned,
+}
+
+struct Slot<V> {
+    state: Mutex<State<V>>,
+    ready: Condvar,
+}
+
+pub struct SingleFlight<K, V> {
+    slots: Mutex<HashMap<K, Arc<Slot<V>>>>,
+}
+
+/// Wakes up the threads waiting for a value if computing it panics, so that
+/// they panic as well rather than wait forever.
+struct Abandon<'a, V: 'a> {
+    slot: &'a Slot<V>,
+    done: bool,
+}
+
+impl<'a, V> Drop for Abandon<'a, V> {
+    fn drop(&mut self) {
+        if !self.done {
+            *self.slot.state.lock().unwrap() = State::Abandoned;
+            self.slot.ready.notify_all();
+        }
+    }
+}
+
+impl<K: Eq + Hash, V: Clone> SingleFlight<K, V> {
+    pub fn new() -> SingleFlight<K, V> {
+        SingleFlight { slots: Mutex::new(HashMap::new()) }
+    }
+
+    /// Returns the value for `key`, calling `f` for it unless another thread
+    /// already has or is at it, in which case this waits for its result.
+    pub fn get<F: FnOnce() -> V>(&self, key: K, f: F) -> V {
+        let (slot, first) = match self.slots.lock().unwrap().entry(key) {
+            Entry::Occupied(entry) => (entry.get().clone(), false),
+            Entry::Vacant(entry) => {
+                let slot = Slot {
+                    state: Mutex::new(State::Pending),
+                    ready: Condvar::new(),
+                };
+                (entry.insert(Arc::new(slot)).clone(), true)
+            }
+        };
+        if first {
+            // The slots aren't locked while computing, so that other keys can
+            // be computed at the same time.
+            let mut abandon = Abandon { slot: &slot, done: false };
+            let value = f();
+            *slot.state.lock().unwrap() = State::Ready(value.clone());
+            abandon.done = true;
+            slot.ready.notify_all();
+            return value;
+        }
+        let mut state = slot.state.lock().unwrap();
+        loop {
+            match *state {
+                State::Pending => state = slot.ready.wait(state).unwrap(),
+                State::Ready(ref value) => return value.clone(),
+                State::Abandoned => panic!("computing a shared value panicked"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Barrier};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::SingleFlight;
+
+    #[test]
+    fn concurrent_requests_coalesce() {
+        let shared = Arc::new(SingleFlight::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8).map(|i| {
+            let (shared, calls, barrier) = (shared.clone(), calls.clone(), barrier.clone());
+            thread::spawn(move || {
+                barrier.wait();
+                let target = shared.get("target.html", || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    // Long enough for the other threads to ask in the meantime.
+                    thread::sleep(Duration::from_millis(50));
+                    format!("parsed by {}", i)
+                });
+                let other = shared.get(if i % 2 == 0 { "even.html" } else { "odd.html" }, || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    String::new()
+                });
+                (target, other)
+            })
+        }).collect::<Vec<_>>();
+        let results = threads.into_iter().map(|t| t.join().unwrap()).collect::<Vec<_>>();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(results.iter().all(|result| *result == results[0]));
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reports as tab-separated values, one row per problem, for pasting into a
+//! spreadsheet.
+
+use std::path::Path;
+
+use linkchecker::{LinkError, ResolvedLink};
+
+use sarif;
+
+/// The first row, naming the columns.
+pub const HEADER: &str = "source_file\tline\tkind\ttarget\tfragment";
+
+/// The first row of `--dump-links`.
+pub const LINKS_HEADER: &str = "source_file\ttarget\tfragment";
+
+/// Replaces the characters which would break up a row or a cell.
+fn sanitize(s: &str) -> String {
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Formats `err`, whose path is relative to `root`, as a row. `root` itself
+/// is relative to the repository root, or absolute if it's outside of it.
+/// The line is left empty for problems with a whole file.
+pub fn row(err: &LinkError, root: &Path) -> String {
+    let file = root.join(&err.file);
+    let file = if file.is_absolute() {
+        file.display().to_string()
+    } else {
+        sarif::uri(&file)
+    };
+    let line = if err.line == 0 { String::new() } else { err.line.to_string() };
+    format!("{}\t{}\t{}\t{}\t{}",
+            sanitize(&file),
+            line,
+            err.kind.name(),
+            sanitize(&err.target),
+            sanitize(err.fragment.as_ref().map(|s| &s[..]).unwrap_or("")))
+}
+
+/// Formats `link` as a row of `--dump-links`, with its paths relative to the
+/// docs root.
+pub fn link_row(link: &ResolvedLink) -> String {
+    format!("{}\t{}\t{}",
+            sanitize(&sarif::uri(&link.source)),
+            sanitize(&sarif::uri(&link.target)),
+            sanitize(link.fragment.as_ref().map(|s| &s[..]).unwrap_or("")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use linkchecker::{ErrorKind, LinkError, ResolvedLink};
+
+    use super::{link_row, row};
+
+    #[test]
+    fn rows() {
+        let file = Path::new("std/index.html");
+        let mut err = LinkError::new(ErrorKind::BrokenFragment, file, 12, 3, "std/a\tb.html");
+        err.fragment = Some("x\ny".to_string());
+        assert_eq!(row(&err, Path::new("doc")),
+                   "doc/std/index.html\t12\tbroken-fragment\tstd/a b.html\tx y");
+        err.line = 0;
+        err.fragment = None;
+        assert_eq!(row(&err, Path::new("")),
+                   "std/index.html\t\tbroken-fragment\tstd/a b.html\t");
+    }
+
+    #[test]
+    fn link_rows() {
+        let mut link = ResolvedLink {
+            source: PathBuf::from("std/index.html"),
+            target: PathBuf::from("std/vec/struct.Vec.html"),
+            fragment: Some("method.new".to_string()),
+        };
+        assert_eq!(link_row(&link),
+                   "std/index.html\tstd/vec/struct.Vec.html\tmethod.new");
+        link.fragment = None;
+        assert_eq!(link_row(&link), "std/index.html\tstd/vec/struct.Vec.html\t");
+    }
+}
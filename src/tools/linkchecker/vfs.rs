@@ -0,0 +1,558 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Access to the files being checked.
+//!
+//! A `Checker` reads the docs through a `FileSystem`, which is usually the
+//! real one, but can also be the contents of a tarball held in memory so that
+//! CI artifacts don't need to be unpacked first, or, experimentally, a site
+//! the docs are deployed to.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::io::prelude::*;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use flate2::read::GzDecoder;
+use tar::{Archive, EntryType};
+
+/// An entry of a directory, as returned by `FileSystem::read_dir`.
+pub struct DirEntry {
+    pub path: PathBuf,
+    /// Whether the entry is a directory. Always `false` for symlinks, which
+    /// need to be resolved to find out.
+    pub is_dir: bool,
+    pub is_symlink: bool,
+}
+
+/// The file operations the checker needs. They may be used from several
+/// threads at once, see `Options::jobs`.
+pub trait FileSystem: Send + Sync {
+    /// Reads the whole file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Opens the file at `path` to be read a bit at a time, see
+    /// `Options::stream_threshold`.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn BufRead>> {
+        Ok(Box::new(io::Cursor::new(self.read(path)?)))
+    }
+
+    /// The size in bytes of the file at `path`, following symlinks, which is
+    /// found by reading it unless the file system knows better.
+    fn size(&self, path: &Path) -> io::Result<u64> {
+        self.read(path).map(|bytes| bytes.len() as u64)
+    }
+
+    /// Whether `path` is a directory, following symlinks. Fails if there's
+    /// nothing at `path`.
+    fn is_dir(&self, path: &Path) -> io::Result<bool>;
+
+    /// Lists the entries of the directory `path`. An entry which can't be
+    /// read doesn't keep the others from being listed, and is returned as
+    /// the error it ran into instead.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<DirEntry>>>;
+
+    /// Returns the absolute path of `path` with all symlinks resolved.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+}
+
+/// The real file system.
+pub struct StdFs;
+
+impl FileSystem for StdFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut fp = File::open(path)?;
+        let len = fp.metadata().map(|m| m.len() as usize).unwrap_or(0);
+        let mut bytes = Vec::with_capacity(len + 1);
+        fp.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn BufRead>> {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+
+    fn size(&self, path: &Path) -> io::Result<u64> {
+        path.metadata().map(|m| m.len())
+    }
+
+    fn is_dir(&self, path: &Path) -> io::Result<bool> {
+        path.metadata().map(|m| m.is_dir())
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<DirEntry>>> {
+        Ok(path.read_dir()?.map(|entry| {
+            let entry = entry?;
+            let path = entry.path();
+            let kind = entry.file_type().map_err(|err| {
+                io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+            })?;
+            Ok(DirEntry {
+                path,
+                is_dir: kind.is_dir(),
+                is_symlink: kind.is_symlink(),
+            })
+        }).collect())
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        path.canonicalize()
+    }
+}
+
+/// Files held in memory, like the contents of a tarball, or a tree built by a
+/// test with `add_file`.
+///
+/// Paths are normalized without looking at the files, as there are no
+/// symlinks to resolve.
+#[derive(Default)]
+pub struct MemoryFs {
+    files: HashMap<PathBuf, Option<Vec<u8>>>,
+    dirs: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl MemoryFs {
+    /// Creates an empty file system.
+    pub fn new() -> MemoryFs {
+        MemoryFs::default()
+    }
+
+    /// Adds a file, along with the directories it's in.
+    pub fn add_file(&mut self, path: &Path, contents: &[u8]) {
+        self.insert(normalize(path), Some(contents.to_vec()));
+    }
+
+    /// Unpacks the `.tar` or `.tar.gz` archive at `path` into memory. Its
+    /// entries appear below `path` itself, as if it were a directory.
+    ///
+    /// The contents of every HTML and Markdown page are kept, the other files
+    /// merely exist, so this takes about as much memory as the pages do
+    /// unpacked, whatever `Options::cache_budget` says. Archives too big for
+    /// that need to be unpacked to disk instead.
+    pub fn unpack_tar(path: &Path) -> io::Result<MemoryFs> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let gzipped = reader.fill_buf()?.starts_with(b"\x1f\x8b");
+        let reader: Box<dyn Read> = if gzipped {
+            Box::new(GzDecoder::new(reader))
+        } else {
+            Box::new(reader)
+        };
+
+        let root = normalize(path);
+        let mut fs = MemoryFs::new();
+        fs.add_dir(&root);
+        let mut archive = Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = root.join(normalize(&entry.path()?));
+            if !name.starts_with(&root) || name == root {
+                debug!("skipping archive entry {}", name.display());
+                continue;
+            }
+            match entry.header().entry_type() {
+                EntryType::Directory => fs.add_dir(&name),
+                EntryType::Regular | EntryType::Continuous => {
+                    let contents = if ::is_html(&name) || ::markdown::is_markdown(&name) {
+                        let mut contents = Vec::new();
+                        entry.read_to_end(&mut contents)?;
+                        Some(contents)
+                    } else {
+                        None
+                    };
+                    fs.insert(name, contents);
+                }
+                _ => debug!("skipping archive entry {}", name.display()),
+            }
+        }
+        Ok(fs)
+    }
+
+    fn insert(&mut self, path: PathBuf, contents: Option<Vec<u8>>) {
+        let parent = path.parent().unwrap().to_path_buf();
+        self.add_dir(&parent);
+        if self.files.insert(path.clone(), contents).is_none() {
+            self.dirs.get_mut(&parent).unwrap().push(path);
+        }
+    }
+
+    /// Adds `dir` and its parents, up to the ones already there.
+    fn add_dir(&mut self, dir: &Path) {
+        if self.dirs.contains_key(dir) {
+            return;
+        }
+        self.dirs.insert(dir.to_path_buf(), Vec::new());
+        if let Some(parent) = dir.parent() {
+            self.add_dir(parent);
+            self.dirs.get_mut(parent).unwrap().push(dir.to_path_buf());
+        }
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let path = normalize(path);
+        match self.files.get(&path) {
+            Some(Some(contents)) => Ok(contents.clone()),
+            Some(None) => Err(io::Error::other("only the contents of HTML pages are kept")),
+            None if self.dirs.contains_key(&path) => {
+                Err(io::Error::other("is a directory"))
+            }
+            None => Err(not_found()),
+        }
+    }
+
+    fn size(&self, path: &Path) -> io::Result<u64> {
+        match self.files.get(&normalize(path)) {
+            Some(Some(contents)) => Ok(contents.len() as u64),
+            _ => self.read(path).map(|bytes| bytes.len() as u64),
+        }
+    }
+
+    fn is_dir(&self, path: &Path) -> io::Result<bool> {
+        let path = normalize(path);
+        if self.dirs.contains_key(&path) {
+            Ok(true)
+        } else if self.files.contains_key(&path) {
+            Ok(false)
+        } else {
+            Err(not_found())
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<DirEntry>>> {
+        let children = self.dirs.get(&normalize(path)).ok_or_else(not_found)?;
+        Ok(children.iter().map(|child| {
+            Ok(DirEntry {
+                path: child.clone(),
+                is_dir: self.dirs.contains_key(child),
+                is_symlink: false,
+            })
+        }).collect())
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = normalize(path);
+        if self.dirs.contains_key(&path) || self.files.contains_key(&path) {
+            Ok(path)
+        } else {
+            Err(not_found())
+        }
+    }
+}
+
+/// Docs deployed to a web server, fetched with `curl`. This is experimental,
+/// meant for spot-checking a few pages of a live site.
+///
+/// URLs stand in for paths as given by `url_path`. Nothing can be listed, so
+/// pages have to be checked one by one rather than walked, and there are no
+/// directories: anything which can be fetched is a file.
+pub struct HttpFs {
+    get: Request,
+    /// Only asks whether there's anything at a URL, which is all that's
+    /// needed of most link targets.
+    head: Request,
+    /// Each URL is only fetched once, as the target of a link is first looked
+    /// up and then read.
+    fetched: Mutex<HashMap<String, Response<Vec<u8>>>>,
+    /// What `head` said about URLs which weren't fetched.
+    found: Mutex<HashMap<String, Response<()>>>,
+}
+
+/// Sends a request for a URL, returning the body of the response.
+type Request = Box<dyn Fn(&str) -> io::Result<Vec<u8>> + Send + Sync>;
+
+/// The outcome of a request, kept to be handed out again. `io::Error` can't
+/// be cloned, so only its kind and message are.
+type Response<T> = Result<T, (io::ErrorKind, String)>;
+
+impl Default for HttpFs {
+    fn default() -> HttpFs {
+        HttpFs::new()
+    }
+}
+
+impl HttpFs {
+    pub fn new() -> HttpFs {
+        HttpFs::with_requests(Box::new(get), Box::new(head))
+    }
+
+    fn with_requests(get: Request, head: Request) -> HttpFs {
+        HttpFs {
+            get,
+            head,
+            fetched: Mutex::new(HashMap::new()),
+            found: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn fetch(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let url = match path_url(path) {
+            Some(url) => url,
+            None => return Err(not_found()),
+        };
+        if let Some(res) = self.fetched.lock().unwrap().get(&url) {
+            return res.clone().map_err(|(kind, msg)| io::Error::new(kind, msg));
+        }
+        debug!("fetching {}", url);
+        let res = (self.get)(&url);
+        let cached = match res {
+            Ok(ref body) => Ok(body.clone()),
+            Err(ref err) => Err((err.kind(), err.to_string())),
+        };
+        self.fetched.lock().unwrap().insert(url, cached);
+        res
+    }
+
+    /// Whether there's anything at `path`, without downloading it unless it
+    /// was fetched already. Servers which don't answer `HEAD` requests get a
+    /// `GET` instead.
+    fn find(&self, path: &Path) -> io::Result<()> {
+        let url = match path_url(path) {
+            Some(url) => url,
+            None => return Err(not_found()),
+        };
+        if let Some(res) = self.fetched.lock().unwrap().get(&url) {
+            return res.as_ref().map(|_| ()).map_err(|&(kind, ref msg)| {
+                io::Error::new(kind, msg.clone())
+            });
+        }
+        if let Some(res) = self.found.lock().unwrap().get(&url) {
+            return res.clone().map_err(|(kind, msg)| io::Error::new(kind, msg));
+        }
+        debug!("looking up {}", url);
+        let res = match (self.head)(&url) {
+            Err(ref err) if err.kind() != io::ErrorKind::NotFound => {
+                return self.fetch(path).map(|_| ());
+            }
+            res => res.map(|_| ()),
+        };
+        let cached = match res {
+            Ok(()) => Ok(()),
+            Err(ref err) => Err((err.kind(), err.to_string())),
+        };
+        self.found.lock().unwrap().insert(url, cached);
+        res
+    }
+}
+
+impl FileSystem for HttpFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.fetch(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> io::Result<bool> {
+        self.find(path).map(|()| false)
+    }
+
+    fn read_dir(&self, _path: &Path) -> io::Result<Vec<io::Result<DirEntry>>> {
+        Err(io::Error::other("directories can't be listed over HTTP"))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        Ok(normalize(path))
+    }
+}
+
+/// Returns the path standing for the `http:` or `https:` URL `url` in an
+/// `HttpFs`, like `/https/doc.rust-lang.org/std/index.html`. The query and
+/// fragment are left out.
+pub fn url_path(url: &str) -> PathBuf {
+    let mut parts = url.splitn(2, "://");
+    let scheme = parts.next().unwrap();
+    let rest = parts.next().unwrap_or("");
+    let rest = rest.split(['?', '#']).next().unwrap();
+    Path::new("/").join(scheme).join(rest.trim_start_matches('/'))
+}
+
+/// The reverse of `url_path`.
+fn path_url(path: &Path) -> Option<String> {
+    let path = normalize(path);
+    let mut parts = path.components().filter_map(|c| {
+        match c {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        }
+    });
+    let scheme = parts.next()?;
+    if scheme != "http" && scheme != "https" {
+        return None;
+    }
+    let host = parts.next()?;
+    Some(format!("{}://{}/{}", scheme, host, parts.collect::<Vec<_>>().join("/")))
+}
+
+/// Fetches `url` with `curl`, following redirects. Only a 404 or 410 means
+/// there's nothing there, other failures may be the server's fault.
+fn get(url: &str) -> io::Result<Vec<u8>> {
+    curl(url, &[])
+}
+
+/// Like `get`, but with a `HEAD` request, returning the headers.
+fn head(url: &str) -> io::Result<Vec<u8>> {
+    curl(url, &["--head"])
+}
+
+fn curl(url: &str, args: &[&str]) -> io::Result<Vec<u8>> {
+    let output = Command::new("curl").arg("--silent")
+                                     .arg("--show-error")
+                                     .arg("--location")
+                                     .arg("--max-time").arg("30")
+                                     .arg("--write-out").arg("%{http_code}")
+                                     .args(args)
+                                     .arg(url)
+                                     .output()?;
+    if !output.status.success() {
+        let msg = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(msg));
+    }
+    // The status is written after the body.
+    let mut body = output.stdout;
+    let at = body.len().saturating_sub(3);
+    let status = String::from_utf8_lossy(&body[at..]).parse().unwrap_or(0);
+    body.truncate(at);
+    match status {
+        200..=299 => Ok(body),
+        404 | 410 => Err(not_found()),
+        _ => Err(io::Error::other(format!("HTTP {}", status))),
+    }
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no such file or directory")
+}
+
+/// Removes `.` and `..` components from `path`, without looking at the file
+/// system. A `..` at the start is dropped.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => { normalized.pop(); }
+            c => normalized.push(c.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// Whether `path` looks like an archive `MemoryFs::unpack_tar` can read.
+pub fn is_tar(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".tar") || name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use tar::{Builder, Header};
+
+    use {Checker, ErrorKind, Options};
+    use super::{FileSystem, HttpFs, MemoryFs, is_tar, not_found, url_path};
+
+    #[test]
+    fn tarballs() {
+        let dir = env::temp_dir().join("linkchecker-test-tarballs");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("docs.tar.gz");
+        {
+            let gz = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+            let mut builder = Builder::new(gz);
+            for &(name, contents) in &[("./std/index.html", "<a href=\"../logo.png\">"),
+                                       ("logo.png", "PNG")] {
+                let mut header = Header::new_gnu();
+                header.set_path(name).unwrap();
+                header.set_size(contents.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append(&header, contents.as_bytes()).unwrap();
+            }
+            builder.into_inner().unwrap().finish().unwrap();
+        }
+        let fs = MemoryFs::unpack_tar(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let page = path.join("std/index.html");
+        assert_eq!(fs.read(&page).unwrap(), b"<a href=\"../logo.png\">");
+        assert!(fs.read(&path.join("logo.png")).is_err());
+        assert!(!fs.is_dir(&path.join("std/../logo.png")).unwrap());
+        assert!(fs.is_dir(&path.join("std")).unwrap());
+        assert!(fs.is_dir(&path.join("missing")).is_err());
+        let mut entries = fs.read_dir(&path).unwrap().into_iter()
+            .map(|e| e.unwrap())
+            .map(|e| (e.path, e.is_dir))
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(entries, [(path.join("logo.png"), false), (path.join("std"), true)]);
+        assert_eq!(fs.canonicalize(&page.join("..")).unwrap(), path.join("std"));
+        assert!(is_tar(Path::new("docs.tgz")) && !is_tar(Path::new("docs")));
+
+        let mut checker = Checker::with_file_system(&path, Options::default(), Box::new(fs));
+        checker.walk(&path).unwrap();
+        assert_eq!(checker.stats().files_checked, 1);
+        assert!(!checker.has_errors());
+    }
+
+    #[test]
+    fn http() {
+        assert_eq!(url_path("https://example.com/docs/?x#y"),
+                   Path::new("/https/example.com/docs"));
+        let site = [
+            ("https://example.com/docs/std/index.html",
+             "<a href=\"../core/index.html#x\">a</a><a href=\"../core/index.html#y\">b</a>\n\
+              <a href=\"gone.html\">c</a><a href=\"../../logo.png\">d</a>"),
+            ("https://example.com/docs/core/index.html", "<h1 id=\"x\">core</h1>"),
+            ("https://example.com/logo.png", "PNG"),
+        ];
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let request = |method: &'static str| {
+            let log = requests.clone();
+            Box::new(move |url: &str| {
+                log.lock().unwrap().push(format!("{} {}", method, url));
+                site.iter()
+                    .find(|&&(page, _)| page == url)
+                    .map(|&(_, body)| body.as_bytes().to_vec())
+                    .ok_or_else(not_found)
+            })
+        };
+        let fs = HttpFs::with_requests(request("GET"), request("HEAD"));
+        assert!(fs.read_dir(&url_path("https://example.com/docs")).is_err());
+        let root = url_path("https://example.com/docs/");
+        let mut checker = Checker::with_file_system(&root, Options::default(), Box::new(fs));
+        checker.check_file(&url_path("https://example.com/docs/std/index.html")).unwrap();
+        let errors = checker.errors().iter().map(|e| (e.kind, &e.target[..])).collect::<Vec<_>>();
+        assert_eq!(errors, [(ErrorKind::BrokenFragment, "core/index.html"),
+                            (ErrorKind::BrokenLink, "std/gone.html")]);
+        // Only the pages whose ids are needed are downloaded.
+        let requests = requests.lock().unwrap();
+        assert_eq!(*requests, ["GET https://example.com/docs/std/index.html",
+                               "HEAD https://example.com/docs/core/index.html",
+                               "GET https://example.com/docs/core/index.html",
+                               "HEAD https://example.com/docs/std/gone.html",
+                               "HEAD https://example.com/logo.png"]);
+        drop(requests);
+
+        // Servers which don't allow `HEAD` are asked with `GET`.
+        let refused = |_: &str| Err(io::Error::other("HTTP 405"));
+        let fs = HttpFs::with_requests(request("GET"), Box::new(refused));
+        assert!(!fs.is_dir(&url_path("https://example.com/logo.png")).unwrap());
+        assert!(fs.is_dir(&url_path("https://example.com/gone.png")).is_err());
+    }
+}
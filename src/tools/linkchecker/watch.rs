@@ -0,0 +1,130 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Finding the files which changed in the docs, for `--watch`. The docs are
+//! polled, which needs nothing from the platform and is quick enough for a
+//! tree the size of the standard library's docs every second or so.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// When each file below a directory was last modified.
+pub type Snapshot = BTreeMap<PathBuf, SystemTime>;
+
+/// Takes a snapshot of the files below `dir`, the way `Checker::walk` sees
+/// them: symbolic links are followed unless `follow_symlinks` is unset, in
+/// which case they're skipped altogether, and each directory is only looked
+/// into once, so a cycle can't keep this going. Files which can't be read are
+/// left out.
+pub fn snapshot(dir: &Path, follow_symlinks: bool) -> Snapshot {
+    let mut files = BTreeMap::new();
+    add_dir(dir, follow_symlinks, &mut HashSet::new(), &mut files);
+    files
+}
+
+fn add_dir(dir: &Path, follow_symlinks: bool, visited: &mut HashSet<PathBuf>,
+           files: &mut Snapshot) {
+    match fs::canonicalize(dir) {
+        Ok(real_dir) => {
+            if !visited.insert(real_dir) {
+                return;
+            }
+        }
+        Err(_) => return,
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        // Unlike `fs::metadata`, this doesn't follow symbolic links.
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() && !follow_symlinks => continue,
+            Ok(_) => {}
+            Err(_) => continue,
+        }
+        // Dangling symbolic links are skipped, like the walk does.
+        match fs::metadata(&path) {
+            Ok(ref metadata) if metadata.is_dir() => {
+                add_dir(&path, follow_symlinks, visited, files)
+            }
+            Ok(metadata) => {
+                if let Ok(modified) = metadata.modified() {
+                    files.insert(path, modified);
+                }
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Returns the files which were added, modified or removed from `old` to
+/// `new`, sorted.
+pub fn changes(old: &Snapshot, new: &Snapshot) -> Vec<PathBuf> {
+    let mut changed = new.iter()
+                         .filter(|&(path, modified)| old.get(path) != Some(modified))
+                         .map(|(path, _)| path.clone())
+                         .collect::<Vec<_>>();
+    changed.extend(old.keys().filter(|path| !new.contains_key(*path)).cloned());
+    changed.sort();
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{Snapshot, changes};
+
+    fn snapshot(files: &[(&str, u64)]) -> Snapshot {
+        files.iter()
+             .map(|&(path, secs)| (PathBuf::from(path), UNIX_EPOCH + Duration::from_secs(secs)))
+             .collect()
+    }
+
+    #[test]
+    fn changed_files() {
+        let old = snapshot(&[("a.html", 1), ("b.html", 1), ("c.html", 1)]);
+        let new = snapshot(&[("a.html", 1), ("c.html", 2), ("d.html", 2)]);
+        assert_eq!(changes(&old, &new), [PathBuf::from("b.html"),
+                                         PathBuf::from("c.html"),
+                                         PathBuf::from("d.html")]);
+        assert!(changes(&new, &new).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = env::temp_dir().join("linkchecker-test-watch-symlinks");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("real")).unwrap();
+        File::create(dir.join("real/a.html")).unwrap();
+        symlink(dir.join("real"), dir.join("alias")).unwrap();
+        symlink(&dir, dir.join("real/cycle")).unwrap();
+        symlink(dir.join("gone.html"), dir.join("dangling.html")).unwrap();
+
+        // `real` is only looked into once, through whichever link to it comes
+        // first, and the dangling link is left out.
+        let followed = super::snapshot(&dir, true);
+        assert_eq!(followed.len(), 1);
+        assert!(followed.keys().all(|path| path.ends_with("a.html")));
+        let skipped = super::snapshot(&dir, false);
+        assert_eq!(skipped.keys().collect::<Vec<_>>(), [&dir.join("real/a.html")]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}